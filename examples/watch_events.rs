@@ -0,0 +1,11 @@
+use muzzman_daemon::prelude::*;
+
+fn main() {
+    let mut session = DaemonSession::new().expect("Cannot connect to daemon");
+    let events = session.subscribe();
+    let _session = session.create_session();
+
+    for event in events {
+        println!("Event: {event:?}");
+    }
+}
@@ -0,0 +1,2079 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use muzzman_lib::prelude::*;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::{
+    builder::DaemonSessionBuilder,
+    error::RequestError,
+    events::EventFilter,
+    packets::{
+        Actions, ClientPackets, DaemonEvent, EventReplay, LocationNode, ServerPackets, TreeField,
+    },
+    query::{ElementQuery, QueryCursor, QueryPage},
+    retried,
+    transport::DaemonAddress,
+    wait_for_async, DaemonSession, TDaemonSession,
+};
+
+/// Async client of the daemon
+/// Uses the same session as `DaemonSession`, with its heartbeat, reconnect and retransmissions
+/// The transport is read by a tokio task, that wakes the requests when their response arrives
+pub struct AsyncDaemonSession {
+    session: Arc<RwLock<DaemonSession>>,
+    /// features that both the daemon and the client have
+    pub features: Vec<String>,
+    /// granted by the daemon, `None` if the daemon doesn't know leases
+    pub lease: Option<Duration>,
+}
+
+impl AsyncDaemonSession {
+    pub async fn new() -> Result<Self, SessionError> {
+        Self::connect(DaemonAddress::default()).await
+    }
+
+    pub async fn connect(address: DaemonAddress) -> Result<Self, SessionError> {
+        Self::open(DaemonSessionBuilder::default().address(address)).await
+    }
+
+    /// `name` is how the daemon will know this client
+    pub async fn connect_as(
+        address: DaemonAddress,
+        name: impl Into<String>,
+    ) -> Result<Self, SessionError> {
+        Self::open(DaemonSessionBuilder::default().address(address).name(name)).await
+    }
+
+    /// Connects with the configuration of the builder
+    pub async fn open(builder: DaemonSessionBuilder) -> Result<Self, SessionError> {
+        let session = tokio::task::spawn_blocking(move || builder.build())
+            .await
+            .map_err(|err| SessionError::Custom(format!("Cannot connect: {err}")))??;
+
+        Ok(Self {
+            features: session.features.clone(),
+            lease: session.lease,
+            session: session
+                .create_async_session()
+                .map_err(|err| SessionError::Custom(format!("Cannot read the daemon: {err}")))?,
+        })
+    }
+
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+
+    /// The blocking session, shared with this one
+    pub fn daemon_session(&self) -> Box<dyn TDaemonSession> {
+        self.session.cl()
+    }
+
+    /// Every daemon event that arrives after this is sent to the receiver
+    pub fn subscribe_daemon(&self) -> UnboundedReceiver<DaemonEvent> {
+        self.session.write().unwrap().subscribe_daemon_async()
+    }
+
+    /// Every session event that arrives after this is sent to the receiver
+    pub fn subscribe(&self) -> UnboundedReceiver<SessionEvent> {
+        self.session.write().unwrap().subscribe_async()
+    }
+
+    /// Only the events that match the filter will be sent by the daemon
+    /// The filter is sent again after reconnecting
+    pub async fn set_event_filter(&self, filter: EventFilter) -> Result<(), SessionError> {
+        if !self.has_feature("event-filter") {
+            return Err(SessionError::Custom(
+                "Daemon cannot filter events".to_string(),
+            ));
+        }
+
+        let id = self.generate();
+        self.session.write().unwrap().event_filter = Some(filter.clone());
+        if let ClientPackets::SetEventFilter(_, response) = self
+            .request(ServerPackets::SetEventFilter { id, filter })
+            .await?
+        {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    /// The events after `seq` that match the filter
+    pub async fn events_since(&self, seq: u64) -> Result<EventReplay, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::EventsSince { id, seq };
+
+        if let ClientPackets::EventsSince(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    /// Runs every request in one round trip, the responses are in the same order
+    pub async fn batch(
+        &self,
+        requests: Vec<ServerPackets>,
+    ) -> Result<Vec<ClientPackets>, SessionError> {
+        if !self.has_feature("batch") {
+            return Err(SessionError::Custom(
+                "Daemon cannot batch requests".to_string(),
+            ));
+        }
+
+        let id = self.generate();
+        let packet = ServerPackets::Batch { id, requests };
+
+        if let ClientPackets::Batch(_, responses) = self.request(packet).await? {
+            Ok(responses)
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    /// The locations and elements under `root` with the selected fields, in one response
+    /// `depth` is how many levels of sub locations are included, 0 is only the root
+    pub async fn get_tree_snapshot(
+        &self,
+        root: LocationId,
+        depth: u32,
+        fields: Vec<TreeField>,
+    ) -> Result<LocationNode, SessionError> {
+        if !self.has_feature("tree-snapshot") {
+            return Err(SessionError::Custom(
+                "Daemon cannot make tree snapshots".to_string(),
+            ));
+        }
+
+        let id = self.generate();
+        let packet = ServerPackets::GetTreeSnapshot {
+            id,
+            root,
+            depth,
+            fields,
+        };
+
+        if let ClientPackets::GetTreeSnapshot(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    /// The elements that match the query, filtered and sorted by the daemon
+    /// `cursor` is `None` for the first page, then `QueryPage::next`
+    pub async fn query_elements(
+        &self,
+        query: ElementQuery,
+        cursor: Option<QueryCursor>,
+        limit: u32,
+    ) -> Result<QueryPage, SessionError> {
+        if !self.has_feature("query") {
+            return Err(SessionError::Custom(
+                "Daemon cannot query elements".to_string(),
+            ));
+        }
+
+        let id = self.generate();
+        let packet = ServerPackets::QueryElements {
+            id,
+            query,
+            cursor,
+            limit,
+        };
+
+        if let ClientPackets::QueryElements(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub fn generate(&self) -> u128 {
+        self.session.generate()
+    }
+
+    /// Sends the packet and waits for the response with the same id
+    /// The timeout and retries are the ones configured for its kind
+    pub async fn request(&self, packet: ServerPackets) -> Result<ClientPackets, RequestError> {
+        let timeout = self.session.read().unwrap().timeout_for(packet.kind());
+        self.request_timeout(packet, Some(timeout)).await
+    }
+
+    /// `None` will wait until the response arrives
+    /// The request is cancelled if the future is dropped before the response arrived
+    pub async fn request_timeout(
+        &self,
+        packet: ServerPackets,
+        timeout: Option<Duration>,
+    ) -> Result<ClientPackets, RequestError> {
+        let id = packet.id();
+        let kind = packet.kind();
+        let mut guard = CancelGuard {
+            session: &self.session,
+            id,
+            done: false,
+        };
+
+        let mut attempt = 0;
+        let response = loop {
+            self.session.send(packet.clone());
+            let response = wait_for_async(&self.session, id, kind, timeout, Some(&packet)).await;
+            if let Some(response) = retried(&self.session, &mut attempt, response) {
+                break response;
+            }
+        };
+        guard.done = true;
+        response
+    }
+
+    pub async fn load_module(&self, path: PathBuf) -> Result<ModuleId, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LoadModule { id, path };
+
+        if let ClientPackets::LoadModule(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn remove_module(&self, module_id: ModuleId) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::RemoveModule { id, module_id };
+
+        if let ClientPackets::RemoveModule(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn load_module_info(&self, info: ModuleInfo) -> Result<ModuleId, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LoadModuleInfo {
+            id,
+            module_info: info,
+        };
+
+        if let ClientPackets::LoadModuleInfo(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn find_module(&self, info: ModuleInfo) -> Result<ModuleId, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::FindModule {
+            id,
+            module_info: info,
+        };
+
+        if let ClientPackets::FindModule(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn get_actions(
+        &self,
+        range: std::ops::Range<usize>,
+    ) -> Result<Actions, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::GetActions { id, range };
+
+        if let ClientPackets::GetActions(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn get_actions_len(&self) -> Result<usize, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::GetActionsLen { id };
+
+        if let ClientPackets::GetActionsLen(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn run_action(
+        &self,
+        module_id: &ModuleId,
+        name: String,
+        data: Vec<Type>,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::RunAction {
+            id,
+            module_id: *module_id,
+            name,
+            data,
+        };
+
+        if let ClientPackets::RunAction(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn get_modules_len(&self) -> Result<usize, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::GetModulesLen { id };
+
+        if let ClientPackets::GetModulesLen(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn get_modules(
+        &self,
+        range: std::ops::Range<usize>,
+    ) -> Result<Vec<ModuleId>, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::GetModules { id, range };
+
+        if let ClientPackets::GetModules(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn module_get_name(&self, module_id: &ModuleId) -> Result<String, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ModuleGetName {
+            id,
+            module_id: *module_id,
+        };
+
+        if let ClientPackets::ModuleGetName(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn module_set_name(
+        &self,
+        module_id: &ModuleId,
+        name: String,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ModuleSetName {
+            id,
+            module_id: *module_id,
+            to: name,
+        };
+
+        if let ClientPackets::ModuleSetName(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn module_get_default_name(
+        &self,
+        module_id: &ModuleId,
+    ) -> Result<String, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ModuleGetDefaultName {
+            id,
+            module_id: *module_id,
+        };
+
+        if let ClientPackets::ModuleGetDefaultName(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn module_get_uid(&self, module_id: &ModuleId) -> Result<UID, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ModuleGetUid {
+            id,
+            module_id: *module_id,
+        };
+
+        if let ClientPackets::ModuleGetUid(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn module_get_version(&self, module_id: &ModuleId) -> Result<String, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ModuleGetVersion {
+            id,
+            module_id: *module_id,
+        };
+
+        if let ClientPackets::ModuleGetVersion(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn module_supported_versions(
+        &self,
+        module_id: &ModuleId,
+    ) -> Result<std::ops::Range<u64>, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ModuleSupportedVersions {
+            id,
+            module_id: *module_id,
+        };
+
+        if let ClientPackets::ModuleSupportedVersions(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn module_get_desc(&self, module_id: &ModuleId) -> Result<String, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ModuleGetDesc {
+            id,
+            module_id: *module_id,
+        };
+
+        if let ClientPackets::ModuleGetDesc(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn module_set_desc(
+        &self,
+        module_id: &ModuleId,
+        desc: String,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ModuleSetDesc {
+            id,
+            module_id: *module_id,
+            to: desc,
+        };
+
+        if let ClientPackets::ModuleSetDesc(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn module_get_default_desc(
+        &self,
+        module_id: &ModuleId,
+    ) -> Result<String, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ModuleGetDefaultDesc {
+            id,
+            module_id: *module_id,
+        };
+
+        if let ClientPackets::ModuleGetDefaultDesc(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn module_get_proxy(&self, module_id: &ModuleId) -> Result<usize, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ModuleGetProxy {
+            id,
+            module_id: *module_id,
+        };
+
+        if let ClientPackets::ModuleGetProxy(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn module_set_proxy(
+        &self,
+        module_id: &ModuleId,
+        proxy: usize,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ModuleSetProxy {
+            id,
+            module_id: *module_id,
+            to: proxy,
+        };
+
+        if let ClientPackets::ModuleSetProxy(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn module_get_settings(&self, module_id: &ModuleId) -> Result<Values, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ModuleGetSettings {
+            id,
+            module_id: *module_id,
+        };
+
+        if let ClientPackets::ModuleGetSettings(_, response) = self.request(packet).await? {
+            *response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn module_set_settings(
+        &self,
+        module_id: &ModuleId,
+        data: Values,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ModuleSetSettings {
+            id,
+            module_id: *module_id,
+            to: data,
+        };
+
+        if let ClientPackets::ModuleSetSettings(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn module_get_element_settings(
+        &self,
+        module_id: &ModuleId,
+    ) -> Result<Values, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ModuleGetElementSettings {
+            id,
+            module_id: *module_id,
+        };
+
+        if let ClientPackets::ModuleGetElementSettings(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn module_set_element_settings(
+        &self,
+        module_id: &ModuleId,
+        data: Values,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ModuleSetElementSettings {
+            id,
+            module_id: *module_id,
+            to: data,
+        };
+
+        if let ClientPackets::ModuleSetElementSettings(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn module_get_location_settings(
+        &self,
+        module_id: &ModuleId,
+    ) -> Result<Values, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ModuleGetLocationSettings {
+            id,
+            module_id: *module_id,
+        };
+
+        if let ClientPackets::ModuleGetLocationSettings(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn module_set_location_settings(
+        &self,
+        module_id: &ModuleId,
+        data: Values,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ModuleSetLocationSettings {
+            id,
+            module_id: *module_id,
+            to: data,
+        };
+
+        if let ClientPackets::ModuleSetLocationSettings(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn module_init_location(
+        &self,
+        module_id: &ModuleId,
+        location_id: &LocationId,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ModuleInitLocation {
+            id,
+            module_id: *module_id,
+            location_id: location_id.clone(),
+        };
+
+        if let ClientPackets::ModuleInitLocation(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn module_init_element(
+        &self,
+        module_id: &ModuleId,
+        element_id: &ElementId,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ModuleInitElement {
+            id,
+            module_id: *module_id,
+            element_id: element_id.clone(),
+        };
+
+        if let ClientPackets::ModuleInitElement(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn module_accept_url(
+        &self,
+        module_id: &ModuleId,
+        url: String,
+    ) -> Result<bool, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ModuleAcceptUrl {
+            id,
+            module_id: *module_id,
+            url,
+        };
+
+        if let ClientPackets::ModuleAcceptUrl(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn module_accept_extension(
+        &self,
+        module_id: &ModuleId,
+        filename: &str,
+    ) -> Result<bool, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ModuleAcceptExtension {
+            id,
+            module_id: *module_id,
+            filename: filename.to_owned(),
+        };
+
+        if let ClientPackets::ModuleAcceptExtension(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn module_accepted_protocols(
+        &self,
+        module_id: &ModuleId,
+    ) -> Result<Vec<String>, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ModuleAcceptedProtocols {
+            id,
+            module_id: *module_id,
+        };
+
+        if let ClientPackets::ModuleAcceptedProtocols(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn module_accepted_extensions(
+        &self,
+        module_id: &ModuleId,
+    ) -> Result<Vec<String>, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ModuleAcceptedExtensions {
+            id,
+            module_id: *module_id,
+        };
+
+        if let ClientPackets::ModuleAcceptedExtensions(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn create_element(
+        &self,
+        name: &str,
+        location_id: &LocationId,
+    ) -> Result<ElementId, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::CreateElement {
+            id,
+            location_id: location_id.clone(),
+            name: name.to_string(),
+        };
+
+        if let ClientPackets::CreateElement(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn load_element_info(
+        &self,
+        element_info: ElementInfo,
+    ) -> Result<ElementId, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LoadElementInfo { id, element_info };
+
+        if let ClientPackets::LoadElementInfo(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn move_element(
+        &self,
+        element: &ElementId,
+        location_id: &LocationId,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::MoveElement {
+            id,
+            element_id: element.clone(),
+            location_id: location_id.clone(),
+        };
+
+        if let ClientPackets::MoveElement(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn destroy_element(&self, element_id: ElementId) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::DestroyElement { id, element_id };
+
+        if let ClientPackets::DestroyElement(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_get_name(&self, element_id: &ElementId) -> Result<String, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementGetName {
+            id,
+            element_id: element_id.clone(),
+        };
+
+        if let ClientPackets::ElementGetName(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_set_name(
+        &self,
+        element_id: &ElementId,
+        name: &str,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementSetName {
+            id,
+            element_id: element_id.clone(),
+            to: name.to_string(),
+        };
+
+        if let ClientPackets::ElementSetName(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_get_desc(&self, element_id: &ElementId) -> Result<String, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementGetDesc {
+            id,
+            element_id: element_id.clone(),
+        };
+
+        if let ClientPackets::ElementGetDesc(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_set_desc(
+        &self,
+        element_id: &ElementId,
+        desc: &str,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementSetDesc {
+            id,
+            element_id: element_id.clone(),
+            to: desc.to_string(),
+        };
+
+        if let ClientPackets::ElementSetDesc(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_get_meta(&self, element_id: &ElementId) -> Result<String, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementGetMeta {
+            id,
+            element_id: element_id.clone(),
+        };
+
+        if let ClientPackets::ElementGetMeta(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_set_meta(
+        &self,
+        element_id: &ElementId,
+        meta: &str,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementSetMeta {
+            id,
+            element_id: element_id.clone(),
+            to: meta.to_string(),
+        };
+
+        if let ClientPackets::ElementSetMeta(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_get_url(
+        &self,
+        element_id: &ElementId,
+    ) -> Result<Option<String>, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementGetUrl {
+            id,
+            element_id: element_id.clone(),
+        };
+
+        if let ClientPackets::ElementGetUrl(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_set_url(
+        &self,
+        element_id: &ElementId,
+        url: Option<String>,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementSetUrl {
+            id,
+            element_id: element_id.clone(),
+            to: url,
+        };
+
+        if let ClientPackets::ElementSetUrl(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_get_element_data(
+        &self,
+        element_id: &ElementId,
+    ) -> Result<Values, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementGetElementData {
+            id,
+            element_id: element_id.clone(),
+        };
+
+        if let ClientPackets::ElementGetElementData(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_set_element_data(
+        &self,
+        element_id: &ElementId,
+        data: Values,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementSetElementData {
+            id,
+            element_id: element_id.clone(),
+            to: data,
+        };
+
+        if let ClientPackets::ElementSetElementData(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_get_module_data(
+        &self,
+        element_id: &ElementId,
+    ) -> Result<Values, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementGetModuleData {
+            id,
+            element_id: element_id.clone(),
+        };
+
+        if let ClientPackets::ElementGetModuleData(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_set_module_data(
+        &self,
+        element_id: &ElementId,
+        data: Values,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementSetModuleData {
+            id,
+            element_id: element_id.clone(),
+            to: data,
+        };
+
+        if let ClientPackets::ElementSetModuleData(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_get_module(
+        &self,
+        element_id: &ElementId,
+    ) -> Result<Option<ModuleId>, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementGetModule {
+            id,
+            element_id: element_id.clone(),
+        };
+
+        if let ClientPackets::ElementGetModule(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_set_module(
+        &self,
+        element_id: &ElementId,
+        module: Option<ModuleId>,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementSetModule {
+            id,
+            element_id: element_id.clone(),
+            module,
+        };
+
+        if let ClientPackets::ElementSetModule(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_get_statuses(
+        &self,
+        element_id: &ElementId,
+    ) -> Result<Vec<String>, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementGetStatuses {
+            id,
+            element_id: element_id.clone(),
+        };
+
+        if let ClientPackets::ElementGetStatuses(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_set_statuses(
+        &self,
+        element_id: &ElementId,
+        statuses: Vec<String>,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementSetStatuses {
+            id,
+            element_id: element_id.clone(),
+            to: statuses,
+        };
+
+        if let ClientPackets::ElementSetStatuses(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_get_status(&self, element_id: &ElementId) -> Result<usize, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementGetStatus {
+            id,
+            element_id: element_id.clone(),
+        };
+
+        if let ClientPackets::ElementGetStatus(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_set_status(
+        &self,
+        element_id: &ElementId,
+        status: usize,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementSetStatus {
+            id,
+            element_id: element_id.clone(),
+            to: status,
+        };
+
+        if let ClientPackets::ElementSetStatus(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_get_data(&self, element_id: &ElementId) -> Result<Data, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementGetData {
+            id,
+            element_id: element_id.clone(),
+        };
+
+        if let ClientPackets::ElementGetData(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_set_data(
+        &self,
+        element_id: &ElementId,
+        data: Data,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementSetData {
+            id,
+            element_id: element_id.clone(),
+            to: data,
+        };
+
+        if let ClientPackets::ElementSetData(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_get_progress(&self, element_id: &ElementId) -> Result<f32, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementGetProgress {
+            id,
+            element_id: element_id.clone(),
+        };
+
+        if let ClientPackets::ElementGetProgress(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_set_progress(
+        &self,
+        element_id: &ElementId,
+        progress: f32,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementSetProgress {
+            id,
+            element_id: element_id.clone(),
+            to: progress,
+        };
+
+        if let ClientPackets::ElementSetProgress(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_get_should_save(
+        &self,
+        element_id: &ElementId,
+    ) -> Result<bool, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementGetShouldSave {
+            id,
+            element_id: element_id.clone(),
+        };
+
+        if let ClientPackets::ElementGetShouldSave(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_set_should_save(
+        &self,
+        element_id: &ElementId,
+        should_save: bool,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementSetShouldSave {
+            id,
+            element_id: element_id.clone(),
+            to: should_save,
+        };
+
+        if let ClientPackets::ElementSetShouldSave(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_get_enabled(&self, element_id: &ElementId) -> Result<bool, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementGetEnabled {
+            id,
+            element_id: element_id.clone(),
+        };
+
+        if let ClientPackets::ElementGetEnabled(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_set_enabled(
+        &self,
+        element_id: &ElementId,
+        enabled: bool,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementSetEnabled {
+            id,
+            element_id: element_id.clone(),
+            to: enabled,
+        };
+
+        if let ClientPackets::ElementSetEnabled(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_is_error(&self, element_id: &ElementId) -> Result<bool, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementIsError {
+            id,
+            element_id: element_id.clone(),
+        };
+
+        if let ClientPackets::ElementIsError(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_resolv_module(
+        &self,
+        element_id: &ElementId,
+    ) -> Result<bool, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementResolvModule {
+            id,
+            element_id: element_id.clone(),
+        };
+
+        if let ClientPackets::ElementResolvModule(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_wait(&self, element_id: &ElementId) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementWait {
+            id,
+            element_id: element_id.clone(),
+        };
+
+        if let ClientPackets::ElementWait(_, response) = self.request_timeout(packet, None).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_get_element_info(
+        &self,
+        element_id: &ElementId,
+    ) -> Result<ElementInfo, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementGetInfo {
+            id,
+            element_id: element_id.clone(),
+        };
+
+        if let ClientPackets::ElementGetInfo(_, response) = self.request(packet).await? {
+            *response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_notify(
+        &self,
+        element_id: &ElementId,
+        event: Event,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementNotify {
+            id,
+            element_id: element_id.clone(),
+            event,
+        };
+
+        if let ClientPackets::ElementNotify(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_emit(
+        &self,
+        element_id: &ElementId,
+        event: Event,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementEmit {
+            id,
+            element_id: element_id.clone(),
+            event,
+        };
+
+        if let ClientPackets::ElementEmit(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_subscribe(
+        &self,
+        element_id: &ElementId,
+        _ref: ID,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementSubscribe {
+            id,
+            element_id: element_id.clone(),
+            to: _ref,
+        };
+
+        if let ClientPackets::ElementSubscribe(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn element_unsubscribe(
+        &self,
+        element_id: &ElementId,
+        _ref: ID,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::ElementUnSubscribe {
+            id,
+            element_id: element_id.clone(),
+            to: _ref,
+        };
+
+        if let ClientPackets::ElementUnSubscribe(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn create_location(
+        &self,
+        name: &str,
+        location_id: &LocationId,
+    ) -> Result<LocationId, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::CreateLocation {
+            id,
+            name: name.to_owned(),
+            location_id: location_id.clone(),
+        };
+
+        if let ClientPackets::CreateLocation(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn load_location_info(
+        &self,
+        location_info: LocationInfo,
+    ) -> Result<LocationId, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LoadLocationInfo { id, location_info };
+
+        if let ClientPackets::LoadLocationInfo(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn get_locations_len(&self, location_id: &LocationId) -> Result<usize, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::GetLocationsLen {
+            id,
+            location_id: location_id.clone(),
+        };
+
+        if let ClientPackets::GetLocationsLen(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn get_locations(
+        &self,
+        location_id: &LocationId,
+        range: std::ops::Range<usize>,
+    ) -> Result<Vec<LocationId>, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::GetLocations {
+            id,
+            location_id: location_id.clone(),
+            range,
+        };
+
+        if let ClientPackets::GetLocations(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn destroy_location(&self, location_id: LocationId) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::DestroyLocation { id, location_id };
+
+        if let ClientPackets::DestroyLocation(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn get_default_location(&self) -> Result<LocationId, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::GetDefaultLocation { id };
+
+        if let ClientPackets::GetDefaultLocation(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn move_location(
+        &self,
+        location_id: &LocationId,
+        to: &LocationId,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::MoveLocation {
+            id,
+            location_id: location_id.clone(),
+            to: to.clone(),
+        };
+
+        if let ClientPackets::MoveLocation(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_get_name(
+        &self,
+        location_id: &LocationId,
+    ) -> Result<String, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationGetName {
+            id,
+            from: location_id.clone(),
+        };
+
+        if let ClientPackets::LocationGetName(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_set_name(
+        &self,
+        location_id: &LocationId,
+        name: &str,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationSetName {
+            id,
+            from: location_id.clone(),
+            to: name.to_string(),
+        };
+
+        if let ClientPackets::LocationSetName(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_get_desc(
+        &self,
+        location_id: &LocationId,
+    ) -> Result<String, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationGetDesc {
+            id,
+            from: location_id.clone(),
+        };
+
+        if let ClientPackets::LocationGetDesc(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_set_desc(
+        &self,
+        location_id: &LocationId,
+        desc: &str,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationSetDesc {
+            id,
+            from: location_id.clone(),
+            to: desc.to_string(),
+        };
+
+        if let ClientPackets::LocationSetDesc(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_get_path(
+        &self,
+        location_id: &LocationId,
+    ) -> Result<PathBuf, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationGetPath {
+            id,
+            location_id: location_id.clone(),
+        };
+
+        if let ClientPackets::LocationGetPath(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_set_path(
+        &self,
+        location_id: &LocationId,
+        path: PathBuf,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationSetPath {
+            id,
+            location_id: location_id.clone(),
+            to: path,
+        };
+
+        if let ClientPackets::LocationSetPath(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_get_should_save(
+        &self,
+        location_id: &LocationId,
+    ) -> Result<bool, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationGetShouldSave {
+            id,
+            location_id: location_id.clone(),
+        };
+
+        if let ClientPackets::LocationGetShouldSave(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_set_should_save(
+        &self,
+        location_id: &LocationId,
+        should_save: bool,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationSetShouldSave {
+            id,
+            location_id: location_id.clone(),
+            to: should_save,
+        };
+
+        if let ClientPackets::LocationSetShouldSave(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_get_elements_len(
+        &self,
+        location_id: &LocationId,
+    ) -> Result<usize, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationGetElementsLen {
+            id,
+            location_id: location_id.clone(),
+        };
+
+        if let ClientPackets::LocationGetElementsLen(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_get_elements(
+        &self,
+        location_id: &LocationId,
+        range: std::ops::Range<usize>,
+    ) -> Result<Vec<ElementId>, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationGetElements {
+            id,
+            location_id: location_id.clone(),
+            range,
+        };
+
+        if let ClientPackets::LocationGetElements(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_get_module(
+        &self,
+        location_id: &LocationId,
+    ) -> Result<Option<ModuleId>, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationGetModule {
+            id,
+            location_id: location_id.clone(),
+        };
+
+        if let ClientPackets::LocationGetModule(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_set_module(
+        &self,
+        location_id: &LocationId,
+        module_id: Option<ModuleId>,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationSetModule {
+            id,
+            location_id: location_id.clone(),
+            module_id,
+        };
+
+        if let ClientPackets::LocationSetModule(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_get_settings(
+        &self,
+        location_id: &LocationId,
+    ) -> Result<Values, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationGetSettings {
+            id,
+            location_id: location_id.clone(),
+        };
+
+        if let ClientPackets::LocationGetSettings(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_set_settings(
+        &self,
+        location_id: &LocationId,
+        data: Values,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationSetSettings {
+            id,
+            location_id: location_id.clone(),
+            to: data,
+        };
+
+        if let ClientPackets::LocationSetSettings(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_get_module_settings(
+        &self,
+        location_id: &LocationId,
+    ) -> Result<Values, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationGetModuleSettings {
+            id,
+            location_id: location_id.clone(),
+        };
+
+        if let ClientPackets::LocationGetModuleSettings(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_set_module_settings(
+        &self,
+        location_id: &LocationId,
+        data: Values,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationSetModuleSettings {
+            id,
+            location_id: location_id.clone(),
+            to: data,
+        };
+
+        if let ClientPackets::LocationSetModuleSettings(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_get_statuses(
+        &self,
+        location_id: &LocationId,
+    ) -> Result<Vec<String>, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationGetStatuses {
+            id,
+            location_id: location_id.clone(),
+        };
+
+        if let ClientPackets::LocationGetStatuses(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_set_statuses(
+        &self,
+        location_id: &LocationId,
+        statuses: Vec<String>,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationSetStatuses {
+            id,
+            location_id: location_id.clone(),
+            statuses,
+        };
+
+        if let ClientPackets::LocationSetStatuses(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_get_status(
+        &self,
+        location_id: &LocationId,
+    ) -> Result<usize, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationGetStatus {
+            id,
+            location_id: location_id.clone(),
+        };
+
+        if let ClientPackets::LocationGetStatus(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_set_status(
+        &self,
+        location_id: &LocationId,
+        status: usize,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationSetStatus {
+            id,
+            location_id: location_id.clone(),
+            to: status,
+        };
+
+        if let ClientPackets::LocationSetStatus(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_get_progress(
+        &self,
+        location_id: &LocationId,
+    ) -> Result<f32, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationGetProgress {
+            id,
+            location_id: location_id.clone(),
+        };
+
+        if let ClientPackets::LocationGetProgress(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_set_progress(
+        &self,
+        location_id: &LocationId,
+        progress: f32,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationSetProgress {
+            id,
+            location_id: location_id.clone(),
+            to: progress,
+        };
+
+        if let ClientPackets::LocationSetProgress(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_is_enabled(
+        &self,
+        location_id: &LocationId,
+    ) -> Result<bool, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationIsEnabled {
+            id,
+            location_id: location_id.clone(),
+        };
+
+        if let ClientPackets::LocationIsEnabled(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_set_enabled(
+        &self,
+        location_id: &LocationId,
+        enabled: bool,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationSetEnabled {
+            id,
+            location_id: location_id.clone(),
+            to: enabled,
+        };
+
+        if let ClientPackets::LocationSetEnabled(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_is_error(&self, location_id: &LocationId) -> Result<bool, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationIsError {
+            id,
+            location_id: location_id.clone(),
+        };
+
+        if let ClientPackets::LocationIsError(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_get_location_info(
+        &self,
+        location_id: &LocationId,
+    ) -> Result<LocationInfo, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationGetInfo {
+            id,
+            from: location_id.clone(),
+        };
+
+        if let ClientPackets::LocationGetInfo(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_notify(
+        &self,
+        location_id: &LocationId,
+        event: Event,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationNotify {
+            id,
+            location_id: location_id.clone(),
+            event,
+        };
+
+        if let ClientPackets::LocationNotify(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_emit(
+        &self,
+        location_id: &LocationId,
+        event: Event,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationEmit {
+            id,
+            location_id: location_id.clone(),
+            event,
+        };
+
+        if let ClientPackets::LocationEmit(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_subscribe(
+        &self,
+        location_id: &LocationId,
+        _ref: ID,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationSubscribe {
+            id,
+            location_id: location_id.clone(),
+            to: _ref,
+        };
+
+        if let ClientPackets::LocationSubscribe(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn location_unsubscribe(
+        &self,
+        location_id: &LocationId,
+        _ref: ID,
+    ) -> Result<(), SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::LocationUnSubscribe {
+            id,
+            location_id: location_id.clone(),
+            to: _ref,
+        };
+
+        if let ClientPackets::LocationUnSubscribe(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn get_version(&self) -> Result<u64, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::GetVersion { id };
+
+        if let ClientPackets::GetVersion(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    pub async fn get_version_text(&self) -> Result<String, SessionError> {
+        let id = self.generate();
+        let packet = ServerPackets::GetVersionText { id };
+
+        if let ClientPackets::GetVersionText(_, response) = self.request(packet).await? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+}
+
+/// Cancels the request on the daemon when is dropped before `done`
+struct CancelGuard<'a> {
+    session: &'a dyn TDaemonSession,
+    id: u128,
+    done: bool,
+}
+
+impl Drop for CancelGuard<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            self.session.cancel(self.id);
+        }
+    }
+}
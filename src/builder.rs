@@ -0,0 +1,70 @@
+use std::{collections::HashMap, time::Duration};
+
+use muzzman_lib::prelude::SessionError;
+
+use crate::{transport::DaemonAddress, DaemonSession, RETRANSMIT, TIMEOUT};
+
+/// Configures a `DaemonSession` before connecting
+pub struct DaemonSessionBuilder {
+    pub(crate) address: DaemonAddress,
+    pub(crate) name: Option<String>,
+    pub(crate) timeout: Duration,
+    pub(crate) timeouts: HashMap<&'static str, Duration>,
+    pub(crate) retries: u32,
+    pub(crate) retransmit: Duration,
+}
+
+impl Default for DaemonSessionBuilder {
+    fn default() -> Self {
+        Self {
+            address: DaemonAddress::default(),
+            name: None,
+            timeout: TIMEOUT,
+            timeouts: HashMap::new(),
+            retries: 0,
+            retransmit: RETRANSMIT,
+        }
+    }
+}
+
+impl DaemonSessionBuilder {
+    pub fn address(mut self, address: DaemonAddress) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// How the daemon will know this client, default is the name of the executable
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// How long every request waits for the response, and the connect
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Timeout for one kind of request, `kind` is the name of the `ServerPackets` variant
+    pub fn operation_timeout(mut self, kind: &'static str, timeout: Duration) -> Self {
+        self.timeouts.insert(kind, timeout);
+        self
+    }
+
+    /// How many times a request that timed out is sent again
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// How often a udp request without response is sent again while waiting for it
+    /// Only used when the daemon remembers the responses, so the request is not run twice
+    pub fn retransmit(mut self, retransmit: Duration) -> Self {
+        self.retransmit = retransmit;
+        self
+    }
+
+    pub fn build(self) -> Result<DaemonSession, SessionError> {
+        DaemonSession::open(self)
+    }
+}
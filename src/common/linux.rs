@@ -11,3 +11,11 @@ pub fn get_muzzman_dir() -> PathBuf {
 pub fn library_termination() -> OsString {
     "so".into()
 }
+
+/// The unix socket of the daemon, in `$XDG_RUNTIME_DIR` if is set
+pub fn get_socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("muzzman-daemon.sock"),
+        None => get_muzzman_dir().join("daemon.sock"),
+    }
+}
@@ -1,38 +1,363 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Display,
+    io::{Read, Seek, SeekFrom, Write},
     net::SocketAddr,
+    panic::AssertUnwindSafe,
     sync::Arc,
     time::{Duration, SystemTime},
 };
 
+#[cfg(unix)]
+use std::{
+    os::unix::fs::{DirBuilderExt, PermissionsExt},
+    path::PathBuf,
+};
+
+/// Presence timeout of the clients that did not `ServerPackets::Connect`
 const CLIENT_TIMEOUT: Duration = Duration::new(3, 0);
+const MIN_LEASE: Duration = Duration::new(1, 0);
+const MAX_LEASE: Duration = Duration::new(60, 0);
+/// How many malformed packets a client can send in `OFFENSE_WINDOW` before being ignored
+const MAX_OFFENSES: u32 = 5;
+const OFFENSE_WINDOW: Duration = Duration::new(10, 0);
+/// How long a client that sends malformed packets is ignored
+const BAN_TIME: Duration = Duration::new(30, 0);
+/// How often the events that were not acked are checked
+const ACK_POLL: Duration = Duration::from_millis(100);
+/// How many session events are kept for clients that missed them
+const EVENT_HISTORY: usize = 4096;
+/// How long to wait for an event ack before the first retransmission, doubles on every retransmission
+const ACK_TIMEOUT: Duration = Duration::from_millis(500);
+const MAX_ACK_TIMEOUT: Duration = Duration::new(8, 0);
+/// How many responses are remembered for every client, to answer retransmitted requests
+const REPLY_CACHE: usize = 256;
+/// Most bytes of the responses that are remembered for every client
+const REPLY_CACHE_BYTES: usize = 1024 * 1024;
+/// Most elements that one page of `ServerPackets::QueryElements` has
+const MAX_QUERY_PAGE: u32 = 1000;
+/// Most bytes of one data read or write
+const DATA_CHUNK: u32 = 16 * 1024;
+/// Most bytes of a data stream that can be in flight
+const DATA_WINDOW: u32 = 256 * 1024;
+/// How many data streams a client can have open
+const MAX_DATA_STREAMS: usize = 16;
 
 use async_trait::async_trait;
 
 use crate::{
-    packets::{ClientPackets, ServerPackets},
-    DAEMON_PORT, DAEMON_VERSION,
+    events::{event_element, Coalescer, EventFilter},
+    packets::{
+        frame::{
+            Frame, Reassembler, MAX_DATAGRAM, MAX_MESSAGE, REASSEMBLY_TIMEOUT, REQUEST_HEADER,
+        },
+        ClientPackets, DaemonEvent, DataStreamInfo, ElementNode, EventReplay, LocationNode,
+        ServerPackets, TreeField, Welcome,
+    },
+    query::{self, matches_pattern, ElementQuery, ElementSort, QueryCursor, QueryPage, SortKey},
+    shm,
+    tree::{element_fields, location_fields},
+    DAEMON_PORT, DAEMON_VERSION, REPLY_EXPIRY,
 };
 use bytes_kman::TBytes;
 use muzzman_lib::{
-    prelude::{TElement, TLocation, TModuleInfo},
-    session::TSession,
+    prelude::{Data, ElementId, LocationId, SessionEvent, TElement, TLocation, TModuleInfo},
+    session::{SessionError, TSession},
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, UdpSocket},
+    sync::{
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        Mutex,
+    },
+    time::Instant,
 };
-use tokio::{net::UdpSocket, sync::Mutex};
+
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+/// Identifies a client
+/// Stream clients are numbered because they don't have a useful address
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ClientAddr {
+    Udp(SocketAddr),
+    Tcp(SocketAddr),
+    Unix(u64),
+}
+
+impl ClientAddr {
+    /// Stream clients are present until the stream is closed
+    pub fn is_stream(&self) -> bool {
+        !matches!(self, ClientAddr::Udp(_))
+    }
+
+    /// The client is on this host
+    pub fn is_local(&self) -> bool {
+        match self {
+            ClientAddr::Udp(addr) | ClientAddr::Tcp(addr) => addr.ip().is_loopback(),
+            ClientAddr::Unix(_) => true,
+        }
+    }
+}
+
+impl Display for ClientAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientAddr::Udp(addr) => write!(f, "udp://{addr}"),
+            ClientAddr::Tcp(addr) => write!(f, "tcp://{addr}"),
+            ClientAddr::Unix(id) => write!(f, "unix://{id}"),
+        }
+    }
+}
+
+/// What transports the daemon will listen on, `None` disables the transport
+pub struct DaemonConfig {
+    pub udp: Option<SocketAddr>,
+    pub tcp: Option<SocketAddr>,
+    #[cfg(unix)]
+    pub unix: Option<PathBuf>,
+    /// How many times unacked events are retransmitted before the client is dropped
+    pub max_event_failures: u32,
+    /// Progress and status events of an element are sent at most once per interval, zero disables it
+    pub event_interval: Duration,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            udp: Some(SocketAddr::from(([127, 0, 0, 1], DAEMON_PORT))),
+            tcp: None,
+            #[cfg(unix)]
+            unix: Some(crate::common::get_socket_path()),
+            max_event_failures: 5,
+            event_interval: Duration::from_millis(250),
+        }
+    }
+}
+
+/// Who sent the message, the request id from the frame or 0 if is unknown, the message
+type Incoming = (ClientAddr, u128, Vec<u8>);
+
+struct Offenses {
+    count: u32,
+    first: SystemTime,
+    banned: Option<SystemTime>,
+}
+
+impl Offenses {
+    fn is_banned(&self) -> bool {
+        self.banned
+            .map(|since| since.elapsed().unwrap_or_default() < BAN_TIME)
+            .unwrap_or(false)
+    }
+}
+
+/// What a client told about itself in the handshake
+#[derive(Clone, Debug)]
+pub struct ClientInfo {
+    pub name: String,
+    pub version: u64,
+    pub features: Vec<String>,
+}
+
+/// Session events sent to a datagram client that were not acked yet
+struct Unacked {
+    events: VecDeque<ClientPackets>,
+    retry: SystemTime,
+    backoff: Duration,
+    failures: u32,
+}
+
+/// What the daemon knows about a request from a client
+pub enum RequestState {
+    /// Was never seen, should run
+    New,
+    /// Is still running, the response will be sent when done
+    Running,
+    /// Already ran, this is the response
+    Done(ClientPackets),
+}
+
+impl Unacked {
+    fn new() -> Self {
+        Self {
+            events: VecDeque::new(),
+            retry: SystemTime::now() + ACK_TIMEOUT,
+            backoff: ACK_TIMEOUT,
+            failures: 0,
+        }
+    }
+}
 
 pub struct DaemonInner {
-    socket: Arc<UdpSocket>,
-    clients: Vec<(SystemTime, SocketAddr)>,
-    buffer: [u8; 4096],
+    socket: Option<Arc<UdpSocket>>,
+    streams: HashMap<ClientAddr, UnboundedSender<(u128, Vec<u8>)>>,
+    clients: Vec<(SystemTime, ClientAddr)>,
+    handshakes: HashMap<ClientAddr, ClientInfo>,
+    /// connected clients with their lease
+    leases: HashMap<ClientAddr, Duration>,
+    /// clients without a filter receive every event
+    filters: HashMap<ClientAddr, EventFilter>,
+    /// sequence number of the last event sent to the client
+    last_sent: HashMap<ClientAddr, u64>,
+    event_seq: u64,
+    history: VecDeque<(u64, SessionEvent)>,
+    unacked: HashMap<ClientAddr, Unacked>,
+    max_event_failures: u32,
+    /// recent requests of every client that cannot run twice
+    replies: HashMap<ClientAddr, VecDeque<CachedReply>>,
+    /// dispatched requests that were not responded yet, `true` when the client cancelled them
+    running: HashMap<(ClientAddr, u128), bool>,
+    data_streams: Arc<std::sync::Mutex<DataStreams>>,
+    offenses: HashMap<ClientAddr, Offenses>,
+    /// pid of the unix clients that run as the same user, only they can exchange blobs
+    shm_peers: HashMap<ClientAddr, u32>,
+    /// parked `ServerPackets::ElementWait` requests
+    waiters: Vec<Waiter>,
+    message_generator: u64,
 }
 
 unsafe impl Send for DaemonInner {}
 unsafe impl Sync for DaemonInner {}
 
+impl DaemonInner {
+    /// Pid of the client if its big messages go through shared memory
+    /// Only unix clients of the same user that negotiated it
+    fn shm_peer(&self, addr: &ClientAddr) -> Option<u32> {
+        let pid = *self.shm_peers.get(addr)?;
+        let negotiated = self
+            .handshakes
+            .get(addr)
+            .map(|info| info.features.iter().any(|feature| feature == "shm"))
+            .unwrap_or(false);
+        (negotiated && shm::available()).then_some(pid)
+    }
+}
+
+/// Response of a request, to answer its retransmissions without running it again
+struct CachedReply {
+    id: u128,
+    /// `None` while running
+    response: Option<ClientPackets>,
+    size: usize,
+    /// when it was responded
+    time: SystemTime,
+}
+
+/// A `ServerPackets::ElementWait` that will be responded when the element is done
+#[derive(Clone)]
+struct Waiter {
+    addr: ClientAddr,
+    id: u128,
+    element_id: ElementId,
+}
+
+/// Data of an element that a client reads or writes in chunks
+struct DataStream {
+    element_id: ElementId,
+    data: Data,
+    write: bool,
+}
+
+/// Open data streams of every client, used from the session thread
+struct DataStreams {
+    generator: u64,
+    open: HashMap<(ClientAddr, u64), Arc<std::sync::Mutex<DataStream>>>,
+    /// bytes of the dispatched reads and writes of every stream that were not responded yet
+    in_flight: HashMap<(ClientAddr, u64), u32>,
+}
+
+impl DataStreams {
+    /// Counts the bytes of a request as in flight, `false` if they don't fit in the `DATA_WINDOW` of the stream
+    fn reserve(&mut self, addr: ClientAddr, stream: u64, len: u32) -> bool {
+        let in_flight = self.in_flight.entry((addr, stream)).or_default();
+        if *in_flight + len > DATA_WINDOW {
+            return false;
+        }
+        *in_flight += len;
+        true
+    }
+
+    /// The request was responded
+    fn release(&mut self, addr: ClientAddr, stream: u64, len: u32) {
+        if let Some(in_flight) = self.in_flight.get_mut(&(addr, stream)) {
+            *in_flight = in_flight.saturating_sub(len);
+            if *in_flight == 0 {
+                self.in_flight.remove(&(addr, stream));
+            }
+        }
+    }
+}
+
+unsafe impl Send for DataStreams {}
+unsafe impl Sync for DataStreams {}
+
+type SessionCall = Box<dyn FnOnce(&dyn TSession) + Send>;
+
+/// The session is not thread safe, it is created on this thread and every call to it runs here
+/// `TSession` doesn't promise that it can be called from other threads and `LocalSession` holds
+/// a callback that is not `Send`, so the calls run one at a time and a slow one delays the next ones
+#[derive(Clone)]
+struct SessionThread(std::sync::mpsc::Sender<SessionCall>);
+
+impl SessionThread {
+    /// `callback` receives every session event
+    fn spawn(callback: impl Fn(SessionEvent) + Send + 'static) -> Result<Self, std::io::Error> {
+        let (calls, receiver) = std::sync::mpsc::channel::<SessionCall>();
+        let (ready, started) = std::sync::mpsc::channel();
+
+        std::thread::Builder::new()
+            .name("muzzman-session".to_string())
+            .spawn(move || {
+                let mut session = muzzman_lib::LocalSession::default();
+                let callback: Box<dyn Fn(SessionEvent)> = Box::new(callback);
+                session.callback = Some(callback);
+                let session = session.new_session();
+                let default_location = session.get_default_location().unwrap();
+                default_location
+                    .set_path(dirs::home_dir().unwrap().join("Downloads"))
+                    .unwrap();
+                let _ = ready.send(());
+
+                while let Ok(call) = receiver.recv() {
+                    call(&*session);
+                }
+            })?;
+
+        started
+            .recv()
+            .map_err(|_| std::io::Error::other("Cannot create the session"))?;
+        Ok(Self(calls))
+    }
+
+    /// Runs `call` on the session thread, `None` if it panicked
+    async fn run<R: Send + 'static>(
+        &self,
+        call: impl FnOnce(&dyn TSession) -> R + Send + 'static,
+    ) -> Option<R> {
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        let call: SessionCall = Box::new(move |session| {
+            match std::panic::catch_unwind(AssertUnwindSafe(|| call(session))) {
+                Ok(result) => {
+                    let _ = sender.send(result);
+                }
+                Err(_) => log::error!("Session call panicked"),
+            }
+        });
+
+        self.0.send(call).ok()?;
+        receiver.await.ok()
+    }
+}
+
 pub struct Daemon {
-    session: Box<dyn TSession>,
+    session: SessionThread,
     inner: Arc<Mutex<DaemonInner>>,
-    socket: Arc<UdpSocket>,
+    incoming: UnboundedReceiver<Incoming>,
+    /// queued for the session thread, in the order that they arrived
+    requests: UnboundedSender<(ClientAddr, ServerPackets)>,
+    data_streams: Arc<std::sync::Mutex<DataStreams>>,
 }
 
 unsafe impl Sync for Daemon {}
@@ -40,1188 +365,1854 @@ unsafe impl Send for Daemon {}
 
 impl Daemon {
     pub async fn new() -> Result<Self, std::io::Error> {
-        let socket = UdpSocket::bind(format!("127.0.0.1:{DAEMON_PORT}")).await?;
-        let socket = Arc::new(socket);
-        let socket_clone = socket.clone();
+        Self::with_config(DaemonConfig::default()).await
+    }
+
+    pub async fn with_config(config: DaemonConfig) -> Result<Self, std::io::Error> {
+        let (incoming_sender, incoming) = unbounded_channel();
 
-        let mut session = muzzman_lib::LocalSession::default();
+        let socket = match config.udp {
+            Some(addr) => Some(Arc::new(UdpSocket::bind(addr).await?)),
+            None => None,
+        };
 
+        let data_streams = Arc::new(std::sync::Mutex::new(DataStreams {
+            generator: 1,
+            open: HashMap::new(),
+            in_flight: HashMap::new(),
+        }));
         let inner = Arc::new(Mutex::new(DaemonInner {
-            socket,
+            socket: socket.clone(),
+            streams: HashMap::new(),
             clients: Vec::new(),
-            buffer: [0; 4096],
+            handshakes: HashMap::new(),
+            leases: HashMap::new(),
+            filters: HashMap::new(),
+            last_sent: HashMap::new(),
+            event_seq: 0,
+            history: VecDeque::with_capacity(EVENT_HISTORY),
+            unacked: HashMap::new(),
+            max_event_failures: config.max_event_failures,
+            replies: HashMap::new(),
+            running: HashMap::new(),
+            data_streams: data_streams.clone(),
+            offenses: HashMap::new(),
+            shm_peers: HashMap::new(),
+            waiters: Vec::new(),
+            message_generator: 1,
         }));
 
-        let inner_clone = inner.clone();
-        session.callback = Some(Box::new(move |event| {
-            let inner_clone = inner_clone.clone();
-            tokio::spawn(async move {
-                log::info!("{event:?}");
-                let clients = inner_clone.clients().await;
-                for client in clients {
-                    inner_clone
-                        .send(ClientPackets::NewSessionEvent(event.clone()), &client)
-                        .await;
-                }
-            });
-        }));
-        let session = session.new_session();
-        let default_location = session.get_default_location().unwrap();
-        default_location
-            .set_path(dirs::home_dir().unwrap().join("Downloads"))
-            .unwrap();
+        if let Some(socket) = socket {
+            tokio::spawn(recv_udp(socket, incoming_sender.clone()));
+        }
+
+        if let Some(addr) = config.tcp {
+            let listener = TcpListener::bind(addr).await?;
+            log::info!("Listening on: {addr}");
+            tokio::spawn(accept_tcp(listener, inner.clone(), incoming_sender.clone()));
+        }
+
+        #[cfg(unix)]
+        if let Some(path) = config.unix {
+            let listener = bind_unix(&path)?;
+            log::info!("Listening on: {path:?}");
+            tokio::spawn(accept_unix(
+                listener,
+                inner.clone(),
+                incoming_sender.clone(),
+            ));
+        }
+
+        let (events_sender, events) = unbounded_channel();
+        let (waits_sender, waits) = unbounded_channel();
+        tokio::spawn(send_events(inner.clone(), events, config.event_interval));
+        let session = SessionThread::spawn(move |event| {
+            if ends_wait(&event) {
+                let _ = waits_sender.send(event.clone());
+            }
+            let _ = events_sender.send(event);
+        })?;
+        tokio::spawn(wake_waiters(session.clone(), inner.clone(), waits));
+        let (requests, queued) = unbounded_channel();
+        tokio::spawn(run_requests(
+            session.clone(),
+            inner.clone(),
+            data_streams.clone(),
+            queued,
+        ));
 
         Ok(Self {
             session,
             inner,
-            socket: socket_clone,
+            incoming,
+            requests,
+            data_streams,
         })
     }
 
     pub async fn run(mut self) {
+        let mut last_gc = SystemTime::now();
         loop {
-            let _ = self.socket.readable().await;
-            self.respond_to_requests().await;
+            match tokio::time::timeout(ACK_POLL, self.incoming.recv()).await {
+                Ok(Some(incoming)) => {
+                    let mut messages = vec![incoming];
+                    while let Ok(incoming) = self.incoming.try_recv() {
+                        messages.push(incoming);
+                    }
+                    self.respond_to_requests(messages).await;
+                }
+                Ok(None) => break,
+                Err(_) => {}
+            }
+            self.inner.retransmit_events().await;
+
+            if last_gc.elapsed().unwrap_or_default() >= shm::GC_INTERVAL {
+                last_gc = SystemTime::now();
+                tokio::task::spawn_blocking(|| shm::gc(REPLY_EXPIRY));
+            }
         }
     }
 
-    async fn respond_to_requests(&mut self) {
-        for (addr, packets) in self.inner.recv().await {
+    async fn respond_to_requests(&mut self, messages: Vec<Incoming>) {
+        let (requests, malformed, refused) = self.inner.decode(messages).await;
+
+        for (addr, packet) in refused {
+            self.inner.send(packet, &addr).await
+        }
+
+        for (addr, id, banned) in malformed {
+            let reason = if banned {
+                format!(
+                    "Too many malformed packets, ignored for {}s",
+                    BAN_TIME.as_secs()
+                )
+            } else {
+                "Malformed packet".to_string()
+            };
+            self.inner
+                .send(
+                    ClientPackets::Error(id, SessionError::Custom(reason)),
+                    &addr,
+                )
+                .await
+        }
+
+        for (addr, packets) in requests {
             for packet in packets {
-                match packet {
-                    ServerPackets::Tick => {}
-                    ServerPackets::GetDefaultLocation { id } => {
-                        let packet = match self.session.get_default_location() {
-                            Ok(ok) => ClientPackets::GetDefaultLocation(id, Ok(ok.id())),
-                            Err(err) => ClientPackets::GetDefaultLocation(id, Err(err)),
-                        };
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::LocationGetName { id, from } => {
-                        let packet = ClientPackets::LocationGetName(
-                            id,
-                            self.session.location_get_name(&from),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::LocationSetName { id, from, to } => {
-                        let packet = ClientPackets::LocationSetName(
-                            id,
-                            self.session.location_set_name(&from, &to),
+                if !matches!(packet, ServerPackets::Hello { .. })
+                    && !self.inner.is_greeted(&addr).await
+                {
+                    log::warn!("Client: {addr} did not send Hello, refused: {packet:?}");
+                    if packet.id() != 0 {
+                        let packet = ClientPackets::Error(
+                            packet.id(),
+                            SessionError::ServerInvalidIndentification,
                         );
                         self.inner.send(packet, &addr).await
                     }
-                    ServerPackets::LocationGetDesc { id, from } => {
-                        let packet = ClientPackets::LocationGetDesc(
-                            id,
-                            self.session.location_get_desc(&from),
-                        );
+                    continue;
+                }
 
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::LocationSetDesc { id, from, to } => {
-                        let packet = ClientPackets::LocationSetDesc(
-                            id,
-                            self.session.location_set_desc(&from, &to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::LocationGetInfo { id, from } => {
-                        let packet = ClientPackets::LocationGetInfo(
-                            id,
-                            self.session.location_get_location_info(&from),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::CreateElement {
+                match packet {
+                    ServerPackets::Hello {
                         id,
-                        location_id,
+                        version,
+                        features,
                         name,
                     } => {
-                        let packet = match self.session.create_element(&name, &location_id) {
-                            Ok(ok) => ClientPackets::CreateElement(id, Ok(ok.id())),
-                            Err(err) => ClientPackets::CreateElement(id, Err(err)),
+                        let packet = if version == DAEMON_VERSION {
+                            log::info!("Client: {name} connected from: {addr}");
+                            let info = ClientInfo {
+                                name,
+                                version,
+                                features,
+                            };
+                            self.inner.greet(addr, info).await;
+                            ClientPackets::Welcome(
+                                id,
+                                Ok(Welcome {
+                                    version: DAEMON_VERSION,
+                                    features: crate::features(self.inner.is_shm_peer(&addr).await),
+                                    event_seq: self.inner.event_seq().await,
+                                }),
+                            )
+                        } else {
+                            log::warn!(
+                                "Client: {name} from: {addr} has version: {version} expected: {DAEMON_VERSION}"
+                            );
+                            ClientPackets::Welcome(
+                                id,
+                                Err(SessionError::ServerInvalidIndentification),
+                            )
                         };
                         self.inner.send(packet, &addr).await
                     }
-                    ServerPackets::ElementGetName { id, element_id } => {
-                        let packet = ClientPackets::ElementGetName(
-                            id,
-                            self.session.element_get_name(&element_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementSetName { id, element_id, to } => {
-                        let packet = ClientPackets::ElementSetName(
-                            id,
-                            self.session.element_set_name(&element_id, &to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementGetDesc { id, element_id } => {
-                        let packet = ClientPackets::ElementGetDesc(
-                            id,
-                            self.session.element_get_desc(&element_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementSetDesc { id, element_id, to } => {
-                        let packet = ClientPackets::ElementSetDesc(
-                            id,
-                            self.session.element_set_desc(&element_id, &to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementGetMeta { id, element_id } => {
-                        let packet = ClientPackets::ElementGetMeta(
-                            id,
-                            self.session.element_get_meta(&element_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementSetMeta { id, element_id, to } => {
-                        let packet = ClientPackets::ElementSetMeta(
-                            id,
-                            self.session.element_set_meta(&element_id, &to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementGetInfo { id, element_id } => {
-                        let packet = ClientPackets::ElementGetInfo(
-                            id,
-                            Box::new(self.session.element_get_element_info(&element_id)),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::LoadModule { id, path } => {
-                        let packet = ClientPackets::LoadModule(
-                            id,
-                            match self.session.load_module(path) {
-                                Ok(ok) => Ok(ok.id()),
-                                Err(err) => Err(err),
-                            },
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::RemoveModule { id, module_id } => {
-                        let packet = ClientPackets::RemoveModule(
-                            id,
-                            match self.session.remove_module(module_id) {
-                                Ok(_) => Ok(()),
-                                Err(err) => Err(err),
-                            },
-                        );
-                        self.inner.send(packet, &addr).await
+                    ServerPackets::Tick => {}
+                    ServerPackets::SetEventFilter { id, filter } => {
+                        self.inner.set_filter(addr, filter).await;
+                        self.inner
+                            .send(ClientPackets::SetEventFilter(id, Ok(())), &addr)
+                            .await
                     }
-                    ServerPackets::GetActionsLen { id } => {
-                        let packet =
-                            ClientPackets::GetActionsLen(id, self.session.get_actions_len());
-                        self.inner.send(packet, &addr).await
+                    ServerPackets::AckEvent { seq } => self.inner.ack_event(&addr, seq).await,
+                    ServerPackets::Connect { id, lease } => {
+                        let lease = self.inner.connect(addr, Duration::from_millis(lease)).await;
+                        self.inner
+                            .send(
+                                ClientPackets::Connect(id, Ok(lease.as_millis() as u64)),
+                                &addr,
+                            )
+                            .await
                     }
-                    ServerPackets::GetActions { id, range } => {
-                        let packet = ClientPackets::GetActions(
-                            id,
-                            match self.session.get_actions(range) {
-                                Ok(ok) => {
-                                    let mut tmp = Vec::new();
-                                    for k in ok {
-                                        tmp.push((k.0, k.1.id(), k.2));
-                                    }
-                                    Ok(tmp)
-                                }
-                                Err(err) => Err(err),
-                            },
-                        );
-                        self.inner.send(packet, &addr).await
+                    ServerPackets::Disconnect => self.inner.remove_client(&addr).await,
+                    ServerPackets::Ping { id } => {
+                        self.inner.send(ClientPackets::Pong(id), &addr).await
                     }
-                    ServerPackets::RunAction {
-                        id,
-                        module_id,
-                        name,
-                        data,
-                    } => {
-                        let packet = ClientPackets::RunAction(
-                            id,
-                            self.session.run_action(&module_id, name, data),
-                        );
-                        self.inner.send(packet, &addr).await
+                    ServerPackets::Cancel { id } => {
+                        if self.inner.remove_waiter(&addr, id).await
+                            || self.inner.cancel_request(&addr, id).await
+                        {
+                            log::debug!("Request: {id} from: {addr} was cancelled");
+                        }
                     }
-                    ServerPackets::GetModulesLen { id } => {
-                        let packet =
-                            ClientPackets::GetModulesLen(id, self.session.get_modules_len());
-                        self.inner.send(packet, &addr).await
+                    ServerPackets::EventsSince { id, seq } => {
+                        let replay = self.inner.events_since(&addr, seq).await;
+                        self.inner
+                            .send(ClientPackets::EventsSince(id, Ok(replay)), &addr)
+                            .await
                     }
-                    ServerPackets::GetModules { id, range } => {
-                        let packet = ClientPackets::GetModules(
+                    ServerPackets::ElementWait { id, element_id } => {
+                        // responded when an event of the element ends it, so other requests are not blocked
+                        let waiter = Waiter {
+                            addr,
                             id,
-                            match self.session.get_modules(range) {
-                                Ok(ok) => {
-                                    let mut tmp = Vec::with_capacity(ok.len());
-                                    for k in ok {
-                                        tmp.push(k.id())
+                            element_id,
+                        };
+                        if !self.inner.park_waiter(waiter.clone()).await {
+                            log::debug!("Wait: {id} from: {addr} is a retransmission");
+                            continue;
+                        }
+                        // the element can be done already
+                        let session = self.session.clone();
+                        let inner = self.inner.clone();
+                        tokio::spawn(async move {
+                            resolve_waiters(&session, &inner, vec![waiter]).await
+                        });
+                    }
+                    packet => match self
+                        .inner
+                        .start_request(addr, packet.id(), !packet.is_idempotent())
+                        .await
+                    {
+                        RequestState::New => {
+                            if let Some((stream, len)) = data_window(&packet) {
+                                let reserved =
+                                    self.data_streams.lock().unwrap().reserve(addr, stream, len);
+                                if !reserved {
+                                    log::warn!("Data window of: {stream} from: {addr} is full");
+                                    let id = packet.id();
+                                    let error = ClientPackets::Error(
+                                        id,
+                                        SessionError::Custom("Data window is full".to_string()),
+                                    );
+                                    if let Some(error) =
+                                        self.inner.finish_request(&addr, id, Some(error)).await
+                                    {
+                                        self.inner.send(error, &addr).await
                                     }
-                                    Ok(tmp)
+                                    continue;
                                 }
-                                Err(err) => Err(err),
-                            },
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ModuleGetName { id, module_id } => {
-                        let packet = ClientPackets::ModuleGetName(
-                            id,
-                            self.session.module_get_name(&module_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ModuleSetName { id, module_id, to } => {
-                        let packet = ClientPackets::ModuleSetName(
-                            id,
-                            self.session.module_set_name(&module_id, to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ModuleGetDefaultName { id, module_id } => {
-                        let packet = ClientPackets::ModuleGetDefaultName(
-                            id,
-                            self.session.module_get_default_name(&module_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ModuleGetDesc { id, module_id } => {
-                        let packet = ClientPackets::ModuleGetDesc(
-                            id,
-                            self.session.module_get_desc(&module_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ModuleSetDesc { id, module_id, to } => {
-                        let packet = ClientPackets::ModuleSetDesc(
-                            id,
-                            self.session.module_set_desc(&module_id, to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ModuleGetDefaultDesc { id, module_id } => {
-                        let packet = ClientPackets::ModuleGetDefaultDesc(
-                            id,
-                            self.session.module_get_default_desc(&module_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ModuleGetProxy { id, module_id } => {
-                        let packet = ClientPackets::ModuleGetProxy(
-                            id,
-                            self.session.module_get_proxy(&module_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ModuleSetProxy { id, module_id, to } => {
-                        let packet = ClientPackets::ModuleSetProxy(
-                            id,
-                            self.session.module_set_proxy(&module_id, to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ModuleGetSettings { id, module_id } => {
-                        let packet = ClientPackets::ModuleGetSettings(
-                            id,
-                            Box::new(self.session.module_get_settings(&module_id)),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ModuleSetSettings { id, module_id, to } => {
-                        let packet = ClientPackets::ModuleSetSettings(
-                            id,
-                            self.session.module_set_settings(&module_id, to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ModuleGetElementSettings { id, module_id } => {
-                        let packet = ClientPackets::ModuleGetElementSettings(
-                            id,
-                            self.session.module_get_element_settings(&module_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ModuleSetElementSettings { id, module_id, to } => {
-                        let packet = ClientPackets::ModuleSetElementSettings(
-                            id,
-                            self.session.module_set_element_settings(&module_id, to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ModuleInitLocation {
-                        id,
-                        module_id,
-                        location_id,
-                    } => {
-                        let packet = ClientPackets::ModuleInitLocation(
-                            id,
-                            self.session.module_init_location(&module_id, &location_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ModuleInitElement {
-                        id,
-                        module_id,
-                        element_id,
-                    } => {
-                        let packet = ClientPackets::ModuleInitElement(
-                            id,
-                            self.session.module_init_element(&module_id, &element_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ModuleAcceptUrl { id, module_id, url } => {
-                        let packet = ClientPackets::ModuleAcceptUrl(
-                            id,
-                            self.session.module_accept_url(&module_id, url),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ModuleAcceptExtension {
-                        id,
-                        module_id,
-                        filename,
-                    } => {
-                        let packet = ClientPackets::ModuleAcceptExtension(
-                            id,
-                            self.session.module_accept_extension(&module_id, &filename),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::MoveElement {
-                        id,
-                        element_id,
-                        location_id,
-                    } => {
-                        let packet = ClientPackets::MoveElement(
-                            id,
-                            self.session.move_element(&element_id, &location_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::DestroyElement { id, element_id } => {
-                        let packet = ClientPackets::DestroyElement(
-                            id,
-                            match self.session.destroy_element(element_id) {
-                                Ok(_) => Ok(()),
-                                Err(err) => Err(err),
-                            },
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementGetElementData { id, element_id } => {
-                        let packet = ClientPackets::ElementGetElementData(
-                            id,
-                            self.session.element_get_element_data(&element_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementSetElementData { id, element_id, to } => {
-                        let packet = ClientPackets::ElementSetElementData(
-                            id,
-                            self.session.element_set_element_data(&element_id, to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementGetModuleData { id, element_id } => {
-                        let packet = ClientPackets::ElementGetModuleData(
-                            id,
-                            self.session.element_get_module_data(&element_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementSetModuleData { id, element_id, to } => {
-                        let packet = ClientPackets::ElementSetModuleData(
-                            id,
-                            self.session.element_set_module_data(&element_id, to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementGetModule { id, element_id } => {
-                        let packet = ClientPackets::ElementGetModule(
-                            id,
-                            match self.session.element_get_module(&element_id) {
-                                Ok(ok) => match ok {
-                                    Some(some) => Ok(Some(some.id())),
-                                    None => Ok(None),
-                                },
-                                Err(err) => Err(err),
-                            },
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementSetModule {
-                        id,
-                        element_id,
-                        module,
-                    } => {
-                        let packet = ClientPackets::ElementSetModule(
-                            id,
-                            self.session.element_set_module(&element_id, module),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementGetStatuses { id, element_id } => {
-                        let packet = ClientPackets::ElementGetStatuses(
-                            id,
-                            self.session.element_get_statuses(&element_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementSetStatuses { id, element_id, to } => {
-                        let packet = ClientPackets::ElementSetStatuses(
-                            id,
-                            self.session.element_set_statuses(&element_id, to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementGetStatus { id, element_id } => {
-                        let packet = ClientPackets::ElementGetStatus(
-                            id,
-                            self.session.element_get_status(&element_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementSetStatus { id, element_id, to } => {
-                        let packet = ClientPackets::ElementSetStatus(
-                            id,
-                            self.session.element_set_status(&element_id, to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementGetData { id, element_id } => {
-                        let packet = ClientPackets::ElementGetData(
-                            id,
-                            self.session.element_get_data(&element_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementSetData { id, element_id, to } => {
-                        let packet = ClientPackets::ElementSetData(
-                            id,
-                            self.session.element_set_data(&element_id, to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementGetProgress { id, element_id } => {
-                        let packet = ClientPackets::ElementGetProgress(
-                            id,
-                            self.session.element_get_progress(&element_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementSetProgress { id, element_id, to } => {
-                        let packet = ClientPackets::ElementSetProgress(
-                            id,
-                            self.session.element_set_progress(&element_id, to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementGetShouldSave { id, element_id } => {
-                        let packet = ClientPackets::ElementGetShouldSave(
-                            id,
-                            self.session.element_get_should_save(&element_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementSetShouldSave { id, element_id, to } => {
-                        let packet = ClientPackets::ElementSetShouldSave(
-                            id,
-                            self.session.element_set_should_save(&element_id, to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementGetEnabled { id, element_id } => {
-                        let packet = ClientPackets::ElementGetEnabled(
-                            id,
-                            self.session.element_get_enabled(&element_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementSetEnabled { id, element_id, to } => {
-                        let packet = ClientPackets::ElementSetEnabled(
-                            id,
-                            self.session.element_set_enabled(&element_id, to, None),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementResolvModule { id, element_id } => {
-                        let packet = ClientPackets::ElementResolvModule(
-                            id,
-                            self.session.element_resolv_module(&element_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementWait { id, element_id } => {
-                        // TODO: Implement this better
-                        // This will block the daemon until the element is done
-                        // This is really bad because any request from other sessions will be ignored
-                        // and result into timeout
-                        // this is here because i'am lazy to makeit better with some event handler
-                        // if some one want to fix this go end do it
-                        //
-                        // Problem from 1/29/2023
-                        let packet =
-                            ClientPackets::ElementWait(id, self.session.element_wait(&element_id));
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementNotify {
-                        id,
-                        element_id,
-                        event,
-                    } => {
-                        let packet = ClientPackets::ElementNotify(
-                            id,
-                            self.session.element_notify(&element_id, event),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementEmit {
-                        id,
-                        element_id,
-                        event,
-                    } => {
-                        let packet = ClientPackets::ElementEmit(
-                            id,
-                            self.session.element_emit(&element_id, event),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementSubscribe { id, element_id, to } => {
-                        let packet = ClientPackets::ElementSubscribe(
-                            id,
-                            self.session.element_subscribe(&element_id, to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementUnSubscribe { id, element_id, to } => {
-                        let packet = ClientPackets::ElementUnSubscribe(
-                            id,
-                            self.session.element_unsubscribe(&element_id, to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::CreateLocation {
-                        id,
-                        name,
-                        location_id,
-                    } => {
-                        let packet = ClientPackets::CreateLocation(
-                            id,
-                            match self.session.create_location(&name, &location_id) {
-                                Ok(ok) => Ok(ok.id()),
-                                Err(err) => Err(err),
-                            },
-                        );
-                        self.inner.send(packet, &addr).await
+                            }
+                            if self.requests.send((addr, packet)).is_err() {
+                                log::error!("Session thread is closed");
+                            }
+                        }
+                        RequestState::Running => {
+                            log::debug!("Request: {} from: {addr} is running", packet.id())
+                        }
+                        RequestState::Done(response) => {
+                            log::debug!("Request: {} from: {addr} was answered", packet.id());
+                            self.inner.send(response, &addr).await
+                        }
+                    },
+                }
+            }
+        }
+    }
+}
+
+/// Events after which an element can be done waiting
+fn ends_wait(event: &SessionEvent) -> bool {
+    matches!(
+        event,
+        SessionEvent::ElementEnabledChanged(..)
+            | SessionEvent::ElementStatusChanged(..)
+            | SessionEvent::DestroyedElement(_)
+            | SessionEvent::ElementIdChanged(..)
+    )
+}
+
+/// Checks the waiters of the elements that the events are about
+async fn wake_waiters(
+    session: SessionThread,
+    inner: Arc<Mutex<DaemonInner>>,
+    mut events: UnboundedReceiver<SessionEvent>,
+) {
+    while let Some(event) = events.recv().await {
+        let mut elements = Vec::new();
+        let mut next = Some(event);
+        // every event that arrived meanwhile is checked together
+        while let Some(event) = next {
+            if let SessionEvent::ElementIdChanged(last, new) = &event {
+                inner.follow_waiters(last, new).await;
+            }
+            if let Some(element_id) = event_element(&event) {
+                if !elements.contains(element_id) {
+                    elements.push(element_id.clone());
+                }
+            }
+            next = events.try_recv().ok();
+        }
+
+        let waiters = inner
+            .waiters()
+            .await
+            .into_iter()
+            .filter(|waiter| elements.contains(&waiter.element_id))
+            .collect();
+        resolve_waiters(&session, &inner, waiters).await;
+    }
+}
+
+/// Responds to the waiters whose element is not enabled anymore, has an error or does not exist
+async fn resolve_waiters(
+    session: &SessionThread,
+    inner: &Arc<Mutex<DaemonInner>>,
+    waiters: Vec<Waiter>,
+) {
+    if waiters.is_empty() {
+        return;
+    }
+
+    let elements = waiters
+        .iter()
+        .map(|waiter| waiter.element_id.clone())
+        .collect::<Vec<ElementId>>();
+    let Some(states) = session
+        .run(move |session| {
+            elements
+                .iter()
+                .map(|element_id| match session.element_is_error(element_id) {
+                    Ok(true) => Some(Ok(())),
+                    Ok(false) => match session.element_get_enabled(element_id) {
+                        Ok(true) => None,
+                        Ok(false) => Some(Ok(())),
+                        Err(err) => Some(Err(err)),
+                    },
+                    Err(err) => Some(Err(err)),
+                })
+                .collect::<Vec<_>>()
+        })
+        .await
+    else {
+        return;
+    };
+
+    for (waiter, state) in waiters.into_iter().zip(states) {
+        let Some(response) = state else {
+            continue;
+        };
+        // the client was removed or cancelled it meanwhile
+        if inner.remove_waiter(&waiter.addr, waiter.id).await {
+            inner
+                .send(
+                    ClientPackets::ElementWait(waiter.id, response),
+                    &waiter.addr,
+                )
+                .await
+        }
+    }
+}
+
+/// Runs the requests one at a time in the order that they arrived
+/// Meanwhile the daemon handles the packets that don't need the session, so a slow call doesn't freeze it
+async fn run_requests(
+    session: SessionThread,
+    inner: Arc<Mutex<DaemonInner>>,
+    data_streams: Arc<std::sync::Mutex<DataStreams>>,
+    mut requests: UnboundedReceiver<(ClientAddr, ServerPackets)>,
+) {
+    while let Some((addr, packet)) = requests.recv().await {
+        run_request(&session, &inner, &data_streams, addr, packet).await
+    }
+}
+
+/// Runs the request on the session thread and sends the response, unless it was cancelled
+async fn run_request(
+    session: &SessionThread,
+    inner: &Arc<Mutex<DaemonInner>>,
+    data_streams: &Arc<std::sync::Mutex<DataStreams>>,
+    addr: ClientAddr,
+    packet: ServerPackets,
+) {
+    let id = packet.id();
+    let window = data_window(&packet);
+    let release = || {
+        if let Some((stream, len)) = window {
+            data_streams.lock().unwrap().release(addr, stream, len);
+        }
+    };
+
+    if inner.is_cancelled(&addr, id).await {
+        log::debug!("Request: {id} from: {addr} was cancelled before running");
+        release();
+        inner.finish_request(&addr, id, None).await;
+        return;
+    }
+
+    let data_streams_clone = data_streams.clone();
+    let response = session
+        .run(move |session| match packet {
+            packet @ (ServerPackets::DataOpen { .. }
+            | ServerPackets::DataRead { .. }
+            | ServerPackets::DataWrite { .. }
+            | ServerPackets::DataClose { .. }) => {
+                handle_data(session, &data_streams_clone, addr, packet)
+            }
+            packet => handle_request(session, packet),
+        })
+        .await;
+    release();
+    match response {
+        Some(response) => {
+            if let Some(packet) = inner.finish_request(&addr, id, response).await {
+                inner.send(packet, &addr).await
+            }
+        }
+        None => {
+            inner.finish_request(&addr, id, None).await;
+            log::error!("Request: {id} from: {addr} failed")
+        }
+    }
+}
+
+/// Responds to a request that only needs the session
+/// Runs on the session thread
+fn handle_request(session: &dyn TSession, packet: ServerPackets) -> Option<ClientPackets> {
+    let packet = match packet {
+        ServerPackets::GetTreeSnapshot {
+            id,
+            root,
+            depth,
+            fields,
+        } => ClientPackets::GetTreeSnapshot(id, tree_snapshot(session, root, depth, &fields)),
+        ServerPackets::QueryElements {
+            id,
+            query,
+            cursor,
+            limit,
+        } => ClientPackets::QueryElements(
+            id,
+            query_elements(session, &query, cursor.as_ref(), limit),
+        ),
+        ServerPackets::Batch { id, requests } => ClientPackets::Batch(
+            id,
+            requests
+                .into_iter()
+                .map(|request| {
+                    let id = request.id();
+                    let kind = request.kind();
+                    handle_request(session, request).unwrap_or_else(|| {
+                        ClientPackets::Error(
+                            id,
+                            SessionError::Custom(format!("{kind} cannot be batched")),
+                        )
+                    })
+                })
+                .collect(),
+        ),
+        ServerPackets::GetDefaultLocation { id } => match session.get_default_location() {
+            Ok(ok) => ClientPackets::GetDefaultLocation(id, Ok(ok.id())),
+            Err(err) => ClientPackets::GetDefaultLocation(id, Err(err)),
+        },
+        ServerPackets::LocationGetName { id, from } => {
+            ClientPackets::LocationGetName(id, session.location_get_name(&from))
+        }
+        ServerPackets::LocationSetName { id, from, to } => {
+            ClientPackets::LocationSetName(id, session.location_set_name(&from, &to))
+        }
+        ServerPackets::LocationGetDesc { id, from } => {
+            ClientPackets::LocationGetDesc(id, session.location_get_desc(&from))
+        }
+        ServerPackets::LocationSetDesc { id, from, to } => {
+            ClientPackets::LocationSetDesc(id, session.location_set_desc(&from, &to))
+        }
+        ServerPackets::LocationGetInfo { id, from } => {
+            ClientPackets::LocationGetInfo(id, session.location_get_location_info(&from))
+        }
+        ServerPackets::CreateElement {
+            id,
+            location_id,
+            name,
+        } => match session.create_element(&name, &location_id) {
+            Ok(ok) => ClientPackets::CreateElement(id, Ok(ok.id())),
+            Err(err) => ClientPackets::CreateElement(id, Err(err)),
+        },
+        ServerPackets::ElementGetName { id, element_id } => {
+            ClientPackets::ElementGetName(id, session.element_get_name(&element_id))
+        }
+        ServerPackets::ElementSetName { id, element_id, to } => {
+            ClientPackets::ElementSetName(id, session.element_set_name(&element_id, &to))
+        }
+        ServerPackets::ElementGetDesc { id, element_id } => {
+            ClientPackets::ElementGetDesc(id, session.element_get_desc(&element_id))
+        }
+        ServerPackets::ElementSetDesc { id, element_id, to } => {
+            ClientPackets::ElementSetDesc(id, session.element_set_desc(&element_id, &to))
+        }
+        ServerPackets::ElementGetMeta { id, element_id } => {
+            ClientPackets::ElementGetMeta(id, session.element_get_meta(&element_id))
+        }
+        ServerPackets::ElementSetMeta { id, element_id, to } => {
+            ClientPackets::ElementSetMeta(id, session.element_set_meta(&element_id, &to))
+        }
+        ServerPackets::ElementGetInfo { id, element_id } => ClientPackets::ElementGetInfo(
+            id,
+            Box::new(session.element_get_element_info(&element_id)),
+        ),
+        ServerPackets::LoadModule { id, path } => ClientPackets::LoadModule(
+            id,
+            match session.load_module(path) {
+                Ok(ok) => Ok(ok.id()),
+                Err(err) => Err(err),
+            },
+        ),
+        ServerPackets::RemoveModule { id, module_id } => ClientPackets::RemoveModule(
+            id,
+            match session.remove_module(module_id) {
+                Ok(_) => Ok(()),
+                Err(err) => Err(err),
+            },
+        ),
+        ServerPackets::GetActionsLen { id } => {
+            ClientPackets::GetActionsLen(id, session.get_actions_len())
+        }
+        ServerPackets::GetActions { id, range } => ClientPackets::GetActions(
+            id,
+            match session.get_actions(range) {
+                Ok(ok) => {
+                    let mut tmp = Vec::new();
+                    for k in ok {
+                        tmp.push((k.0, k.1.id(), k.2));
+                    }
+                    Ok(tmp)
+                }
+                Err(err) => Err(err),
+            },
+        ),
+        ServerPackets::RunAction {
+            id,
+            module_id,
+            name,
+            data,
+        } => ClientPackets::RunAction(id, session.run_action(&module_id, name, data)),
+        ServerPackets::GetModulesLen { id } => {
+            ClientPackets::GetModulesLen(id, session.get_modules_len())
+        }
+        ServerPackets::GetModules { id, range } => ClientPackets::GetModules(
+            id,
+            match session.get_modules(range) {
+                Ok(ok) => {
+                    let mut tmp = Vec::with_capacity(ok.len());
+                    for k in ok {
+                        tmp.push(k.id())
+                    }
+                    Ok(tmp)
+                }
+                Err(err) => Err(err),
+            },
+        ),
+        ServerPackets::ModuleGetName { id, module_id } => {
+            ClientPackets::ModuleGetName(id, session.module_get_name(&module_id))
+        }
+        ServerPackets::ModuleSetName { id, module_id, to } => {
+            ClientPackets::ModuleSetName(id, session.module_set_name(&module_id, to))
+        }
+        ServerPackets::ModuleGetDefaultName { id, module_id } => {
+            ClientPackets::ModuleGetDefaultName(id, session.module_get_default_name(&module_id))
+        }
+        ServerPackets::ModuleGetDesc { id, module_id } => {
+            ClientPackets::ModuleGetDesc(id, session.module_get_desc(&module_id))
+        }
+        ServerPackets::ModuleSetDesc { id, module_id, to } => {
+            ClientPackets::ModuleSetDesc(id, session.module_set_desc(&module_id, to))
+        }
+        ServerPackets::ModuleGetDefaultDesc { id, module_id } => {
+            ClientPackets::ModuleGetDefaultDesc(id, session.module_get_default_desc(&module_id))
+        }
+        ServerPackets::ModuleGetProxy { id, module_id } => {
+            ClientPackets::ModuleGetProxy(id, session.module_get_proxy(&module_id))
+        }
+        ServerPackets::ModuleSetProxy { id, module_id, to } => {
+            ClientPackets::ModuleSetProxy(id, session.module_set_proxy(&module_id, to))
+        }
+        ServerPackets::ModuleGetSettings { id, module_id } => {
+            ClientPackets::ModuleGetSettings(id, Box::new(session.module_get_settings(&module_id)))
+        }
+        ServerPackets::ModuleSetSettings { id, module_id, to } => {
+            ClientPackets::ModuleSetSettings(id, session.module_set_settings(&module_id, to))
+        }
+        ServerPackets::ModuleGetElementSettings { id, module_id } => {
+            ClientPackets::ModuleGetElementSettings(
+                id,
+                session.module_get_element_settings(&module_id),
+            )
+        }
+        ServerPackets::ModuleSetElementSettings { id, module_id, to } => {
+            ClientPackets::ModuleSetElementSettings(
+                id,
+                session.module_set_element_settings(&module_id, to),
+            )
+        }
+        ServerPackets::ModuleInitLocation {
+            id,
+            module_id,
+            location_id,
+        } => ClientPackets::ModuleInitLocation(
+            id,
+            session.module_init_location(&module_id, &location_id),
+        ),
+        ServerPackets::ModuleInitElement {
+            id,
+            module_id,
+            element_id,
+        } => ClientPackets::ModuleInitElement(
+            id,
+            session.module_init_element(&module_id, &element_id),
+        ),
+        ServerPackets::ModuleAcceptUrl { id, module_id, url } => {
+            ClientPackets::ModuleAcceptUrl(id, session.module_accept_url(&module_id, url))
+        }
+        ServerPackets::ModuleAcceptExtension {
+            id,
+            module_id,
+            filename,
+        } => ClientPackets::ModuleAcceptExtension(
+            id,
+            session.module_accept_extension(&module_id, &filename),
+        ),
+        ServerPackets::MoveElement {
+            id,
+            element_id,
+            location_id,
+        } => ClientPackets::MoveElement(id, session.move_element(&element_id, &location_id)),
+        ServerPackets::DestroyElement { id, element_id } => ClientPackets::DestroyElement(
+            id,
+            match session.destroy_element(element_id) {
+                Ok(_) => Ok(()),
+                Err(err) => Err(err),
+            },
+        ),
+        ServerPackets::ElementGetElementData { id, element_id } => {
+            ClientPackets::ElementGetElementData(id, session.element_get_element_data(&element_id))
+        }
+        ServerPackets::ElementSetElementData { id, element_id, to } => {
+            ClientPackets::ElementSetElementData(
+                id,
+                session.element_set_element_data(&element_id, to),
+            )
+        }
+        ServerPackets::ElementGetModuleData { id, element_id } => {
+            ClientPackets::ElementGetModuleData(id, session.element_get_module_data(&element_id))
+        }
+        ServerPackets::ElementSetModuleData { id, element_id, to } => {
+            ClientPackets::ElementSetModuleData(
+                id,
+                session.element_set_module_data(&element_id, to),
+            )
+        }
+        ServerPackets::ElementGetModule { id, element_id } => ClientPackets::ElementGetModule(
+            id,
+            match session.element_get_module(&element_id) {
+                Ok(ok) => match ok {
+                    Some(some) => Ok(Some(some.id())),
+                    None => Ok(None),
+                },
+                Err(err) => Err(err),
+            },
+        ),
+        ServerPackets::ElementSetModule {
+            id,
+            element_id,
+            module,
+        } => ClientPackets::ElementSetModule(id, session.element_set_module(&element_id, module)),
+        ServerPackets::ElementGetStatuses { id, element_id } => {
+            ClientPackets::ElementGetStatuses(id, session.element_get_statuses(&element_id))
+        }
+        ServerPackets::ElementSetStatuses { id, element_id, to } => {
+            ClientPackets::ElementSetStatuses(id, session.element_set_statuses(&element_id, to))
+        }
+        ServerPackets::ElementGetStatus { id, element_id } => {
+            ClientPackets::ElementGetStatus(id, session.element_get_status(&element_id))
+        }
+        ServerPackets::ElementSetStatus { id, element_id, to } => {
+            ClientPackets::ElementSetStatus(id, session.element_set_status(&element_id, to))
+        }
+        ServerPackets::ElementGetData { id, element_id } => {
+            ClientPackets::ElementGetData(id, session.element_get_data(&element_id))
+        }
+        ServerPackets::ElementSetData { id, element_id, to } => {
+            ClientPackets::ElementSetData(id, session.element_set_data(&element_id, to))
+        }
+        ServerPackets::ElementGetProgress { id, element_id } => {
+            ClientPackets::ElementGetProgress(id, session.element_get_progress(&element_id))
+        }
+        ServerPackets::ElementSetProgress { id, element_id, to } => {
+            ClientPackets::ElementSetProgress(id, session.element_set_progress(&element_id, to))
+        }
+        ServerPackets::ElementGetShouldSave { id, element_id } => {
+            ClientPackets::ElementGetShouldSave(id, session.element_get_should_save(&element_id))
+        }
+        ServerPackets::ElementSetShouldSave { id, element_id, to } => {
+            ClientPackets::ElementSetShouldSave(
+                id,
+                session.element_set_should_save(&element_id, to),
+            )
+        }
+        ServerPackets::ElementGetEnabled { id, element_id } => {
+            ClientPackets::ElementGetEnabled(id, session.element_get_enabled(&element_id))
+        }
+        ServerPackets::ElementSetEnabled { id, element_id, to } => {
+            ClientPackets::ElementSetEnabled(id, session.element_set_enabled(&element_id, to, None))
+        }
+        ServerPackets::ElementResolvModule { id, element_id } => {
+            ClientPackets::ElementResolvModule(id, session.element_resolv_module(&element_id))
+        }
+        ServerPackets::ElementNotify {
+            id,
+            element_id,
+            event,
+        } => ClientPackets::ElementNotify(id, session.element_notify(&element_id, event)),
+        ServerPackets::ElementEmit {
+            id,
+            element_id,
+            event,
+        } => ClientPackets::ElementEmit(id, session.element_emit(&element_id, event)),
+        ServerPackets::ElementSubscribe { id, element_id, to } => {
+            ClientPackets::ElementSubscribe(id, session.element_subscribe(&element_id, to))
+        }
+        ServerPackets::ElementUnSubscribe { id, element_id, to } => {
+            ClientPackets::ElementUnSubscribe(id, session.element_unsubscribe(&element_id, to))
+        }
+        ServerPackets::CreateLocation {
+            id,
+            name,
+            location_id,
+        } => ClientPackets::CreateLocation(
+            id,
+            match session.create_location(&name, &location_id) {
+                Ok(ok) => Ok(ok.id()),
+                Err(err) => Err(err),
+            },
+        ),
+        ServerPackets::GetLocationsLen { id, location_id } => {
+            ClientPackets::GetLocationsLen(id, session.get_locations_len(&location_id))
+        }
+        ServerPackets::GetLocations {
+            id,
+            location_id,
+            range,
+        } => ClientPackets::GetLocations(
+            id,
+            match session.get_locations(&location_id, range) {
+                Ok(ok) => {
+                    let mut tmp = Vec::with_capacity(ok.len());
+
+                    for k in ok {
+                        tmp.push(k.id())
                     }
-                    ServerPackets::GetLocationsLen { id, location_id } => {
-                        let packet = ClientPackets::GetLocationsLen(
-                            id,
-                            self.session.get_locations_len(&location_id),
-                        );
-                        self.inner.send(packet, &addr).await
+
+                    Ok(tmp)
+                }
+                Err(err) => Err(err),
+            },
+        ),
+        ServerPackets::DestroyLocation { id, location_id } => ClientPackets::DestroyLocation(
+            id,
+            match session.destroy_location(location_id) {
+                Ok(_) => Ok(()),
+                Err(err) => Err(err),
+            },
+        ),
+        ServerPackets::MoveLocation {
+            id,
+            location_id,
+            to,
+        } => ClientPackets::MoveLocation(id, session.move_location(&location_id, &to)),
+        ServerPackets::LocationGetPath { id, location_id } => {
+            ClientPackets::LocationGetPath(id, session.location_get_path(&location_id))
+        }
+        ServerPackets::LocationSetPath {
+            id,
+            location_id,
+            to,
+        } => ClientPackets::LocationSetPath(id, session.location_set_path(&location_id, to)),
+        ServerPackets::LocationGetShouldSave { id, location_id } => {
+            ClientPackets::LocationGetShouldSave(id, session.location_get_should_save(&location_id))
+        }
+        ServerPackets::LocationSetShouldSave {
+            id,
+            location_id,
+            to,
+        } => ClientPackets::LocationSetShouldSave(
+            id,
+            session.location_set_should_save(&location_id, to),
+        ),
+        ServerPackets::LocationGetElementsLen { id, location_id } => {
+            ClientPackets::LocationGetElementsLen(
+                id,
+                session.location_get_elements_len(&location_id),
+            )
+        }
+        ServerPackets::LocationGetElements {
+            id,
+            location_id,
+            range,
+        } => ClientPackets::LocationGetElements(
+            id,
+            match session.location_get_elements(&location_id, range) {
+                Ok(ok) => {
+                    let mut tmp = Vec::with_capacity(ok.len());
+
+                    for k in ok {
+                        tmp.push(k.id())
                     }
-                    ServerPackets::GetLocations {
-                        id,
-                        location_id,
-                        range,
-                    } => {
-                        let packet = ClientPackets::GetLocations(
-                            id,
-                            match self.session.get_locations(&location_id, range) {
-                                Ok(ok) => {
-                                    let mut tmp = Vec::with_capacity(ok.len());
 
-                                    for k in ok {
-                                        tmp.push(k.id())
-                                    }
+                    Ok(tmp)
+                }
+                Err(err) => Err(err),
+            },
+        ),
+        ServerPackets::LocationNotify {
+            id,
+            location_id,
+            event,
+        } => ClientPackets::LocationNotify(id, session.location_notify(&location_id, event)),
+        ServerPackets::LocationEmit {
+            id,
+            location_id,
+            event,
+        } => ClientPackets::LocationEmit(id, session.location_emit(&location_id, event)),
+        ServerPackets::LocationSubscribe {
+            id,
+            location_id,
+            to,
+        } => ClientPackets::LocationSubscribe(id, session.location_subscribe(&location_id, to)),
+        ServerPackets::LocationUnSubscribe {
+            id,
+            location_id,
+            to,
+        } => ClientPackets::LocationUnSubscribe(id, session.location_unsubscribe(&location_id, to)),
+        ServerPackets::ModuleAcceptedProtocols { id, module_id } => {
+            ClientPackets::ModuleAcceptedProtocols(
+                id,
+                session.module_accepted_protocols(&module_id),
+            )
+        }
+        ServerPackets::ElementGetUrl { id, element_id } => {
+            ClientPackets::ElementGetUrl(id, session.element_get_url(&element_id))
+        }
+        ServerPackets::ElementSetUrl { id, element_id, to } => {
+            ClientPackets::ElementSetUrl(id, session.element_set_url(&element_id, to))
+        }
+        ServerPackets::LoadModuleInfo { id, module_info } => ClientPackets::LoadModuleInfo(
+            id,
+            session.load_module_info(module_info).map(|_ref| _ref.id()),
+        ),
+        ServerPackets::FindModule { id, module_info } => {
+            ClientPackets::FindModule(id, session.find_module(module_info).map(|_ref| _ref.id()))
+        }
+        ServerPackets::ModuleGetUid { id, module_id } => {
+            ClientPackets::ModuleGetUid(id, session.module_get_uid(&module_id))
+        }
+        ServerPackets::ModuleGetVersion { id, module_id } => {
+            ClientPackets::ModuleGetVersion(id, session.module_get_version(&module_id))
+        }
+        ServerPackets::ModuleSupportedVersions { id, module_id } => {
+            ClientPackets::ModuleSupportedVersions(
+                id,
+                session.module_supported_versions(&module_id),
+            )
+        }
+        ServerPackets::ModuleAcceptedExtensions { id, module_id } => {
+            ClientPackets::ModuleAcceptedExtensions(
+                id,
+                session.module_accepted_extensions(&module_id),
+            )
+        }
+        ServerPackets::LoadElementInfo { id, element_info } => ClientPackets::LoadElementInfo(
+            id,
+            session
+                .load_element_info(element_info)
+                .map(|_ref| _ref.id()),
+        ),
+        ServerPackets::LoadLocationInfo { id, location_info } => ClientPackets::LoadLocationInfo(
+            id,
+            session
+                .load_location_info(location_info)
+                .map(|_ref| _ref.id()),
+        ),
+        ServerPackets::GetVersion { id } => ClientPackets::GetVersion(id, session.get_version()),
+        ServerPackets::GetVersionText { id } => ClientPackets::GetVersionText(
+            id,
+            session
+                .get_version_text()
+                .map(|version| format!("{version}, Daemon: {DAEMON_VERSION}")),
+        ),
+        ServerPackets::ModuleGetLocationSettings { id, module_id } => {
+            ClientPackets::ModuleGetLocationSettings(
+                id,
+                session.module_get_location_settings(&module_id),
+            )
+        }
+        ServerPackets::ModuleSetLocationSettings { id, module_id, to } => {
+            ClientPackets::ModuleSetLocationSettings(
+                id,
+                session.module_set_location_settings(&module_id, to),
+            )
+        }
+        ServerPackets::ElementIsError { id, element_id } => {
+            ClientPackets::ElementIsError(id, session.element_is_error(&element_id))
+        }
+        ServerPackets::LocationGetModule { id, location_id } => ClientPackets::LocationGetModule(
+            id,
+            session
+                .location_get_module(&location_id)
+                .map(|option_module_ref| option_module_ref.map(|module_ref| module_ref.id())),
+        ),
+        ServerPackets::LocationSetModule {
+            id,
+            location_id,
+            module_id,
+        } => ClientPackets::LocationSetModule(
+            id,
+            session.location_set_module(&location_id, module_id),
+        ),
+        ServerPackets::LocationGetSettings { id, location_id } => {
+            ClientPackets::LocationGetSettings(id, session.location_get_settings(&location_id))
+        }
+        ServerPackets::LocationSetSettings {
+            id,
+            location_id,
+            to,
+        } => {
+            ClientPackets::LocationSetSettings(id, session.location_set_settings(&location_id, to))
+        }
+        ServerPackets::LocationGetModuleSettings { id, location_id } => {
+            ClientPackets::LocationGetModuleSettings(
+                id,
+                session.location_get_module_settings(&location_id),
+            )
+        }
+        ServerPackets::LocationSetModuleSettings {
+            id,
+            location_id,
+            to,
+        } => ClientPackets::LocationSetModuleSettings(
+            id,
+            session.location_set_module_settings(&location_id, to),
+        ),
+        ServerPackets::LocationGetStatuses { id, location_id } => {
+            ClientPackets::LocationGetStatuses(id, session.location_get_statuses(&location_id))
+        }
+        ServerPackets::LocationSetStatuses {
+            id,
+            location_id,
+            statuses,
+        } => ClientPackets::LocationSetStatuses(
+            id,
+            session.location_set_statuses(&location_id, statuses),
+        ),
+        ServerPackets::LocationGetStatus { id, location_id } => {
+            ClientPackets::LocationGetStatus(id, session.location_get_status(&location_id))
+        }
+        ServerPackets::LocationSetStatus {
+            id,
+            location_id,
+            to,
+        } => ClientPackets::LocationSetStatus(id, session.location_set_status(&location_id, to)),
+        ServerPackets::LocationGetProgress { id, location_id } => {
+            ClientPackets::LocationGetProgress(id, session.location_get_progress(&location_id))
+        }
+        ServerPackets::LocationSetProgress {
+            id,
+            location_id,
+            to,
+        } => {
+            ClientPackets::LocationSetProgress(id, session.location_set_progress(&location_id, to))
+        }
+        ServerPackets::LocationIsEnabled { id, location_id } => {
+            ClientPackets::LocationIsEnabled(id, session.location_is_enabled(&location_id))
+        }
+        ServerPackets::LocationSetEnabled {
+            id,
+            location_id,
+            to,
+        } => ClientPackets::LocationSetEnabled(
+            id,
+            session.location_set_enabled(&location_id, to, None),
+        ),
+        ServerPackets::LocationIsError { id, location_id } => {
+            ClientPackets::LocationIsError(id, session.location_is_error(&location_id))
+        }
+        // handled by Daemon::respond_to_requests and handle_data, blobs are read when decoded
+        ServerPackets::Hello { .. }
+        | ServerPackets::Tick
+        | ServerPackets::ElementWait { .. }
+        | ServerPackets::SetEventFilter { .. }
+        | ServerPackets::EventsSince { .. }
+        | ServerPackets::AckEvent { .. }
+        | ServerPackets::Connect { .. }
+        | ServerPackets::Disconnect
+        | ServerPackets::Ping { .. }
+        | ServerPackets::Cancel { .. }
+        | ServerPackets::DataOpen { .. }
+        | ServerPackets::DataRead { .. }
+        | ServerPackets::DataWrite { .. }
+        | ServerPackets::DataClose { .. }
+        | ServerPackets::Shm { .. } => return None,
+    };
+    Some(packet)
+}
 
-                                    Ok(tmp)
-                                }
-                                Err(err) => Err(err),
-                            },
-                        );
-                        self.inner.send(packet, &addr).await
+/// The location with its elements, and its sub locations until `depth` is reached
+/// Only the `fields` are filled, the others are left empty
+fn tree_snapshot(
+    session: &dyn TSession,
+    location_id: LocationId,
+    depth: u32,
+    fields: &[TreeField],
+) -> Result<LocationNode, SessionError> {
+    let len = session.location_get_elements_len(&location_id)?;
+    let elements = session
+        .location_get_elements(&location_id, 0..len)?
+        .into_iter()
+        .map(|element| {
+            let element_id = element.id();
+            ElementNode {
+                fields: element_fields(session, &element_id, fields),
+                id: element_id,
+            }
+        })
+        .collect();
+
+    let len = session.get_locations_len(&location_id)?;
+    let (locations, truncated) = if depth == 0 {
+        (Vec::new(), len > 0)
+    } else {
+        let mut locations = Vec::with_capacity(len);
+        for location in session.get_locations(&location_id, 0..len)? {
+            locations.push(tree_snapshot(session, location.id(), depth - 1, fields)?);
+        }
+        (locations, false)
+    };
+
+    Ok(LocationNode {
+        fields: location_fields(session, &location_id, fields),
+        id: location_id,
+        elements,
+        locations,
+        truncated,
+    })
+}
+
+/// Responds to the requests of the data streams
+/// Can block, so is called on a blocking thread
+fn handle_data(
+    session: &dyn TSession,
+    data_streams: &std::sync::Mutex<DataStreams>,
+    addr: ClientAddr,
+    packet: ServerPackets,
+) -> Option<ClientPackets> {
+    let packet = match packet {
+        ServerPackets::DataOpen {
+            id,
+            element_id,
+            write,
+        } => ClientPackets::DataOpen(
+            id,
+            open_data(session, data_streams, addr, element_id, write),
+        ),
+        ServerPackets::DataRead {
+            id,
+            stream,
+            offset,
+            len,
+        } => ClientPackets::DataRead(
+            id,
+            with_data_stream(data_streams, addr, stream, |stream| {
+                let len = len.min(DATA_CHUNK) as usize;
+                let mut bytes = vec![0; len];
+                let mut read = 0;
+                stream.data.seek(SeekFrom::Start(offset))?;
+                while read < len {
+                    match stream.data.read(&mut bytes[read..])? {
+                        0 => break,
+                        n => read += n,
                     }
-                    ServerPackets::DestroyLocation { id, location_id } => {
-                        let packet = ClientPackets::DestroyLocation(
-                            id,
-                            match self.session.destroy_location(location_id) {
-                                Ok(_) => Ok(()),
-                                Err(err) => Err(err),
-                            },
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::MoveLocation {
-                        id,
-                        location_id,
-                        to,
-                    } => {
-                        let packet = ClientPackets::MoveLocation(
-                            id,
-                            self.session.move_location(&location_id, &to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::LocationGetPath { id, location_id } => {
-                        let packet = ClientPackets::LocationGetPath(
-                            id,
-                            self.session.location_get_path(&location_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::LocationSetPath {
-                        id,
-                        location_id,
-                        to,
-                    } => {
-                        let packet = ClientPackets::LocationSetPath(
-                            id,
-                            self.session.location_set_path(&location_id, to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::LocationGetShouldSave { id, location_id } => {
-                        let packet = ClientPackets::LocationGetShouldSave(
-                            id,
-                            self.session.location_get_should_save(&location_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::LocationSetShouldSave {
-                        id,
-                        location_id,
-                        to,
-                    } => {
-                        let packet = ClientPackets::LocationSetShouldSave(
-                            id,
-                            self.session.location_set_should_save(&location_id, to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::LocationGetElementsLen { id, location_id } => {
-                        let packet = ClientPackets::LocationGetElementsLen(
-                            id,
-                            self.session.location_get_elements_len(&location_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::LocationGetElements {
-                        id,
-                        location_id,
-                        range,
-                    } => {
-                        let packet = ClientPackets::LocationGetElements(
-                            id,
-                            match self.session.location_get_elements(&location_id, range) {
-                                Ok(ok) => {
-                                    let mut tmp = Vec::with_capacity(ok.len());
+                }
+                bytes.truncate(read);
+                Ok(bytes)
+            }),
+        ),
+        ServerPackets::DataWrite {
+            id,
+            stream,
+            offset,
+            bytes,
+        } => ClientPackets::DataWrite(
+            id,
+            with_data_stream(data_streams, addr, stream, |stream| {
+                if !stream.write {
+                    return Err(std::io::Error::other("Data stream is not writable"));
+                }
+                if bytes.len() > DATA_CHUNK as usize {
+                    return Err(std::io::Error::other("Chunk is too big"));
+                }
+                stream.data.seek(SeekFrom::Start(offset))?;
+                stream.data.write_all(&bytes)?;
+                Ok(bytes.len() as u32)
+            }),
+        ),
+        ServerPackets::DataClose {
+            id,
+            stream,
+            discard,
+        } => ClientPackets::DataClose(id, close_data(session, data_streams, addr, stream, discard)),
+        _ => return None,
+    };
+    Some(packet)
+}
 
-                                    for k in ok {
-                                        tmp.push(k.id())
-                                    }
+/// The stream is removed only when nothing else uses it, so a busy stream can be closed again later
+/// A discarded stream is removed at once, the requests that still use it finish on their own
+fn close_data(
+    session: &dyn TSession,
+    data_streams: &std::sync::Mutex<DataStreams>,
+    addr: ClientAddr,
+    stream: u64,
+    discard: bool,
+) -> Result<(), SessionError> {
+    let stream = {
+        // the streams are shared only while this lock is held, so the stream cannot be taken in between
+        let mut data_streams = data_streams.lock().unwrap();
+        let key = (addr, stream);
+        let Some(stream) = data_streams.open.remove(&key) else {
+            return Err(SessionError::Custom("Unknown data stream".to_string()));
+        };
+        if discard {
+            return Ok(());
+        }
+        match Arc::try_unwrap(stream) {
+            Ok(stream) => stream.into_inner().unwrap(),
+            Err(busy) => {
+                data_streams.open.insert(key, busy);
+                return Err(SessionError::Custom("Data stream is busy".to_string()));
+            }
+        }
+    };
 
-                                    Ok(tmp)
-                                }
-                                Err(err) => Err(err),
-                            },
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::LocationNotify {
-                        id,
-                        location_id,
-                        event,
-                    } => {
-                        let packet = ClientPackets::LocationNotify(
-                            id,
-                            self.session.location_notify(&location_id, event),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::LocationEmit {
-                        id,
-                        location_id,
-                        event,
-                    } => {
-                        let packet = ClientPackets::LocationEmit(
-                            id,
-                            self.session.location_emit(&location_id, event),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::LocationSubscribe {
-                        id,
-                        location_id,
-                        to,
-                    } => {
-                        let packet = ClientPackets::LocationSubscribe(
-                            id,
-                            self.session.location_subscribe(&location_id, to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::LocationUnSubscribe {
-                        id,
-                        location_id,
-                        to,
-                    } => {
-                        let packet = ClientPackets::LocationUnSubscribe(
-                            id,
-                            self.session.location_unsubscribe(&location_id, to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ModuleAcceptedProtocols { id, module_id } => {
-                        let packet = ClientPackets::ModuleAcceptedProtocols(
-                            id,
-                            self.session.module_accepted_protocols(&module_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementGetUrl { id, element_id } => {
-                        let packet = ClientPackets::ElementGetUrl(
-                            id,
-                            self.session.element_get_url(&element_id),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ElementSetUrl { id, element_id, to } => {
-                        let packet = ClientPackets::ElementSetUrl(
-                            id,
-                            self.session.element_set_url(&element_id, to),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::LoadModuleInfo { id, module_info } => {
-                        let packet = ClientPackets::LoadModuleInfo(
-                            id,
-                            self.session
-                                .load_module_info(module_info)
-                                .map(|_ref| _ref.id()),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::FindModule { id, module_info } => {
-                        let packet = ClientPackets::FindModule(
-                            id,
-                            self.session.find_module(module_info).map(|_ref| _ref.id()),
-                        );
-                        self.inner.send(packet, &addr).await
-                    }
-                    ServerPackets::ModuleGetUid { id, module_id } => {
-                        self.inner
-                            .send(
-                                ClientPackets::ModuleGetUid(
-                                    id,
-                                    self.session.module_get_uid(&module_id),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::ModuleGetVersion { id, module_id } => {
-                        self.inner
-                            .send(
-                                ClientPackets::ModuleGetVersion(
-                                    id,
-                                    self.session.module_get_version(&module_id),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::ModuleSupportedVersions { id, module_id } => {
-                        self.inner
-                            .send(
-                                ClientPackets::ModuleSupportedVersions(
-                                    id,
-                                    self.session.module_supported_versions(&module_id),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::ModuleAcceptedExtensions { id, module_id } => {
-                        self.inner
-                            .send(
-                                ClientPackets::ModuleAcceptedExtensions(
-                                    id,
-                                    self.session.module_accepted_extensions(&module_id),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::LoadElementInfo { id, element_info } => {
-                        self.inner
-                            .send(
-                                ClientPackets::LoadElementInfo(
-                                    id,
-                                    self.session
-                                        .load_element_info(element_info)
-                                        .map(|_ref| _ref.id()),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::LoadLocationInfo { id, location_info } => {
-                        self.inner
-                            .send(
-                                ClientPackets::LoadLocationInfo(
-                                    id,
-                                    self.session
-                                        .load_location_info(location_info)
-                                        .map(|_ref| _ref.id()),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::GetVersion { id } => {
-                        self.inner
-                            .send(
-                                ClientPackets::GetVersion(id, self.session.get_version()),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::GetVersionText { id } => {
-                        self.inner
-                            .send(
-                                ClientPackets::GetVersionText(
-                                    id,
-                                    self.session.get_version_text().map(|version| {
-                                        format!("{version}, Daemon: {DAEMON_VERSION}")
-                                    }),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::ModuleGetLocationSettings { id, module_id } => {
-                        self.inner
-                            .send(
-                                ClientPackets::ModuleGetLocationSettings(
-                                    id,
-                                    self.session.module_get_location_settings(&module_id),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::ModuleSetLocationSettings { id, module_id, to } => {
-                        self.inner
-                            .send(
-                                ClientPackets::ModuleSetLocationSettings(
-                                    id,
-                                    self.session.module_set_location_settings(&module_id, to),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::ElementIsError { id, element_id } => {
-                        self.inner
-                            .send(
-                                ClientPackets::ElementIsError(
-                                    id,
-                                    self.session.element_is_error(&element_id),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::LocationGetModule { id, location_id } => {
-                        self.inner
-                            .send(
-                                ClientPackets::LocationGetModule(
-                                    id,
-                                    self.session.location_get_module(&location_id).map(
-                                        |option_module_ref| {
-                                            option_module_ref.map(|module_ref| module_ref.id())
-                                        },
-                                    ),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::LocationSetModule {
-                        id,
-                        location_id,
-                        module_id,
-                    } => {
-                        self.inner
-                            .send(
-                                ClientPackets::LocationSetModule(
-                                    id,
-                                    self.session.location_set_module(&location_id, module_id),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::LocationGetSettings { id, location_id } => {
-                        self.inner
-                            .send(
-                                ClientPackets::LocationGetSettings(
-                                    id,
-                                    self.session.location_get_settings(&location_id),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::LocationSetSettings {
-                        id,
-                        location_id,
-                        to,
-                    } => {
-                        self.inner
-                            .send(
-                                ClientPackets::LocationSetSettings(
-                                    id,
-                                    self.session.location_set_settings(&location_id, to),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::LocationGetModuleSettings { id, location_id } => {
-                        self.inner
-                            .send(
-                                ClientPackets::LocationGetModuleSettings(
-                                    id,
-                                    self.session.location_get_module_settings(&location_id),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::LocationSetModuleSettings {
-                        id,
-                        location_id,
-                        to,
-                    } => {
-                        self.inner
-                            .send(
-                                ClientPackets::LocationSetModuleSettings(
-                                    id,
-                                    self.session.location_set_module_settings(&location_id, to),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::LocationGetStatuses { id, location_id } => {
-                        self.inner
-                            .send(
-                                ClientPackets::LocationGetStatuses(
-                                    id,
-                                    self.session.location_get_statuses(&location_id),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::LocationSetStatuses {
-                        id,
-                        location_id,
-                        statuses,
-                    } => {
-                        self.inner
-                            .send(
-                                ClientPackets::LocationSetStatuses(
-                                    id,
-                                    self.session.location_set_statuses(&location_id, statuses),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::LocationGetStatus { id, location_id } => {
-                        self.inner
-                            .send(
-                                ClientPackets::LocationGetStatus(
-                                    id,
-                                    self.session.location_get_status(&location_id),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::LocationSetStatus {
-                        id,
-                        location_id,
-                        to,
-                    } => {
-                        self.inner
-                            .send(
-                                ClientPackets::LocationSetStatus(
-                                    id,
-                                    self.session.location_set_status(&location_id, to),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::LocationGetProgress { id, location_id } => {
-                        self.inner
-                            .send(
-                                ClientPackets::LocationGetProgress(
-                                    id,
-                                    self.session.location_get_progress(&location_id),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::LocationSetProgress {
-                        id,
-                        location_id,
-                        to,
-                    } => {
-                        self.inner
-                            .send(
-                                ClientPackets::LocationSetProgress(
-                                    id,
-                                    self.session.location_set_progress(&location_id, to),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::LocationIsEnabled { id, location_id } => {
-                        self.inner
-                            .send(
-                                ClientPackets::LocationIsEnabled(
-                                    id,
-                                    self.session.location_is_enabled(&location_id),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::LocationSetEnabled {
-                        id,
-                        location_id,
-                        to,
-                    } => {
-                        self.inner
-                            .send(
-                                ClientPackets::LocationSetEnabled(
-                                    id,
-                                    self.session.location_set_enabled(&location_id, to, None),
-                                ),
-                                &addr,
-                            )
-                            .await
-                    }
-                    ServerPackets::LocationIsError { id, location_id } => {
-                        self.inner
-                            .send(
-                                ClientPackets::LocationIsError(
-                                    id,
-                                    self.session.location_is_error(&location_id),
-                                ),
-                                &addr,
-                            )
-                            .await
+    if stream.write {
+        session.element_set_data(&stream.element_id, stream.data)
+    } else {
+        Ok(())
+    }
+}
+
+/// The stream and how many bytes a data request moves, counted against the `DATA_WINDOW` of the stream
+fn data_window(packet: &ServerPackets) -> Option<(u64, u32)> {
+    match packet {
+        ServerPackets::DataRead { stream, len, .. } => Some((*stream, (*len).min(DATA_CHUNK))),
+        ServerPackets::DataWrite { stream, bytes, .. } => Some((*stream, bytes.len() as u32)),
+        _ => None,
+    }
+}
+
+fn open_data(
+    session: &dyn TSession,
+    data_streams: &std::sync::Mutex<DataStreams>,
+    addr: ClientAddr,
+    element_id: ElementId,
+    write: bool,
+) -> Result<DataStreamInfo, SessionError> {
+    let mut data = session.element_get_data(&element_id)?;
+    let len = data
+        .seek(SeekFrom::End(0))
+        .map_err(|err| SessionError::Custom(err.to_string()))?;
+
+    let mut data_streams = data_streams.lock().unwrap();
+    let open = data_streams
+        .open
+        .keys()
+        .filter(|(client, _)| *client == addr)
+        .count();
+    if open >= MAX_DATA_STREAMS {
+        return Err(SessionError::Custom(format!(
+            "Cannot have more than {MAX_DATA_STREAMS} data streams open"
+        )));
+    }
+
+    let stream = data_streams.generator;
+    data_streams.generator += 1;
+    data_streams.open.insert(
+        (addr, stream),
+        Arc::new(std::sync::Mutex::new(DataStream {
+            element_id,
+            data,
+            write,
+        })),
+    );
+
+    Ok(DataStreamInfo {
+        stream,
+        len,
+        chunk: DATA_CHUNK,
+        window: DATA_WINDOW,
+    })
+}
+
+/// Only the client that opened the stream can use it
+fn with_data_stream<T>(
+    data_streams: &std::sync::Mutex<DataStreams>,
+    addr: ClientAddr,
+    stream: u64,
+    f: impl FnOnce(&mut DataStream) -> Result<T, std::io::Error>,
+) -> Result<T, SessionError> {
+    let Some(stream) = data_streams
+        .lock()
+        .unwrap()
+        .open
+        .get(&(addr, stream))
+        .cloned()
+    else {
+        return Err(SessionError::Custom("Unknown data stream".to_string()));
+    };
+
+    let mut stream = stream.lock().unwrap();
+    f(&mut stream).map_err(|err| SessionError::Custom(err.to_string()))
+}
+
+fn query_elements(
+    session: &dyn TSession,
+    query: &ElementQuery,
+    cursor: Option<&QueryCursor>,
+    limit: u32,
+) -> Result<QueryPage, SessionError> {
+    let root = match &query.root {
+        Some(root) => root.clone(),
+        None => session.get_default_location()?.id(),
+    };
+
+    let mut matched = Vec::new();
+    collect_elements(session, root, query, &mut matched)?;
+
+    let limit = if limit == 0 || limit > MAX_QUERY_PAGE {
+        MAX_QUERY_PAGE
+    } else {
+        limit
+    };
+    Ok(query::page(query, matched, cursor, limit as usize))
+}
+
+/// The elements of the location and of its sub locations that match the query
+fn collect_elements(
+    session: &dyn TSession,
+    location_id: LocationId,
+    query: &ElementQuery,
+    matched: &mut Vec<(ElementId, SortKey)>,
+) -> Result<(), SessionError> {
+    let len = session.location_get_elements_len(&location_id)?;
+    for element in session.location_get_elements(&location_id, 0..len)? {
+        let element_id = element.id();
+        if let Some(key) = element_matches(session, &element_id, query) {
+            matched.push((element_id, key));
+        }
+    }
+
+    let len = session.get_locations_len(&location_id)?;
+    for location in session.get_locations(&location_id, 0..len)? {
+        collect_elements(session, location.id(), query, matched)?;
+    }
+    Ok(())
+}
+
+/// The sort key of the element if it matches the query
+/// Elements whose filtered fields cannot be read don't match
+fn element_matches(
+    session: &dyn TSession,
+    element_id: &ElementId,
+    query: &ElementQuery,
+) -> Option<SortKey> {
+    if let Some(status) = query.status {
+        if session.element_get_status(element_id).ok()? != status {
+            return None;
+        }
+    }
+    if let Some(enabled) = query.enabled {
+        if session.element_get_enabled(element_id).ok()? != enabled {
+            return None;
+        }
+    }
+    if let Some(is_error) = query.is_error {
+        if session.element_is_error(element_id).ok()? != is_error {
+            return None;
+        }
+    }
+    if let Some(module_id) = query.module {
+        if session.element_get_module(element_id).ok()??.id() != module_id {
+            return None;
+        }
+    }
+    if let Some(url) = &query.url {
+        if !session
+            .element_get_url(element_id)
+            .ok()??
+            .contains(url.as_str())
+        {
+            return None;
+        }
+    }
+
+    let mut name = None;
+    if let Some(pattern) = &query.name {
+        let element_name = session.element_get_name(element_id).ok()?;
+        if !matches_pattern(pattern, &element_name) {
+            return None;
+        }
+        name = Some(element_name);
+    }
+
+    Some(match query.sort {
+        ElementSort::Tree => SortKey::Tree,
+        ElementSort::Name => SortKey::Name(match name {
+            Some(name) => name,
+            None => session.element_get_name(element_id).unwrap_or_default(),
+        }),
+        ElementSort::Progress => {
+            SortKey::Progress(session.element_get_progress(element_id).unwrap_or_default())
+        }
+        ElementSort::Status => {
+            SortKey::Status(session.element_get_status(element_id).unwrap_or_default())
+        }
+    })
+}
+
+/// Changes of the same kind for the same element are coalesced for `interval`, the last one wins
+/// Other events flush the coalesced ones first, so the order is kept
+async fn send_events(
+    inner: Arc<Mutex<DaemonInner>>,
+    mut events: UnboundedReceiver<SessionEvent>,
+    interval: Duration,
+) {
+    let mut coalescer = Coalescer::default();
+    let mut flush_at = Instant::now();
+
+    loop {
+        let event = if coalescer.is_empty() {
+            events.recv().await
+        } else {
+            match tokio::time::timeout_at(flush_at, events.recv()).await {
+                Ok(event) => event,
+                Err(_) => {
+                    for event in coalescer.flush() {
+                        publish_event(&inner, event).await;
+                    }
+                    continue;
+                }
+            }
+        };
+        let Some(event) = event else {
+            break;
+        };
+
+        if interval.is_zero() {
+            publish_event(&inner, event).await;
+            continue;
+        }
+
+        let was_empty = coalescer.is_empty();
+        for event in coalescer.push(event) {
+            publish_event(&inner, event).await;
+        }
+        if was_empty && !coalescer.is_empty() {
+            flush_at = Instant::now() + interval;
+        }
+    }
+}
+
+/// The events of `history` after `seq` that pass the filter
+/// `event_seq` is the sequence number of the last event
+fn replay(
+    history: &VecDeque<(u64, SessionEvent)>,
+    event_seq: u64,
+    filter: Option<&EventFilter>,
+    seq: u64,
+) -> EventReplay {
+    let oldest = history
+        .front()
+        .map(|(seq, _)| *seq)
+        .unwrap_or(event_seq + 1);
+    // the events after seq were dropped from the history, or seq is from another daemon
+    if seq + 1 < oldest || seq > event_seq {
+        return EventReplay::ResyncRequired(event_seq);
+    }
+
+    EventReplay::Events(
+        history
+            .iter()
+            .filter(|(event_seq, event)| {
+                *event_seq > seq && filter.map(|filter| filter.matches(event)).unwrap_or(true)
+            })
+            .cloned()
+            .collect(),
+    )
+}
+
+async fn publish_event(inner: &Arc<Mutex<DaemonInner>>, event: SessionEvent) {
+    log::info!("{event:?}");
+    let seq = inner.record(event.clone()).await;
+    for (client, last) in inner.subscribers(seq, &event).await {
+        let packet = ClientPackets::NewSessionEvent(seq, last, event.clone());
+        inner.track_event(&client, packet.clone()).await;
+        inner.send(packet, &client).await;
+    }
+}
+
+async fn recv_udp(socket: Arc<UdpSocket>, incoming: UnboundedSender<Incoming>) {
+    let mut buffer = [0; MAX_DATAGRAM];
+    let mut reassembler = Reassembler::default();
+
+    loop {
+        match tokio::time::timeout(REASSEMBLY_TIMEOUT, socket.recv_from(&mut buffer)).await {
+            Ok(Ok((len, from))) => {
+                let Some(frame) = Frame::decode(&buffer[0..len]) else {
+                    log::warn!("Dropped invalid frame from: {from}");
+                    continue;
+                };
+
+                if let Some((request, message)) = reassembler.push(from, frame) {
+                    if incoming
+                        .send((ClientAddr::Udp(from), request, message))
+                        .is_err()
+                    {
+                        return;
                     }
                 }
             }
+            Ok(Err(err)) => log::error!("Udp: {err}"),
+            Err(_) => {}
+        }
+
+        for incomplete in reassembler.gc() {
+            log::error!(
+                "Request: {} from: {} arrived incomplete, {} of {} chunks",
+                incomplete.request,
+                incomplete.from,
+                incomplete.received,
+                incomplete.count
+            );
+        }
+    }
+}
+
+async fn accept_tcp(
+    listener: TcpListener,
+    inner: Arc<Mutex<DaemonInner>>,
+    incoming: UnboundedSender<Incoming>,
+) {
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(ok) => ok,
+            Err(err) => {
+                log::error!("Tcp: {err}");
+                continue;
+            }
+        };
+        let _ = stream.set_nodelay(true);
+
+        let addr = ClientAddr::Tcp(addr);
+        let (reader, writer) = stream.into_split();
+        inner.add_stream(addr, writer).await;
+        tokio::spawn(read_stream(addr, reader, inner.clone(), incoming.clone()));
+    }
+}
+
+#[cfg(unix)]
+fn bind_unix(path: &std::path::Path) -> Result<UnixListener, std::io::Error> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    if path.exists() {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(std::io::ErrorKind::AddrInUse.into());
+        }
+        // left by a daemon that was not stopped cleanly
+        std::fs::remove_file(path)?;
+    }
+
+    // bound inside a private directory, so the socket is never reachable with the umask permissions
+    let private = path.with_file_name(format!(".muzzman-daemon-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&private);
+    std::fs::DirBuilder::new().mode(0o700).create(&private)?;
+
+    let bound = (|| {
+        let tmp_path = private.join("socket");
+        let listener = UnixListener::bind(&tmp_path)?;
+        std::fs::set_permissions(&tmp_path, std::fs::Permissions::from_mode(0o600))?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(listener)
+    })();
+    let _ = std::fs::remove_dir_all(&private);
+    bound
+}
+
+#[cfg(unix)]
+async fn accept_unix(
+    listener: UnixListener,
+    inner: Arc<Mutex<DaemonInner>>,
+    incoming: UnboundedSender<Incoming>,
+) {
+    let mut generator = 1;
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                log::error!("Unix: {err}");
+                continue;
+            }
+        };
+
+        let addr = ClientAddr::Unix(generator);
+        generator += 1;
+
+        let peer = shm::same_user_peer(&stream);
+        let (reader, writer) = stream.into_split();
+        inner.add_stream(addr, writer).await;
+        if let Some(pid) = peer {
+            inner.add_shm_peer(addr, pid).await;
+        }
+        tokio::spawn(read_stream(addr, reader, inner.clone(), incoming.clone()));
+    }
+}
+
+async fn read_stream(
+    addr: ClientAddr,
+    mut reader: impl AsyncRead + Unpin,
+    inner: Arc<Mutex<DaemonInner>>,
+    incoming: UnboundedSender<Incoming>,
+) {
+    while let Ok(len) = reader.read_u32_le().await {
+        let len = len as usize;
+        if len > MAX_MESSAGE {
+            log::error!("Message too big: {len} from: {addr}");
+            break;
+        }
+
+        // grows as the bytes arrive, a length alone doesn't make the daemon allocate
+        let mut message = Vec::with_capacity(len.min(MAX_DATAGRAM));
+        match (&mut reader)
+            .take(len as u64)
+            .read_to_end(&mut message)
+            .await
+        {
+            Ok(read) if read == len => {}
+            _ => break,
+        }
+
+        if incoming.send((addr, 0, message)).is_err() {
+            break;
+        }
+    }
+
+    log::trace!("Disconnected: {addr}");
+    inner.remove_client(&addr).await;
+}
+
+async fn write_stream(
+    mut writer: impl AsyncWrite + Unpin,
+    mut outgoing: UnboundedReceiver<(u128, Vec<u8>)>,
+) {
+    // the request leads the message, so the client knows whose response is arriving
+    while let Some((request, message)) = outgoing.recv().await {
+        let mut header = ((REQUEST_HEADER + message.len()) as u32)
+            .to_le_bytes()
+            .to_vec();
+        header.extend(request.to_le_bytes());
+        if writer.write_all(&header).await.is_err() || writer.write_all(&message).await.is_err() {
+            break;
         }
     }
 }
 
+/// Requests by client, malformed requests with if the client was banned, errors of unreadable requests
+type Decoded = (
+    Vec<(ClientAddr, Vec<ServerPackets>)>,
+    Vec<(ClientAddr, u128, bool)>,
+    Vec<(ClientAddr, ClientPackets)>,
+);
+
 #[async_trait]
 trait TDaemonInner {
-    async fn send(&self, packet: ClientPackets, to: &SocketAddr);
-    async fn recv(&self) -> Vec<(SocketAddr, Vec<ServerPackets>)>;
+    async fn send(&self, packet: ClientPackets, to: &ClientAddr);
+    /// Returns the packets, the malformed requests with if the client was banned for them
+    /// and the errors of the requests that are valid but cannot be read
+    async fn decode(&self, messages: Vec<Incoming>) -> Decoded;
     // garbage collect clients
     async fn gc_clients(&self);
-    async fn clients(&self) -> Vec<SocketAddr>;
-}
+    async fn clients(&self) -> Vec<ClientAddr>;
+    async fn add_stream(&self, addr: ClientAddr, writer: impl AsyncWrite + Unpin + Send + 'static);
+    /// The unix client runs as the same user, with this pid
+    async fn add_shm_peer(&self, addr: ClientAddr, pid: u32);
+    async fn is_shm_peer(&self, addr: &ClientAddr) -> bool;
+    /// Releases every state of the client
+    async fn remove_client(&self, addr: &ClientAddr);
+    /// Returns the granted lease
+    async fn connect(&self, addr: ClientAddr, lease: Duration) -> Duration;
+    async fn broadcast(&self, packet: ClientPackets, except: &ClientAddr);
+    async fn greet(&self, addr: ClientAddr, info: ClientInfo);
+    async fn is_greeted(&self, addr: &ClientAddr) -> bool;
+    async fn set_filter(&self, addr: ClientAddr, filter: EventFilter);
+    /// Gives the event a sequence number and keeps it in the history
+    async fn record(&self, event: SessionEvent) -> u64;
+    async fn event_seq(&self) -> u64;
+    /// Clients that want the event, with the sequence number of the last event that they received
+    async fn subscribers(&self, seq: u64, event: &SessionEvent) -> Vec<(ClientAddr, u64)>;
+    async fn events_since(&self, addr: &ClientAddr, seq: u64) -> EventReplay;
+    /// Keeps the event until the client acks it, only for datagram clients that can ack
+    async fn track_event(&self, addr: &ClientAddr, packet: ClientPackets);
+    /// The client received every event until `seq`
+    async fn ack_event(&self, addr: &ClientAddr, seq: u64);
+    /// Sends again the events that were not acked in time, drops the clients that don't ack
+    async fn retransmit_events(&self);
 
-impl DaemonInner {
-    fn get_inner(&mut self) -> (&mut [u8], &UdpSocket) {
-        (&mut self.buffer, &self.socket)
-    }
+    /// Marks the request as running, unless it was seen before
+    /// Only the responses of the requests to `remember` answer their retransmissions
+    async fn start_request(&self, addr: ClientAddr, id: u128, remember: bool) -> RequestState;
+    /// Remembers the response, `None` forgets the request
+    /// Returns the response that should be sent, `None` if the request was cancelled
+    async fn finish_request(
+        &self,
+        addr: &ClientAddr,
+        id: u128,
+        response: Option<ClientPackets>,
+    ) -> Option<ClientPackets>;
+    /// Returns `false` if the request is not running
+    async fn cancel_request(&self, addr: &ClientAddr, id: u128) -> bool;
+    async fn is_cancelled(&self, addr: &ClientAddr, id: u128) -> bool;
+
+    /// Returns `false` if the request is already parked
+    async fn park_waiter(&self, waiter: Waiter) -> bool;
+    async fn waiters(&self) -> Vec<Waiter>;
+    /// Keeps the waiters of the element when its id changes
+    async fn follow_waiters(&self, last: &ElementId, new: &ElementId);
+    /// Returns `false` if the waiter is not parked anymore
+    async fn remove_waiter(&self, addr: &ClientAddr, id: u128) -> bool;
 }
 
 #[async_trait]
 impl TDaemonInner for Arc<Mutex<DaemonInner>> {
-    async fn send(&self, packet: ClientPackets, to: &SocketAddr) {
+    async fn send(&self, packet: ClientPackets, to: &ClientAddr) {
         log::trace!("Send: {}, Packet: {:?}", to, packet);
         let mut bytes = packet.to_bytes();
         bytes.reverse();
 
-        let inner = self.lock().await;
-        let socket = inner.socket.clone();
+        // writing the blob can take long, the daemon is not locked meanwhile
+        if bytes.len() > shm::SHM_THRESHOLD && self.lock().await.shm_peer(to).is_some() {
+            let id = packet.id();
+            bytes =
+                match tokio::task::spawn_blocking(move || shm::offload_response(id, bytes)).await {
+                    Ok(bytes) => bytes,
+                    Err(err) => {
+                        log::error!("Cannot send the response for: {id} to: {to}: {err}");
+                        return;
+                    }
+                };
+        }
+
+        let mut inner = self.lock().await;
+        match to {
+            ClientAddr::Udp(addr) => {
+                let Some(socket) = inner.socket.clone() else {
+                    return;
+                };
+                let message = inner.message_generator;
+                inner.message_generator += 1;
+                drop(inner);
 
-        for chunk in bytes.chunks(4096) {
-            let _ = socket.send_to(chunk, to).await;
+                for datagram in Frame::split(message, packet.id(), &bytes) {
+                    let _ = socket.send_to(&datagram, addr).await;
+                }
+            }
+            _ => {
+                if let Some(stream) = inner.streams.get(to) {
+                    let _ = stream.send((packet.id(), bytes));
+                }
+            }
         }
     }
 
-    async fn recv(&self) -> Vec<(SocketAddr, Vec<ServerPackets>)> {
+    async fn decode(&self, messages: Vec<Incoming>) -> Decoded {
+        let mut master_buffer: HashMap<ClientAddr, Vec<ServerPackets>> = HashMap::new();
+        let mut malformed = Vec::new();
+        let mut refused = Vec::new();
+
+        let (banned, shm_peers) = {
+            let inner = self.lock().await;
+            let banned = messages
+                .iter()
+                .map(|(from, _, _)| *from)
+                .filter(|from| {
+                    inner
+                        .offenses
+                        .get(from)
+                        .map(|offenses| offenses.is_banned())
+                        .unwrap_or(false)
+                })
+                .collect::<HashSet<ClientAddr>>();
+            let shm_peers = messages
+                .iter()
+                .filter_map(|(from, _, _)| Some((*from, inner.shm_peer(from)?)))
+                .collect::<HashMap<ClientAddr, u32>>();
+            (banned, shm_peers)
+        };
+
+        // reading the blobs can take long, the daemon is not locked meanwhile
+        let mut packets = Vec::with_capacity(messages.len());
+        for (from, request, mut message) in messages {
+            if banned.contains(&from) {
+                log::trace!("Ignored message from banned: {from}");
+                continue;
+            }
+
+            let packet = match ServerPackets::from_bytes(&mut message)
+                .filter(|_| message.is_empty())
+            {
+                Some(ServerPackets::Shm { id, blob }) => match shm_peers.get(&from).copied() {
+                    Some(pid) => {
+                        match tokio::task::spawn_blocking(move || shm::take_request(&blob, pid))
+                            .await
+                            .unwrap_or_else(|err| Err(std::io::Error::other(err)))
+                        {
+                            Ok(ServerPackets::Shm { .. }) => None,
+                            Ok(packet) => Some(packet),
+                            // the blob can be lost without the client being at fault
+                            Err(err) => {
+                                log::warn!("Cannot read request: {id} from: {from}: {err}");
+                                let err =
+                                    SessionError::Custom(format!("Cannot read the blob: {err}"));
+                                refused.push((from, ClientPackets::Error(id, err)));
+                                continue;
+                            }
+                        }
+                    }
+                    // only the clients that negotiated it can make the daemon read blobs
+                    None => None,
+                },
+                packet => packet,
+            };
+            packets.push((from, request, packet));
+        }
+
         let mut inner = self.lock().await;
+        for (from, request, packet) in packets {
+            match packet {
+                Some(packet) => {
+                    log::trace!("From: {}, Packet: {:?}", from, packet);
+                    master_buffer.entry(from).or_default().push(packet);
+                }
+                _ => {
+                    log::warn!("Malformed packet for request: {request} from: {from}");
+                    let offenses = inner.offenses.entry(from).or_insert(Offenses {
+                        count: 0,
+                        first: SystemTime::now(),
+                        banned: None,
+                    });
 
-        let mut master_buffer: HashMap<SocketAddr, Vec<u8>> = HashMap::new();
-        let (buffer, socket) = inner.get_inner();
+                    if offenses.first.elapsed().unwrap_or_default() > OFFENSE_WINDOW {
+                        offenses.count = 0;
+                        offenses.first = SystemTime::now();
+                    }
+                    offenses.count += 1;
 
-        while let Ok((len, from)) = socket.try_recv_from(buffer) {
-            let buf = master_buffer.get_mut(&from);
-            match buf {
-                Some(buf) => buf.append(&mut buffer[0..len].to_vec()),
-                None => {
-                    master_buffer.insert(from, buffer[0..len].to_vec());
+                    let banned = offenses.count >= MAX_OFFENSES;
+                    if banned {
+                        log::warn!("Ignoring: {from} for {}s", BAN_TIME.as_secs());
+                        offenses.banned = Some(SystemTime::now());
+                        offenses.count = 0;
+                    }
+                    malformed.push((from, request, banned));
                 }
             }
         }
 
-        master_buffer
+        inner
+            .offenses
+            .retain(|_, offenses| offenses.is_banned() || offenses.count > 0);
+
+        let requests = master_buffer
             .drain()
-            .map(|(from, mut buffer)| {
-                let mut packets = Vec::new();
-
-                let mut finded = false;
-                for client in inner.clients.iter_mut() {
-                    if client.1 == from {
-                        client.0 = SystemTime::now();
-                        finded = true;
-                        break;
-                    }
+            .filter_map(|(from, packets)| {
+                // the stream was closed while its messages were queued
+                if from.is_stream() && !inner.streams.contains_key(&from) {
+                    log::trace!("Ignored packets from removed: {from}");
+                    return None;
                 }
 
-                if !finded {
-                    inner.clients.push((SystemTime::now(), from))
+                // a client is registered by its Hello, here it's only kept alive
+                if let Some(client) = inner.clients.iter_mut().find(|client| client.1 == from) {
+                    client.0 = SystemTime::now();
                 }
-                while !buffer.is_empty() {
-                    let Some(packet) = ServerPackets::from_bytes(&mut buffer) else{continue};
-                    log::trace!("From: {}, Packet: {:?}", from, packet);
-                    packets.push(packet)
-                }
-                (from, packets)
+                Some((from, packets))
             })
-            .collect()
+            .collect();
+
+        (requests, malformed, refused)
     }
 
     async fn gc_clients(&self) {
-        self.lock()
-            .await
-            .clients
-            .retain(|(time, _)| time.elapsed().unwrap() < CLIENT_TIMEOUT);
+        let mut expired = Vec::new();
+        {
+            let mut inner = self.lock().await;
+            let inner = &mut *inner;
+            let leases = &inner.leases;
+
+            inner.clients.retain(|(time, addr)| {
+                let elapsed = time.elapsed().unwrap_or_default();
+                match leases.get(addr) {
+                    // stream clients are present until their connection is closed
+                    _ if addr.is_stream() => true,
+                    Some(lease) if elapsed >= *lease => {
+                        expired.push(*addr);
+                        false
+                    }
+                    Some(_) => true,
+                    None if elapsed >= CLIENT_TIMEOUT => {
+                        expired.push(*addr);
+                        false
+                    }
+                    None => true,
+                }
+            });
+        }
+
+        for addr in expired {
+            log::info!("Client: {addr} expired");
+            self.remove_client(&addr).await;
+        }
     }
 
-    async fn clients(&self) -> Vec<SocketAddr> {
+    async fn clients(&self) -> Vec<ClientAddr> {
         self.gc_clients().await;
 
         log::trace!("Clients: {:?}", self.lock().await.clients);
@@ -1230,6 +2221,450 @@ impl TDaemonInner for Arc<Mutex<DaemonInner>> {
             .clients
             .iter()
             .map(|(_, addr)| *addr)
-            .collect::<Vec<SocketAddr>>()
+            .collect::<Vec<ClientAddr>>()
+    }
+
+    async fn add_stream(&self, addr: ClientAddr, writer: impl AsyncWrite + Unpin + Send + 'static) {
+        let (sender, outgoing) = unbounded_channel();
+        tokio::spawn(write_stream(writer, outgoing));
+
+        let mut inner = self.lock().await;
+        inner.streams.insert(addr, sender);
+    }
+
+    async fn remove_client(&self, addr: &ClientAddr) {
+        let (info, connected) = {
+            let mut inner = self.lock().await;
+            inner.streams.remove(addr);
+            inner.filters.remove(addr);
+            inner.last_sent.remove(addr);
+            inner.unacked.remove(addr);
+            inner.replies.remove(addr);
+            inner.running.retain(|(client, _), _| client != addr);
+            inner.waiters.retain(|waiter| waiter.addr != *addr);
+            inner.shm_peers.remove(addr);
+            // not closed, so what was written is not set
+            inner
+                .data_streams
+                .lock()
+                .unwrap()
+                .open
+                .retain(|(client, _), _| client != addr);
+            inner
+                .data_streams
+                .lock()
+                .unwrap()
+                .in_flight
+                .retain(|(client, _), _| client != addr);
+            inner.clients.retain(|(_, client)| client != addr);
+            (
+                inner.handshakes.remove(addr),
+                inner.leases.remove(addr).is_some(),
+            )
+        };
+
+        if let (Some(info), true) = (info, connected) {
+            log::info!("Client: {} disconnected from: {addr}", info.name);
+            let event = DaemonEvent::ClientLeft {
+                name: info.name,
+                addr: addr.to_string(),
+            };
+            self.broadcast(ClientPackets::DaemonEvent(event), addr)
+                .await;
+        }
+    }
+
+    async fn connect(&self, addr: ClientAddr, lease: Duration) -> Duration {
+        let lease = lease.clamp(MIN_LEASE, MAX_LEASE);
+        let name = {
+            let mut inner = self.lock().await;
+            inner.leases.insert(addr, lease);
+            inner
+                .handshakes
+                .get(&addr)
+                .map(|info| info.name.clone())
+                .unwrap_or_default()
+        };
+
+        let event = DaemonEvent::ClientJoined {
+            name,
+            addr: addr.to_string(),
+        };
+        self.broadcast(ClientPackets::DaemonEvent(event), &addr)
+            .await;
+        lease
+    }
+
+    async fn broadcast(&self, packet: ClientPackets, except: &ClientAddr) {
+        let clients = self
+            .lock()
+            .await
+            .clients
+            .iter()
+            .map(|(_, addr)| *addr)
+            .filter(|addr| addr != except)
+            .collect::<Vec<ClientAddr>>();
+
+        for client in clients {
+            self.send(packet.clone(), &client).await;
+        }
+    }
+
+    async fn greet(&self, addr: ClientAddr, info: ClientInfo) {
+        let mut inner = self.lock().await;
+        inner.handshakes.insert(addr, info);
+        match inner.clients.iter_mut().find(|client| client.1 == addr) {
+            Some(client) => client.0 = SystemTime::now(),
+            None => inner.clients.push((SystemTime::now(), addr)),
+        }
+    }
+
+    async fn add_shm_peer(&self, addr: ClientAddr, pid: u32) {
+        self.lock().await.shm_peers.insert(addr, pid);
+    }
+
+    async fn is_shm_peer(&self, addr: &ClientAddr) -> bool {
+        self.lock().await.shm_peers.contains_key(addr)
+    }
+
+    async fn is_greeted(&self, addr: &ClientAddr) -> bool {
+        self.lock().await.handshakes.contains_key(addr)
+    }
+
+    async fn set_filter(&self, addr: ClientAddr, filter: EventFilter) {
+        self.lock().await.filters.insert(addr, filter);
+    }
+
+    async fn record(&self, event: SessionEvent) -> u64 {
+        let mut inner = self.lock().await;
+        inner.event_seq += 1;
+        let seq = inner.event_seq;
+
+        if inner.history.len() == EVENT_HISTORY {
+            inner.history.pop_front();
+        }
+        inner.history.push_back((seq, event));
+        seq
+    }
+
+    async fn event_seq(&self) -> u64 {
+        self.lock().await.event_seq
+    }
+
+    async fn subscribers(&self, seq: u64, event: &SessionEvent) -> Vec<(ClientAddr, u64)> {
+        let clients = self.clients().await;
+        let mut inner = self.lock().await;
+
+        let subscribers = clients
+            .into_iter()
+            .filter(|client| {
+                inner
+                    .filters
+                    .get(client)
+                    .map(|filter| filter.matches(event))
+                    .unwrap_or(true)
+            })
+            .map(|client| (client, inner.last_sent.get(&client).copied().unwrap_or(0)))
+            .collect::<Vec<_>>();
+
+        for (client, _) in subscribers.iter() {
+            inner.last_sent.insert(*client, seq);
+        }
+
+        for filter in inner.filters.values_mut() {
+            filter.follow(event);
+        }
+
+        subscribers
+    }
+
+    async fn track_event(&self, addr: &ClientAddr, packet: ClientPackets) {
+        let mut inner = self.lock().await;
+        let acks = !addr.is_stream()
+            && inner
+                .handshakes
+                .get(addr)
+                .map(|info| info.features.iter().any(|feature| feature == "event-ack"))
+                .unwrap_or(false);
+
+        if acks {
+            let unacked = inner.unacked.entry(*addr).or_insert_with(Unacked::new);
+            if unacked.events.is_empty() {
+                unacked.retry = SystemTime::now() + unacked.backoff;
+            }
+            unacked.events.push_back(packet);
+        }
+    }
+
+    async fn ack_event(&self, addr: &ClientAddr, seq: u64) {
+        let mut inner = self.lock().await;
+        let Some(unacked) = inner.unacked.get_mut(addr) else {
+            return;
+        };
+
+        unacked.events.retain(|packet| match packet {
+            ClientPackets::NewSessionEvent(event_seq, ..) => *event_seq > seq,
+            _ => false,
+        });
+        unacked.failures = 0;
+        unacked.backoff = ACK_TIMEOUT;
+        unacked.retry = SystemTime::now() + ACK_TIMEOUT;
+    }
+
+    async fn retransmit_events(&self) {
+        let mut retransmit = Vec::new();
+        let mut dropped = Vec::new();
+
+        {
+            let mut inner = self.lock().await;
+            let max_failures = inner.max_event_failures;
+            let now = SystemTime::now();
+
+            for (addr, unacked) in inner.unacked.iter_mut() {
+                if unacked.events.is_empty() || unacked.retry > now {
+                    continue;
+                }
+
+                if unacked.failures >= max_failures {
+                    dropped.push(*addr);
+                    continue;
+                }
+
+                unacked.failures += 1;
+                unacked.backoff = (unacked.backoff * 2).min(MAX_ACK_TIMEOUT);
+                unacked.retry = now + unacked.backoff;
+                for packet in unacked.events.iter() {
+                    retransmit.push((*addr, packet.clone()));
+                }
+            }
+        }
+
+        for addr in dropped {
+            log::warn!("Client: {addr} does not ack events, dropped");
+            self.remove_client(&addr).await;
+        }
+
+        for (addr, packet) in retransmit {
+            self.send(packet, &addr).await;
+        }
+    }
+
+    async fn start_request(&self, addr: ClientAddr, id: u128, remember: bool) -> RequestState {
+        // requests without id are not responded
+        if id == 0 {
+            return RequestState::New;
+        }
+
+        let mut inner = self.lock().await;
+        // streams don't retransmit
+        if remember && !addr.is_stream() {
+            let replies = inner.replies.entry(addr).or_default();
+            while replies.front().is_some_and(|reply| {
+                reply.response.is_some() && reply.time.elapsed().unwrap_or_default() > REPLY_EXPIRY
+            }) {
+                replies.pop_front();
+            }
+
+            if let Some(reply) = replies.iter().find(|reply| reply.id == id) {
+                return match &reply.response {
+                    Some(response) => RequestState::Done(response.clone()),
+                    None => RequestState::Running,
+                };
+            }
+
+            if replies.len() == REPLY_CACHE {
+                replies.pop_front();
+            }
+            replies.push_back(CachedReply {
+                id,
+                response: None,
+                size: 0,
+                time: SystemTime::now(),
+            });
+        }
+
+        inner.running.insert((addr, id), false);
+        RequestState::New
+    }
+
+    async fn finish_request(
+        &self,
+        addr: &ClientAddr,
+        id: u128,
+        response: Option<ClientPackets>,
+    ) -> Option<ClientPackets> {
+        let mut inner = self.lock().await;
+        let cancelled = inner.running.remove(&(*addr, id)).unwrap_or(false);
+        let response = response.filter(|_| !cancelled);
+
+        if let Some(replies) = inner.replies.get_mut(addr) {
+            match &response {
+                Some(response) => {
+                    if let Some(reply) = replies.iter_mut().find(|reply| reply.id == id) {
+                        reply.size = response.size();
+                        reply.response = Some(response.clone());
+                        reply.time = SystemTime::now();
+                    }
+                    // the oldest are forgotten, the last one is kept even if is too big
+                    let mut size = replies.iter().map(|reply| reply.size).sum::<usize>();
+                    while size > REPLY_CACHE_BYTES && replies.len() > 1 {
+                        if let Some(reply) = replies.pop_front() {
+                            size -= reply.size;
+                        }
+                    }
+                }
+                None => replies.retain(|reply| reply.id != id),
+            }
+        }
+        response
+    }
+
+    async fn cancel_request(&self, addr: &ClientAddr, id: u128) -> bool {
+        match self.lock().await.running.get_mut(&(*addr, id)) {
+            Some(cancelled) => {
+                *cancelled = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    async fn is_cancelled(&self, addr: &ClientAddr, id: u128) -> bool {
+        self.lock()
+            .await
+            .running
+            .get(&(*addr, id))
+            .copied()
+            .unwrap_or(false)
+    }
+
+    async fn park_waiter(&self, waiter: Waiter) -> bool {
+        let mut inner = self.lock().await;
+        if inner
+            .waiters
+            .iter()
+            .any(|parked| parked.addr == waiter.addr && parked.id == waiter.id)
+        {
+            return false;
+        }
+        inner.waiters.push(waiter);
+        true
+    }
+
+    async fn waiters(&self) -> Vec<Waiter> {
+        self.lock().await.waiters.clone()
+    }
+
+    async fn follow_waiters(&self, last: &ElementId, new: &ElementId) {
+        for waiter in self.lock().await.waiters.iter_mut() {
+            if waiter.element_id == *last {
+                waiter.element_id = new.clone();
+            }
+        }
+    }
+
+    async fn remove_waiter(&self, addr: &ClientAddr, id: u128) -> bool {
+        let mut inner = self.lock().await;
+        let waiters = inner.waiters.len();
+        inner
+            .waiters
+            .retain(|waiter| waiter.addr != *addr || waiter.id != id);
+        waiters != inner.waiters.len()
+    }
+
+    async fn events_since(&self, addr: &ClientAddr, seq: u64) -> EventReplay {
+        let inner = self.lock().await;
+        replay(
+            &inner.history,
+            inner.event_seq,
+            inner.filters.get(addr),
+            seq,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(uid: usize) -> ElementId {
+        ElementId {
+            uid: uid as _,
+            location_id: LocationId(Vec::new()),
+        }
+    }
+
+    /// Events from `first` to `last`, after the older ones were dropped
+    fn history(first: u64, last: u64) -> VecDeque<(u64, SessionEvent)> {
+        (first..=last)
+            .map(|seq| (seq, SessionEvent::NewElement(element(seq as usize))))
+            .collect()
+    }
+
+    fn seqs(replay: EventReplay) -> Vec<u64> {
+        match replay {
+            EventReplay::Events(events) => events.into_iter().map(|(seq, _)| seq).collect(),
+            EventReplay::ResyncRequired(_) => panic!("Expected events"),
+        }
+    }
+
+    #[test]
+    fn replays_events_after_seq() {
+        assert_eq!(seqs(replay(&history(1, 5), 5, None, 2)), vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn up_to_date_client_has_nothing_to_replay() {
+        assert!(seqs(replay(&history(1, 5), 5, None, 5)).is_empty());
+        assert!(seqs(replay(&VecDeque::new(), 0, None, 0)).is_empty());
+    }
+
+    #[test]
+    fn oldest_event_can_be_replayed() {
+        assert_eq!(seqs(replay(&history(3, 5), 5, None, 2)), vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn dropped_events_need_resync() {
+        assert!(matches!(
+            replay(&history(4, 5), 5, None, 2),
+            EventReplay::ResyncRequired(5)
+        ));
+    }
+
+    #[test]
+    fn seq_from_another_daemon_needs_resync() {
+        assert!(matches!(
+            replay(&history(1, 5), 5, None, 9),
+            EventReplay::ResyncRequired(5)
+        ));
+    }
+
+    #[test]
+    fn replay_is_filtered() {
+        let filter = EventFilter::all().element(element(4));
+
+        assert_eq!(seqs(replay(&history(1, 5), 5, Some(&filter), 2)), vec![4]);
+    }
+
+    #[test]
+    fn data_window_limits_the_bytes_in_flight() {
+        let mut data_streams = DataStreams {
+            generator: 1,
+            open: HashMap::new(),
+            in_flight: HashMap::new(),
+        };
+        let addr = ClientAddr::Unix(1);
+
+        assert!(data_streams.reserve(addr, 1, DATA_WINDOW - DATA_CHUNK));
+        assert!(data_streams.reserve(addr, 1, DATA_CHUNK));
+        assert!(!data_streams.reserve(addr, 1, 1));
+        // every stream has its own window
+        assert!(data_streams.reserve(addr, 2, DATA_CHUNK));
+
+        data_streams.release(addr, 1, DATA_CHUNK);
+        assert!(data_streams.reserve(addr, 1, DATA_CHUNK));
+        data_streams.release(addr, 1, DATA_WINDOW);
+        assert!(!data_streams.in_flight.contains_key(&(addr, 1)));
     }
 }
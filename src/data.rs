@@ -0,0 +1,188 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use muzzman_lib::prelude::SessionError;
+
+use crate::{
+    packets::{ClientPackets, DataStreamInfo, ServerPackets},
+    TDaemonSession,
+};
+
+/// Data of an element that is read or written in chunks through the daemon
+/// Up to `DataStreamInfo::window` bytes are requested at once, so a big read doesn't wait for every chunk
+/// What was written is set to the element only by `close`, dropped without it the writes are discarded
+pub struct ElementData {
+    session: Box<dyn TDaemonSession>,
+    pub info: DataStreamInfo,
+    offset: u64,
+    closed: bool,
+}
+
+impl ElementData {
+    pub(crate) fn new(session: Box<dyn TDaemonSession>, info: DataStreamInfo) -> Self {
+        Self {
+            session,
+            info,
+            offset: 0,
+            closed: false,
+        }
+    }
+
+    /// Closes the stream, if it was opened for writing the data is set to the element
+    pub fn close(mut self) -> Result<(), SessionError> {
+        self.closed = true;
+        let id = self.session.generate();
+        let packet = ServerPackets::DataClose {
+            id,
+            stream: self.info.stream,
+            discard: false,
+        };
+        if let ClientPackets::DataClose(_, response) = self.session.request(packet)? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    /// Sends the requests of the chunks together, then waits for every response in order
+    /// Stops at the first that fails, the responses that are not waited for are dropped when they arrive
+    fn pipeline(&self, packets: Vec<ServerPackets>) -> Vec<Result<ClientPackets, SessionError>> {
+        let ids = packets
+            .iter()
+            .map(|packet| packet.id())
+            .collect::<Vec<u128>>();
+        for packet in packets {
+            self.session.send(packet);
+        }
+
+        let mut responses = Vec::with_capacity(ids.len());
+        let mut ids = ids.into_iter();
+        for id in ids.by_ref() {
+            let response = self.session.waiting_for(id);
+            let failed = response.is_err();
+            responses.push(response);
+            if failed {
+                // it can still arrive after the timeout
+                self.session.forget(id);
+                break;
+            }
+        }
+        for id in ids {
+            self.session.forget(id);
+        }
+        responses
+    }
+}
+
+fn io_error(err: SessionError) -> std::io::Error {
+    std::io::Error::other(format!("{err:?}"))
+}
+
+impl Read for ElementData {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let want = buf.len().min(self.info.window as usize);
+        let chunk = (self.info.chunk as usize).max(1);
+
+        let mut packets = Vec::new();
+        let mut lens = Vec::new();
+        let mut offset = self.offset;
+        let mut remaining = want;
+        while remaining > 0 {
+            let len = remaining.min(chunk);
+            lens.push(len);
+            packets.push(ServerPackets::DataRead {
+                id: self.session.generate(),
+                stream: self.info.stream,
+                offset,
+                len: len as u32,
+            });
+            offset += len as u64;
+            remaining -= len;
+        }
+
+        let mut read = 0;
+        let mut end = false;
+        for (response, len) in self.pipeline(packets).into_iter().zip(lens) {
+            let bytes = match response.map_err(io_error)? {
+                ClientPackets::DataRead(_, response) => response.map_err(io_error)?,
+                _ => return Err(io_error(SessionError::ServerTimeOut)),
+            };
+            // after the end of the data every chunk is empty
+            if end {
+                continue;
+            }
+            // the daemon cannot write past the chunk that was asked
+            let bytes = &bytes[..bytes.len().min(len)];
+            end = bytes.len() < len;
+            buf[read..read + bytes.len()].copy_from_slice(bytes);
+            read += bytes.len();
+        }
+
+        self.offset += read as u64;
+        Ok(read)
+    }
+}
+
+impl Write for ElementData {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let want = buf.len().min(self.info.window as usize);
+        let chunk = (self.info.chunk as usize).max(1);
+
+        let packets = buf[..want]
+            .chunks(chunk)
+            .enumerate()
+            .map(|(i, bytes)| ServerPackets::DataWrite {
+                id: self.session.generate(),
+                stream: self.info.stream,
+                offset: self.offset + (i * chunk) as u64,
+                bytes: bytes.to_vec(),
+            })
+            .collect();
+
+        let mut written = 0;
+        for response in self.pipeline(packets) {
+            match response.map_err(io_error)? {
+                ClientPackets::DataWrite(_, response) => {
+                    written += response.map_err(io_error)? as usize
+                }
+                _ => return Err(io_error(SessionError::ServerTimeOut)),
+            }
+        }
+
+        self.offset += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for ElementData {
+    /// `SeekFrom::End` is from the size that the data had when it was opened
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let offset = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.info.len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => self.offset.checked_add_signed(delta),
+        };
+        self.offset = offset
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Invalid seek"))?;
+        Ok(self.offset)
+    }
+}
+
+/// Not closed, so it was abandoned, maybe in the middle of the writes
+impl Drop for ElementData {
+    fn drop(&mut self) {
+        if !self.closed {
+            let id = self.session.generate();
+            // nobody waits for the response
+            self.session.forget(id);
+            self.session.send(ServerPackets::DataClose {
+                id,
+                stream: self.info.stream,
+                discard: true,
+            });
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::{fmt::Display, time::Duration};
+
+use muzzman_lib::session::SessionError;
+
+/// Why a request to the daemon failed
+#[derive(Clone, Debug)]
+pub enum RequestError {
+    /// No response arrived in time, after every retry
+    TimedOut {
+        kind: &'static str,
+        id: u128,
+        after: Duration,
+    },
+    /// The response was lost in transit
+    Incomplete { kind: &'static str, id: u128 },
+    /// The request was cancelled with `TDaemonSession::cancel`
+    Cancelled { kind: &'static str, id: u128 },
+    /// The daemon is dead
+    Disconnected,
+    /// The daemon responded with an error
+    Session(SessionError),
+}
+
+impl Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestError::TimedOut { kind, id, after } => {
+                write!(
+                    f,
+                    "Request: {kind} with id: {id} timed out after: {after:?}"
+                )
+            }
+            RequestError::Incomplete { kind, id } => {
+                write!(f, "Response for: {kind} with id: {id} arrived incomplete")
+            }
+            RequestError::Cancelled { kind, id } => {
+                write!(f, "Request: {kind} with id: {id} was cancelled")
+            }
+            RequestError::Disconnected => write!(f, "Disconnected from the daemon"),
+            RequestError::Session(err) => write!(f, "{err:?}"),
+        }
+    }
+}
+
+impl std::error::Error for RequestError {}
+
+impl From<SessionError> for RequestError {
+    fn from(value: SessionError) -> Self {
+        Self::Session(value)
+    }
+}
+
+impl From<RequestError> for SessionError {
+    fn from(value: RequestError) -> Self {
+        match value {
+            RequestError::Disconnected => SessionError::CannotConnectToServer,
+            RequestError::Session(err) => err,
+            err => SessionError::Custom(err.to_string()),
+        }
+    }
+}
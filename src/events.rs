@@ -0,0 +1,381 @@
+use std::mem::Discriminant;
+
+use bytes_kman::prelude::*;
+use muzzman_lib::prelude::{ElementId, LocationId, SessionEvent};
+
+/// Events with the same key replace each other
+pub type CoalesceKey = (Discriminant<SessionEvent>, Vec<u8>);
+
+/// Only changes of an element or location can be coalesced
+/// Creations, destructions and id changes have to arrive every one
+pub fn coalesce_key(event: &SessionEvent) -> Option<CoalesceKey> {
+    if EventKind::of(event) != EventKind::Changed {
+        return None;
+    }
+
+    let target = match event_element(event) {
+        Some(element_id) => element_id.to_bytes(),
+        None => event_location(event)?.to_bytes(),
+    };
+    Some((std::mem::discriminant(event), target))
+}
+
+/// What happened in a `SessionEvent`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Bytes)]
+pub enum EventKind {
+    Created,
+    Destroyed,
+    IdChanged,
+    /// Everything else, like progress and status changes
+    Changed,
+}
+
+impl EventKind {
+    pub fn of(event: &SessionEvent) -> Self {
+        match event {
+            SessionEvent::NewElement(_)
+            | SessionEvent::NewLocation(_)
+            | SessionEvent::NewModule(_) => EventKind::Created,
+            SessionEvent::DestroyedElement(_)
+            | SessionEvent::DestroyedLocation(_)
+            | SessionEvent::DestroyedModule(_) => EventKind::Destroyed,
+            SessionEvent::ElementIdChanged(..)
+            | SessionEvent::LocationIdChanged(..)
+            | SessionEvent::ModuleIdChanged(..) => EventKind::IdChanged,
+            SessionEvent::ElementNameChanged(..)
+            | SessionEvent::ElementDescChanged(..)
+            | SessionEvent::ElementProgressChanged(..)
+            | SessionEvent::ElementStatusChanged(..)
+            | SessionEvent::ElementEnabledChanged(..)
+            | SessionEvent::LocationNameChanged(..)
+            | SessionEvent::LocationDescChanged(..)
+            | SessionEvent::LocationProgressChanged(..)
+            | SessionEvent::LocationStatusChanged(..)
+            | SessionEvent::LocationEnabledChanged(..) => EventKind::Changed,
+        }
+    }
+}
+
+/// Holds the events that can be coalesced until they are flushed
+#[derive(Default)]
+pub struct Coalescer {
+    held: Vec<(CoalesceKey, SessionEvent)>,
+}
+
+impl Coalescer {
+    /// The events that have to be published now, in order
+    /// An event that cannot be coalesced flushes the held ones before it
+    pub fn push(&mut self, event: SessionEvent) -> Vec<SessionEvent> {
+        match coalesce_key(&event) {
+            Some(key) => {
+                match self.held.iter_mut().find(|(k, _)| *k == key) {
+                    Some((_, last)) => *last = event,
+                    None => self.held.push((key, event)),
+                }
+                Vec::new()
+            }
+            None => {
+                let mut events = self.flush();
+                events.push(event);
+                events
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> Vec<SessionEvent> {
+        self.held.drain(..).map(|(_, event)| event).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.held.is_empty()
+    }
+}
+
+/// The element that the event is about
+pub fn event_element(event: &SessionEvent) -> Option<&ElementId> {
+    match event {
+        SessionEvent::NewElement(id)
+        | SessionEvent::DestroyedElement(id)
+        | SessionEvent::ElementIdChanged(_, id)
+        | SessionEvent::ElementNameChanged(id, _)
+        | SessionEvent::ElementDescChanged(id, _)
+        | SessionEvent::ElementProgressChanged(id, _)
+        | SessionEvent::ElementStatusChanged(id, _)
+        | SessionEvent::ElementEnabledChanged(id, _) => Some(id),
+        _ => None,
+    }
+}
+
+/// The location that the event is about, for elements is the location that contains them
+pub fn event_location(event: &SessionEvent) -> Option<&LocationId> {
+    match event {
+        SessionEvent::NewLocation(id)
+        | SessionEvent::DestroyedLocation(id)
+        | SessionEvent::LocationIdChanged(_, id)
+        | SessionEvent::LocationNameChanged(id, _)
+        | SessionEvent::LocationDescChanged(id, _)
+        | SessionEvent::LocationProgressChanged(id, _)
+        | SessionEvent::LocationStatusChanged(id, _)
+        | SessionEvent::LocationEnabledChanged(id, _) => Some(id),
+        event => event_element(event).map(|id| &id.location_id),
+    }
+}
+
+/// Which session events a client wants to receive
+/// Empty fields don't filter anything
+#[derive(Clone, Debug, Default, Bytes)]
+pub struct EventFilter {
+    pub kinds: Vec<EventKind>,
+    /// Events of these locations and of everything inside them
+    pub locations: Vec<LocationId>,
+    pub elements: Vec<ElementId>,
+}
+
+impl EventFilter {
+    /// Every event
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    pub fn kinds(mut self, kinds: impl IntoIterator<Item = EventKind>) -> Self {
+        self.kinds.extend(kinds);
+        self
+    }
+
+    pub fn location(mut self, location_id: LocationId) -> Self {
+        self.locations.push(location_id);
+        self
+    }
+
+    pub fn element(mut self, element_id: ElementId) -> Self {
+        self.elements.push(element_id);
+        self
+    }
+
+    pub fn matches(&self, event: &SessionEvent) -> bool {
+        if !self.kinds.is_empty() && !self.kinds.contains(&EventKind::of(event)) {
+            return false;
+        }
+
+        if self.locations.is_empty() && self.elements.is_empty() {
+            return true;
+        }
+
+        let in_locations = |location_id: &LocationId| {
+            self.locations
+                .iter()
+                .any(|location| location_id.0.starts_with(&location.0))
+        };
+
+        match event {
+            SessionEvent::ElementIdChanged(last, _) if self.elements.contains(last) => return true,
+            SessionEvent::LocationIdChanged(last, _) if in_locations(last) => return true,
+            _ => {}
+        }
+
+        if let Some(element_id) = event_element(event) {
+            if self.elements.contains(element_id) {
+                return true;
+            }
+        }
+
+        event_location(event).map(in_locations).unwrap_or(false)
+    }
+
+    /// Keeps the filter pointing to the same entities when their id changes
+    pub fn follow(&mut self, event: &SessionEvent) {
+        match event {
+            SessionEvent::ElementIdChanged(last, new) => {
+                for element_id in self.elements.iter_mut() {
+                    if element_id == last {
+                        *element_id = new.clone();
+                    }
+                }
+            }
+            SessionEvent::LocationIdChanged(last, new) => {
+                for location_id in self.locations.iter_mut() {
+                    if location_id == last {
+                        *location_id = new.clone();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use muzzman_lib::prelude::ModuleId;
+
+    fn location(path: &[usize]) -> LocationId {
+        LocationId(path.iter().map(|index| *index as _).collect())
+    }
+
+    fn element(path: &[usize], uid: usize) -> ElementId {
+        ElementId {
+            uid: uid as _,
+            location_id: location(path),
+        }
+    }
+
+    #[test]
+    fn change_events_have_their_element() {
+        let element_id = element(&[0, 1], 2);
+        let event = SessionEvent::ElementProgressChanged(element_id.clone(), 0.5);
+
+        assert_eq!(event_element(&event), Some(&element_id));
+        assert_eq!(event_location(&event), Some(&element_id.location_id));
+    }
+
+    #[test]
+    fn change_events_have_their_location() {
+        let location_id = location(&[0, 1]);
+        let event = SessionEvent::LocationStatusChanged(location_id.clone(), 1);
+
+        assert_eq!(event_element(&event), None);
+        assert_eq!(event_location(&event), Some(&location_id));
+    }
+
+    #[test]
+    fn only_changes_have_a_coalesce_key() {
+        let element_id = element(&[0], 1);
+
+        assert!(coalesce_key(&SessionEvent::NewElement(element_id.clone())).is_none());
+        assert!(coalesce_key(&SessionEvent::DestroyedElement(element_id.clone())).is_none());
+        assert!(coalesce_key(&SessionEvent::ElementIdChanged(
+            element_id.clone(),
+            element(&[0], 2)
+        ))
+        .is_none());
+
+        let progress = coalesce_key(&SessionEvent::ElementProgressChanged(
+            element_id.clone(),
+            0.1,
+        ));
+        assert!(progress.is_some());
+        assert_eq!(
+            progress,
+            coalesce_key(&SessionEvent::ElementProgressChanged(
+                element_id.clone(),
+                0.9
+            ))
+        );
+        assert_ne!(
+            progress,
+            coalesce_key(&SessionEvent::ElementStatusChanged(element_id, 1))
+        );
+    }
+
+    #[test]
+    fn progress_of_one_element_is_coalesced() {
+        let mut coalescer = Coalescer::default();
+        let element_id = element(&[0], 1);
+
+        assert!(coalescer
+            .push(SessionEvent::ElementProgressChanged(
+                element_id.clone(),
+                0.1
+            ))
+            .is_empty());
+        assert!(coalescer
+            .push(SessionEvent::ElementProgressChanged(
+                element_id.clone(),
+                0.2
+            ))
+            .is_empty());
+
+        let events = coalescer.flush();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            &events[0],
+            SessionEvent::ElementProgressChanged(id, progress) if *id == element_id && *progress == 0.2
+        ));
+        assert!(coalescer.is_empty());
+    }
+
+    #[test]
+    fn different_elements_are_not_coalesced() {
+        let mut coalescer = Coalescer::default();
+        coalescer.push(SessionEvent::ElementProgressChanged(element(&[0], 1), 0.1));
+        coalescer.push(SessionEvent::ElementProgressChanged(element(&[0], 2), 0.1));
+        coalescer.push(SessionEvent::ElementStatusChanged(element(&[0], 1), 1));
+
+        assert_eq!(coalescer.flush().len(), 3);
+    }
+
+    #[test]
+    fn creation_flushes_in_order() {
+        let mut coalescer = Coalescer::default();
+        coalescer.push(SessionEvent::ElementProgressChanged(element(&[0], 1), 0.1));
+
+        let events = coalescer.push(SessionEvent::DestroyedElement(element(&[0], 1)));
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            events[0],
+            SessionEvent::ElementProgressChanged(..)
+        ));
+        assert!(matches!(events[1], SessionEvent::DestroyedElement(..)));
+        assert!(coalescer.is_empty());
+    }
+
+    #[test]
+    fn empty_filter_matches_everything() {
+        let filter = EventFilter::all();
+
+        assert!(filter.matches(&SessionEvent::NewLocation(location(&[0]))));
+        assert!(filter.matches(&SessionEvent::ElementProgressChanged(element(&[0], 0), 0.1)));
+    }
+
+    #[test]
+    fn filters_by_kind() {
+        let filter = EventFilter::all().kinds([EventKind::Created, EventKind::Destroyed]);
+
+        assert!(filter.matches(&SessionEvent::NewElement(element(&[0], 0))));
+        assert!(filter.matches(&SessionEvent::DestroyedLocation(location(&[0]))));
+        assert!(!filter.matches(&SessionEvent::ElementProgressChanged(element(&[0], 0), 0.1)));
+    }
+
+    #[test]
+    fn filters_by_location_subtree() {
+        let filter = EventFilter::all().location(location(&[0, 1]));
+
+        assert!(filter.matches(&SessionEvent::ElementProgressChanged(
+            element(&[0, 1, 3], 0),
+            0.1
+        )));
+        assert!(filter.matches(&SessionEvent::LocationNameChanged(
+            location(&[0, 1]),
+            "name".to_string()
+        )));
+        assert!(!filter.matches(&SessionEvent::ElementProgressChanged(
+            element(&[0, 2], 0),
+            0.1
+        )));
+        assert!(!filter.matches(&SessionEvent::LocationNameChanged(
+            location(&[0]),
+            "name".to_string()
+        )));
+    }
+
+    #[test]
+    fn filters_by_element() {
+        let filter = EventFilter::all().element(element(&[0], 1));
+
+        assert!(filter.matches(&SessionEvent::ElementStatusChanged(element(&[0], 1), 2)));
+        assert!(!filter.matches(&SessionEvent::ElementStatusChanged(element(&[0], 2), 2)));
+        assert!(!filter.matches(&SessionEvent::NewModule(ModuleId(0))));
+    }
+
+    #[test]
+    fn follows_id_changes() {
+        let mut filter = EventFilter::all().element(element(&[0], 1));
+        let event = SessionEvent::ElementIdChanged(element(&[0], 1), element(&[1], 0));
+
+        assert!(filter.matches(&event));
+        filter.follow(&event);
+        assert!(filter.matches(&SessionEvent::ElementStatusChanged(element(&[1], 0), 2)));
+        assert!(!filter.matches(&SessionEvent::ElementStatusChanged(element(&[0], 1), 2)));
+    }
+}
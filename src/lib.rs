@@ -1,117 +1,674 @@
 use std::{
-    net::UdpSocket,
+    collections::HashMap,
     ops::{AddAssign, Sub},
-    sync::{Arc, RwLock},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex, RwLock, Weak,
+    },
     thread::{self, JoinHandle},
     time::{Duration, SystemTime},
 };
 
+use builder::DaemonSessionBuilder;
 use bytes_kman::TBytes;
+use data::ElementData;
+use error::RequestError;
+use events::EventFilter;
 use muzzman_lib::prelude::*;
-use packets::{ClientPackets, ServerPackets};
+use packets::{
+    frame::MAX_DATAGRAM, ClientPackets, DaemonEvent, EventReplay, LocationNode, ServerPackets,
+    TreeField,
+};
+use query::{ElementQuery, QueryCursor, QueryPage};
+use replies::{Replies, Reply};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use transport::{AsyncReader, Connection, DaemonAddress, Received, Transport, TransportReader};
+
+/// Changes every time that the layout of `ServerPackets` or `ClientPackets` changes
+/// Peers with another version are refused in the handshake instead of decoding garbage
+pub const DAEMON_VERSION: u64 = 7;
 
-pub const DAEMON_VERSION: u64 = 1;
+/// Protocol features that this version knows, negotiated in the handshake
+pub const DAEMON_FEATURES: &[&str] = &[
+    "framing",
+    "event-filter",
+    "event-ack",
+    "lease",
+    "heartbeat",
+    "request-cache",
+    "cancel",
+    "batch",
+    "tree-snapshot",
+    "query",
+    "data-stream",
+    "shm",
+];
 
+/// `DAEMON_FEATURES` that can be used with the other side
+/// "shm" only when the other side is a process of the same user connected by a unix socket
+pub(crate) fn features(shm_peer: bool) -> Vec<String> {
+    DAEMON_FEATURES
+        .iter()
+        .filter(|feature| **feature != "shm" || (shm_peer && shm::available()))
+        .map(|feature| feature.to_string())
+        .collect()
+}
+
+pub mod async_session;
+pub mod builder;
 pub mod common;
 pub mod daemon;
+pub mod data;
+pub mod error;
+pub mod events;
 pub mod packets;
+pub mod query;
+pub mod replica;
+pub mod replies;
 pub mod row;
 pub mod session;
+pub mod shm;
+pub mod transport;
+pub mod tree;
 
 pub const DAEMON_PORT: u16 = 2118;
 
 pub const TIMEOUT: Duration = Duration::new(3, 0);
+/// Longest time that the watcher thread waits for packets before checking the connection
+pub const EVENT_POLL: Duration = Duration::from_millis(50);
+/// Lease that clients ask for, the daemon forgets a client that is silent for longer
+pub const LEASE: Duration = Duration::new(10, 0);
+/// The daemon is pinged when nothing was received for this long
+pub const HEARTBEAT: Duration = Duration::new(1, 0);
+/// The daemon is considered dead when nothing was received for this long
+pub const DEAD_AFTER: Duration = Duration::new(3, 0);
+/// How often a dead daemon is tried again
+pub const RECONNECT_INTERVAL: Duration = Duration::new(2, 0);
+/// How long a reconnect waits for every response
+const RECONNECT_TIMEOUT: Duration = Duration::from_millis(500);
+/// How often a udp request without response is sent again
+pub const RETRANSMIT: Duration = Duration::from_millis(500);
+/// Replies that were not taken for this long are dropped, their request timed out
+pub const REPLY_EXPIRY: Duration = Duration::new(60, 0);
 
 pub mod prelude {
+    pub use crate::async_session::AsyncDaemonSession;
+    pub use crate::builder::DaemonSessionBuilder;
     pub use crate::common::get_modules;
+    pub use crate::data::ElementData;
+    pub use crate::error::RequestError;
+    pub use crate::events::{EventFilter, EventKind};
+    pub use crate::query::{ElementQuery, ElementSort};
+    pub use crate::replica::Replica;
+    pub use crate::transport::DaemonAddress;
     pub use crate::DaemonSession;
     pub use muzzman_lib::prelude::*;
 }
 
 pub struct DaemonSession {
-    pub conn: UdpSocket,
-    pub packets: Vec<ClientPackets>,
+    pub transport: Transport,
+    /// read by the watcher thread without the session lock
+    reader: Arc<Mutex<TransportReader>>,
+    /// pid of the daemon if it runs as the same user on a unix socket, only then blobs are exchanged
+    shm_peer: Option<u32>,
+    /// responses waiting for their request
+    pub replies: Arc<Replies>,
     pub generator: u128,
     pub locations_refs: Vec<LRef>,
     pub element_refs: Vec<ERef>,
     pub module_refs: Vec<MRef>,
     pub watcher_thread: JoinHandle<()>,
+    /// features that both the daemon and the client have
+    pub features: Vec<String>,
+    /// receivers of the session events
+    pub subscribers: Vec<Sender<SessionEvent>>,
+    /// sequence number of the last session event
+    pub last_event: u64,
+    /// request for the events that were missed
+    replaying: Option<(u128, SystemTime)>,
+    /// events that arrived while replaying
+    held_events: Vec<(u64, SessionEvent)>,
+    /// events arrived since the last ack
+    ack_needed: bool,
+    /// receivers of the daemon events
+    pub daemon_subscribers: Vec<Sender<DaemonEvent>>,
+    /// receivers of the session events of the async session
+    async_subscribers: Vec<UnboundedSender<SessionEvent>>,
+    /// receivers of the daemon events of the async session
+    async_daemon_subscribers: Vec<UnboundedSender<DaemonEvent>>,
+    /// granted by the daemon, `None` if the daemon doesn't know leases
+    pub lease: Option<Duration>,
+    last_sent: SystemTime,
+    /// only a `ClientPackets::Pong` proves that the daemon is alive
+    last_pong: SystemTime,
+    last_ping: SystemTime,
+    /// id of the last `ServerPackets::Ping`
+    ping: u128,
+    /// `false` when the daemon is dead, requests fail fast until is reconnected
+    pub connected: bool,
+    /// how many times session events were lost, caches of the session should be fetched again
+    pub resyncs: u64,
+    last_reconnect: SystemTime,
+    address: DaemonAddress,
+    name: String,
+    /// sent again after reconnecting
+    event_filter: Option<EventFilter>,
+    /// default time to wait for a response
+    pub timeout: Duration,
+    /// time to wait for a response by request kind
+    pub timeouts: HashMap<&'static str, Duration>,
+    /// how many times a request that timed out is sent again
+    pub retries: u32,
+    /// how often a udp request without response is sent again, while waiting
+    pub retransmit: Duration,
 }
 
 unsafe impl Send for DaemonSession {}
 unsafe impl Sync for DaemonSession {}
 
 impl DaemonSession {
-    pub fn new() -> Result<Self, std::io::Error> {
-        let conn = UdpSocket::bind("127.0.0.1:0")?;
-        conn.connect(format!("127.0.0.1:{DAEMON_PORT}"))?;
-        let _ = conn.set_nonblocking(true);
-        let _ = conn.set_read_timeout(Some(TIMEOUT));
-        Ok(Self {
-            conn,
-            packets: Vec::new(),
+    pub fn new() -> Result<Self, SessionError> {
+        Self::connect(DaemonAddress::default())
+    }
+
+    pub fn connect(address: DaemonAddress) -> Result<Self, SessionError> {
+        Self::builder().address(address).build()
+    }
+
+    /// `name` is how the daemon will know this client
+    pub fn connect_as(
+        address: DaemonAddress,
+        name: impl Into<String>,
+    ) -> Result<Self, SessionError> {
+        Self::builder().address(address).name(name).build()
+    }
+
+    pub fn builder() -> DaemonSessionBuilder {
+        DaemonSessionBuilder::default()
+    }
+
+    pub(crate) fn open(builder: DaemonSessionBuilder) -> Result<Self, SessionError> {
+        let DaemonSessionBuilder {
+            address,
+            name,
+            timeout,
+            timeouts,
+            retries,
+            retransmit,
+        } = builder;
+        let name = name.unwrap_or_else(|| {
+            std::env::current_exe()
+                .ok()
+                .and_then(|path| Some(path.file_name()?.to_string_lossy().to_string()))
+                .unwrap_or_else(|| "DaemonSession".to_string())
+        });
+
+        let (transport, reader) = Transport::connect(&address, timeout).map_err(|err| {
+            log::error!("Cannot connect to {address:?}: {err}");
+            SessionError::CannotConnectToServer
+        })?;
+
+        let mut session = Self {
+            shm_peer: transport.shm_peer(),
+            transport,
+            reader: Arc::new(Mutex::new(reader)),
+            replies: Arc::new(Replies::default()),
             generator: 1,
             locations_refs: Vec::new(),
             element_refs: Vec::new(),
             module_refs: Vec::new(),
             watcher_thread: thread::spawn(|| {}),
-        })
+            features: Vec::new(),
+            subscribers: Vec::new(),
+            last_event: 0,
+            replaying: None,
+            held_events: Vec::new(),
+            ack_needed: false,
+            daemon_subscribers: Vec::new(),
+            async_subscribers: Vec::new(),
+            async_daemon_subscribers: Vec::new(),
+            lease: None,
+            last_sent: SystemTime::now(),
+            last_pong: SystemTime::now(),
+            last_ping: SystemTime::now(),
+            ping: 0,
+            connected: true,
+            resyncs: 0,
+            last_reconnect: SystemTime::now(),
+            address,
+            name: name.clone(),
+            event_filter: None,
+            timeout,
+            timeouts,
+            retries,
+            retransmit,
+        };
+        session.handshake(name, timeout)?;
+        if session.has_feature("lease") {
+            session.connect_lease(timeout)?;
+        }
+        Ok(session)
     }
 
-    pub fn pull_packets(&mut self) {
-        let mut buffer = [0; 4096];
+    fn handshake(&mut self, name: String, timeout: Duration) -> Result<(), SessionError> {
+        let id = self.generator;
+        self.generator += 1;
 
-        let mut master_buffer = Vec::new();
+        let features = features(self.shm_peer.is_some());
+        self.send(ServerPackets::Hello {
+            id,
+            version: DAEMON_VERSION,
+            features: features.clone(),
+            name,
+        });
 
-        while let Ok(len) = self.conn.recv(&mut buffer) {
-            master_buffer.append(&mut buffer[0..len].to_vec());
+        match self.wait_response(id, timeout)? {
+            ClientPackets::Welcome(_, Ok(welcome)) => {
+                if welcome.version != DAEMON_VERSION {
+                    log::error!(
+                        "Daemon has version: {} expected: {DAEMON_VERSION}",
+                        welcome.version
+                    );
+                    return Err(SessionError::ServerInvalidIndentification);
+                }
+                self.features = welcome
+                    .features
+                    .into_iter()
+                    .filter(|feature| features.contains(feature))
+                    .collect();
+                self.last_event = welcome.event_seq;
+                Ok(())
+            }
+            ClientPackets::Welcome(_, Err(err)) | ClientPackets::Error(_, err) => Err(err),
+            _ => Err(SessionError::ServerInvalidIndentification),
         }
+    }
 
-        while !master_buffer.is_empty() {
-            if let Some(packet) = ClientPackets::from_bytes(&mut master_buffer) {
-                if let ClientPackets::NewSessionEvent(event) = packet {
-                    match event {
-                        SessionEvent::DestroyedElement(id) => {
-                            self.element_refs.retain(|eref| eref.id() != id);
-                        }
-                        SessionEvent::DestroyedLocation(id) => {
-                            self.locations_refs.retain(|lref| lref.id() != id)
-                        }
-                        SessionEvent::DestroyedModule(id) => {
-                            self.module_refs.retain(|mref| mref.id() != id)
-                        }
-                        SessionEvent::ElementIdChanged(last, new) => {
-                            for eref in self.element_refs.iter_mut() {
-                                if eref.id() == last {
-                                    eref.write().unwrap().id = new.clone();
-                                    break;
-                                }
-                            }
-                        }
-                        SessionEvent::LocationIdChanged(last, new) => {
-                            for lref in self.locations_refs.iter_mut() {
-                                if lref.id() == last {
-                                    lref.write().unwrap().id = new;
-                                    break;
-                                }
-                            }
+    /// Tells the daemon that we are present, until we are silent for longer than the lease
+    fn connect_lease(&mut self, timeout: Duration) -> Result<(), SessionError> {
+        let id = self.generator;
+        self.generator += 1;
+
+        self.send(ServerPackets::Connect {
+            id,
+            lease: LEASE.as_millis() as u64,
+        });
+        match self.wait_response(id, timeout)? {
+            ClientPackets::Connect(_, Ok(lease)) => {
+                self.lease = Some(Duration::from_millis(lease));
+                Ok(())
+            }
+            ClientPackets::Connect(_, Err(err)) | ClientPackets::Error(_, err) => Err(err),
+            _ => Err(SessionError::ServerInvalidIndentification),
+        }
+    }
+
+    /// Waits for a response before the session is shared
+    fn wait_response(
+        &mut self,
+        id: u128,
+        timeout: Duration,
+    ) -> Result<ClientPackets, SessionError> {
+        let start_time = SystemTime::now();
+        loop {
+            match self.replies.take(id) {
+                Some(Reply::Response(packet)) => return Ok(packet),
+                Some(Reply::Incomplete) | Some(Reply::Cancelled) => {
+                    return Err(SessionError::ServerTimeOut)
+                }
+                None => {}
+            }
+
+            let elapsed = start_time.elapsed().unwrap_or_default();
+            if elapsed > timeout {
+                return Err(SessionError::ServerTimeOut);
+            }
+            let received = self.reader.lock().unwrap().recv(timeout - elapsed);
+            self.handle_received(received);
+        }
+    }
+
+    /// Pings the daemon, detects if is dead and tries to reconnect, called by the watcher
+    /// Returns `true` when it reconnected, the refs should be revalidated
+    pub fn keep_alive(&mut self) -> bool {
+        if !self.connected {
+            if self.last_reconnect.elapsed().unwrap_or_default() >= RECONNECT_INTERVAL {
+                self.last_reconnect = SystemTime::now();
+                match self.reconnect() {
+                    Ok(()) => return true,
+                    Err(err) => log::debug!("Cannot reconnect to the daemon: {err:?}"),
+                }
+            }
+            return false;
+        }
+
+        let silent = self.last_pong.elapsed().unwrap_or_default();
+        let closed = self.reader.lock().unwrap().closed;
+        if closed || (self.has_feature("heartbeat") && silent > DEAD_AFTER) {
+            log::error!("Lost the connection with the daemon");
+            self.disconnected();
+            self.last_reconnect = SystemTime::now();
+            return false;
+        }
+
+        if self.has_feature("heartbeat")
+            && silent >= HEARTBEAT
+            && self.last_ping.elapsed().unwrap_or_default() >= HEARTBEAT
+        {
+            let id = self.generator;
+            self.generator += 1;
+            self.last_ping = SystemTime::now();
+            self.ping = id;
+            self.send(ServerPackets::Ping { id });
+        } else if self.last_sent.elapsed().unwrap_or_default() >= self.tick_interval() {
+            // any packet keeps the client present
+            self.send(ServerPackets::Tick);
+        }
+        false
+    }
+
+    /// Connects again to the same address
+    /// The refs can be outdated after, `create_daemon_session` revalidates them
+    pub fn reconnect(&mut self) -> Result<(), SessionError> {
+        let (transport, reader) =
+            Transport::connect(&self.address, self.timeout).map_err(|err| {
+                log::debug!("Cannot connect to {:?}: {err}", self.address);
+                SessionError::CannotConnectToServer
+            })?;
+        self.shm_peer = transport.shm_peer();
+        self.transport = transport;
+        self.reader = Arc::new(Mutex::new(reader));
+        self.replies.clear();
+        self.replaying = None;
+        self.held_events.clear();
+        self.features.clear();
+        self.lease = None;
+
+        self.handshake(self.name.clone(), RECONNECT_TIMEOUT)?;
+        if self.has_feature("lease") {
+            self.connect_lease(RECONNECT_TIMEOUT)?;
+        }
+        if let Some(filter) = self.event_filter.clone() {
+            let id = self.generator;
+            self.generator += 1;
+            self.send(ServerPackets::SetEventFilter { id, filter });
+            self.wait_response(id, RECONNECT_TIMEOUT)?;
+        }
+
+        self.connected = true;
+        self.last_pong = SystemTime::now();
+        log::info!("Reconnected to the daemon");
+        // the events of the time that the daemon was dead are lost
+        self.resyncs += 1;
+        Ok(())
+    }
+
+    /// Requests fail fast until is reconnected, the waiters are woken to notice it
+    fn disconnected(&mut self) {
+        self.connected = false;
+        self.replies.wake_all();
+    }
+
+    /// How long the client can be silent before a `ServerPackets::Tick` is needed
+    pub fn tick_interval(&self) -> Duration {
+        self.lease
+            .map(|lease| lease / 3)
+            .unwrap_or(Duration::new(1, 0))
+    }
+
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+
+    /// Tells the daemon that nobody waits for the request, and drops its response
+    fn give_up(&mut self, id: u128) {
+        self.replies.forget(id);
+        if self.connected && self.has_feature("cancel") {
+            self.send(ServerPackets::Cancel { id });
+        }
+    }
+
+    /// Requests are sent again only over udp and when the daemon will not run them twice
+    fn can_retransmit(&self) -> bool {
+        matches!(self.transport.conn, Connection::Udp(_)) && self.has_feature("request-cache")
+    }
+
+    /// How long to wait for the response of this kind of request
+    pub fn timeout_for(&self, kind: &str) -> Duration {
+        self.timeouts.get(kind).copied().unwrap_or(self.timeout)
+    }
+
+    /// Every daemon event that arrives after this is sent to the receiver
+    pub fn subscribe_daemon(&mut self) -> Receiver<DaemonEvent> {
+        let (sender, receiver) = channel();
+        self.daemon_subscribers.push(sender);
+        receiver
+    }
+
+    /// Every session event that arrives after this is sent to the receiver
+    /// Events are received by the watcher thread after `create_session`
+    pub fn subscribe(&mut self) -> Receiver<SessionEvent> {
+        let (sender, receiver) = channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    /// Like `subscribe_daemon`, the events are sent by the task that reads the transport
+    pub(crate) fn subscribe_daemon_async(&mut self) -> UnboundedReceiver<DaemonEvent> {
+        let (sender, receiver) = unbounded_channel();
+        self.async_daemon_subscribers.push(sender);
+        receiver
+    }
+
+    /// Like `subscribe`, the events are sent by the task that reads the transport
+    pub(crate) fn subscribe_async(&mut self) -> UnboundedReceiver<SessionEvent> {
+        let (sender, receiver) = unbounded_channel();
+        self.async_subscribers.push(sender);
+        receiver
+    }
+
+    pub fn send(&mut self, packet: ServerPackets) {
+        let mut bytes = packet.to_bytes();
+        bytes.reverse();
+        if self.has_feature("shm") {
+            bytes = shm::offload_request(packet.id(), bytes);
+        }
+
+        if let Err(err) = self.transport.send(packet.id(), &bytes) {
+            log::error!("Cannot send packet: {err}");
+        }
+        self.last_sent = SystemTime::now();
+    }
+
+    /// Handles the packets that already arrived, does not block
+    /// Does nothing while the watcher thread is reading
+    pub fn pull_packets(&mut self) {
+        let reader = self.reader.clone();
+        let Ok(mut reader) = reader.try_lock() else {
+            return;
+        };
+        let received = reader.recv(Duration::ZERO);
+        drop(reader);
+        self.handle_received(received);
+    }
+
+    fn handle_received(&mut self, received: Vec<Received>) {
+        for received in received {
+            match received {
+                Received::Message(request, mut message) => {
+                    let Some(mut packet) = ClientPackets::from_bytes(&mut message) else {
+                        log::error!("Cannot decode the response for: {request}");
+                        if request != 0 {
+                            self.replies.insert(request, Reply::Incomplete);
                         }
-                        SessionEvent::ModuleIdChanged(last, new) => {
-                            for mref in self.module_refs.iter_mut() {
-                                if mref.id() == last {
-                                    mref.write().unwrap().uid = new;
+                        continue;
+                    };
+
+                    if let ClientPackets::Shm(id, blob) = &packet {
+                        // only the daemon that negotiated it can make the client read blobs
+                        let taken = match self.shm_peer.filter(|_| self.has_feature("shm")) {
+                            Some(pid) => shm::take_response(blob, pid),
+                            None => Err(std::io::Error::other("Shared memory was not negotiated")),
+                        };
+                        match taken {
+                            Ok(inner) => packet = inner,
+                            Err(err) => {
+                                log::error!("Cannot read the response for: {id}: {err}");
+                                if *id != 0 {
+                                    self.replies.insert(*id, Reply::Incomplete);
                                 }
+                                continue;
                             }
                         }
-                        _ => {}
                     }
+
+                    self.handle_packet(packet);
+                }
+                Received::Incomplete(request) => {
+                    if request != 0 {
+                        self.replies.insert(request, Reply::Incomplete);
+                    }
+                }
+            }
+        }
+
+        if let Some((_, start_time)) = self.replaying {
+            if start_time.elapsed().unwrap_or_default() > self.timeout {
+                log::error!("Missed session events were not replayed in time");
+                self.resyncs += 1;
+                self.finish_replay();
+            }
+        }
+
+        // streams are reliable, the daemon only waits acks from datagram clients
+        if self.ack_needed
+            && self.has_feature("event-ack")
+            && matches!(self.transport.conn, Connection::Udp(_))
+        {
+            self.ack_needed = false;
+            self.send(ServerPackets::AckEvent {
+                seq: self.last_event,
+            });
+        }
+    }
+
+    fn handle_packet(&mut self, packet: ClientPackets) {
+        match packet {
+            ClientPackets::NewSessionEvent(seq, last, event) => {
+                self.ack_needed = true;
+                if self.replaying.is_some() {
+                    self.held_events.push((seq, event));
+                } else if last > self.last_event {
+                    log::warn!("Missed session events after: {}", self.last_event);
+                    self.held_events.push((seq, event));
+                    self.replay_events();
                 } else {
-                    self.packets.push(packet)
+                    self.apply_event(seq, event);
+                }
+            }
+            ClientPackets::EventsSince(id, replay)
+                if self.replaying.map(|(replay_id, _)| replay_id) == Some(id) =>
+            {
+                match replay {
+                    Ok(EventReplay::Events(events)) => {
+                        for (seq, event) in events {
+                            self.apply_event(seq, event);
+                        }
+                    }
+                    Ok(EventReplay::ResyncRequired(_)) => {
+                        log::error!("Session events were lost, refs can be outdated");
+                        self.resyncs += 1;
+                    }
+                    Err(err) => log::error!("Cannot replay session events: {err:?}"),
                 }
+                self.finish_replay();
+            }
+            ClientPackets::Pong(_) => self.last_pong = SystemTime::now(),
+            // the daemon restarted and does not know this client
+            ClientPackets::Error(id, SessionError::ServerInvalidIndentification)
+                if id == self.ping =>
+            {
+                log::error!("Daemon does not know this client anymore");
+                self.disconnected();
+                self.last_reconnect = SystemTime::UNIX_EPOCH;
+            }
+            ClientPackets::DaemonEvent(event) => {
+                self.daemon_subscribers
+                    .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+                self.async_daemon_subscribers
+                    .retain(|subscriber| subscriber.send(event.clone()).is_ok());
             }
+            ClientPackets::Error(0, err) => log::error!("Daemon refused a packet: {err:?}"),
+            packet => self.replies.insert(packet.id(), Reply::Response(packet)),
+        }
+    }
+
+    /// Asks the daemon for the events after `last_event`
+    fn replay_events(&mut self) {
+        let id = self.generator;
+        self.generator += 1;
+
+        self.send(ServerPackets::EventsSince {
+            id,
+            seq: self.last_event,
+        });
+        self.replaying = Some((id, SystemTime::now()));
+    }
+
+    fn finish_replay(&mut self) {
+        self.replaying = None;
+        for (seq, event) in std::mem::take(&mut self.held_events) {
+            self.apply_event(seq, event);
         }
     }
 
+    fn apply_event(&mut self, seq: u64, event: SessionEvent) {
+        // already received
+        if seq <= self.last_event {
+            return;
+        }
+        self.last_event = seq;
+
+        match event.clone() {
+            SessionEvent::DestroyedElement(id) => {
+                self.element_refs.retain(|eref| eref.id() != id);
+            }
+            SessionEvent::DestroyedLocation(id) => {
+                self.locations_refs.retain(|lref| lref.id() != id)
+            }
+            SessionEvent::DestroyedModule(id) => self.module_refs.retain(|mref| mref.id() != id),
+            SessionEvent::ElementIdChanged(last, new) => {
+                for eref in self.element_refs.iter_mut() {
+                    if eref.id() == last {
+                        eref.write().unwrap().id = new.clone();
+                        break;
+                    }
+                }
+            }
+            SessionEvent::LocationIdChanged(last, new) => {
+                for lref in self.locations_refs.iter_mut() {
+                    if lref.id() == last {
+                        lref.write().unwrap().id = new;
+                        break;
+                    }
+                }
+            }
+            SessionEvent::ModuleIdChanged(last, new) => {
+                for mref in self.module_refs.iter_mut() {
+                    if mref.id() == last {
+                        mref.write().unwrap().uid = new;
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        self.subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+        self.async_subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
     pub fn gc_refs(&mut self) {
         self.module_refs.retain(|mref| {
             let count = Arc::strong_count(mref);
@@ -130,36 +687,262 @@ impl DaemonSession {
     }
 
     pub fn create_session(self) -> Box<dyn TSession> {
+        Box::new(self.create_daemon_session())
+    }
+
+    /// Like `create_session` but keeps the daemon specific api
+    pub fn create_daemon_session(self) -> Box<dyn TDaemonSession> {
         let s = Arc::new(RwLock::new(self));
 
         let sc = s.clone();
 
         s.write().unwrap().watcher_thread = thread::spawn(move || {
             let sc = sc;
+            let mut last_gc = SystemTime::now();
+            let mut last_shm_gc = SystemTime::now();
             loop {
-                thread::sleep(Duration::new(1, 0));
+                // the only thread that waits on the transport, without the session lock
+                let (reader, replies) = {
+                    let s = sc.read().unwrap();
+                    (s.reader.clone(), s.replies.clone())
+                };
+                let mut reader = reader.lock().unwrap();
+                let received = reader.recv(EVENT_POLL);
+                replies.mark_arriving(|id| reader.in_progress(id));
+                drop(reader);
+
+                let mut s = sc.write().unwrap();
+                s.handle_received(received);
+                let reconnected = s.keep_alive();
+                drop(s);
+                if reconnected {
+                    revalidate_refs(&sc);
+                }
+
+                if last_gc.elapsed().unwrap_or_default() < Duration::new(1, 0) {
+                    continue;
+                }
+                last_gc = SystemTime::now();
+
                 let count = Arc::strong_count(&sc);
-                sc.write().unwrap().gc_refs();
+                if collect(&sc, &mut last_shm_gc) {
+                    shm::gc(REPLY_EXPIRY);
+                }
                 if count == 1 {
                     break;
                 }
-                sc.pull_packets();
-                let mut bytes = ServerPackets::Tick.to_bytes();
-                bytes.reverse();
-                let _ = sc.read().unwrap().conn.send(&bytes);
             }
         });
 
-        Box::new(Box::new(s) as Box<dyn TDaemonSession>)
+        Box::new(s)
+    }
+
+    /// Like `create_daemon_session` for the async session, must be called inside a tokio runtime
+    /// The transport is read by a task that wakes the waiters and sends the events to the async subscribers
+    pub(crate) fn create_async_session(self) -> Result<Arc<RwLock<Self>>, std::io::Error> {
+        let s = Arc::new(RwLock::new(self));
+        drive(&s)?;
+        tokio::spawn(watch(Arc::downgrade(&s)));
+        Ok(s)
+    }
+}
+
+/// Forgets the refs and the replies that nobody uses anymore
+/// Returns `true` when the blobs that were never taken should be removed too
+fn collect(session: &Arc<RwLock<DaemonSession>>, last_shm_gc: &mut SystemTime) -> bool {
+    session.write().unwrap().gc_refs();
+    session.read().unwrap().replies.gc(REPLY_EXPIRY);
+    // a client that never offloaded a message has no blobs to lose
+    if shm::created() && last_shm_gc.elapsed().unwrap_or_default() >= shm::GC_INTERVAL {
+        *last_shm_gc = SystemTime::now();
+        return true;
+    }
+    false
+}
+
+/// Hands the transport of the session to a reader and a writer task
+/// Called again after every reconnect, the tasks of the last transport stop by themselves
+fn drive(session: &Arc<RwLock<DaemonSession>>) -> Result<(), std::io::Error> {
+    let mut s = session.write().unwrap();
+    let reader = s.reader.clone();
+    let (conn, writer, queued) = s.transport.drive(&mut reader.lock().unwrap())?;
+    drop(s);
+
+    tokio::spawn(writer.write_queued(queued));
+    tokio::spawn(read_transport(Arc::downgrade(session), reader, conn));
+    Ok(())
+}
+
+/// The async watcher of the transport, what arrives is handled by the session without waiting
+/// The waiters of the responses are woken by `Replies::notify`
+async fn read_transport(
+    session: Weak<RwLock<DaemonSession>>,
+    reader: Arc<Mutex<TransportReader>>,
+    mut conn: AsyncReader,
+) {
+    let mut buffer = vec![0; MAX_DATAGRAM];
+    loop {
+        // the incomplete messages are collected also when nothing arrives
+        let read = tokio::time::timeout(EVENT_POLL, conn.read(&mut buffer)).await;
+        let Some(session) = session.upgrade() else {
+            break;
+        };
+        let replies = {
+            let s = session.read().unwrap();
+            // reconnected, the new transport has its own task
+            if !Arc::ptr_eq(&s.reader, &reader) {
+                break;
+            }
+            s.replies.clone()
+        };
+
+        let mut r = reader.lock().unwrap();
+        let mut received = match read {
+            Ok(Ok(Some(len))) => r.push(&buffer[..len]),
+            Ok(Ok(None)) => {
+                log::error!("Daemon closed the connection");
+                r.closed = true;
+                Vec::new()
+            }
+            // a datagram can be refused while the daemon is dead, the heartbeat notices it
+            Ok(Err(err)) if matches!(conn, AsyncReader::Udp(_)) => {
+                log::debug!("Cannot receive: {err}");
+                Vec::new()
+            }
+            Ok(Err(err)) => {
+                log::error!("Cannot receive: {err}");
+                r.closed = true;
+                Vec::new()
+            }
+            Err(_) => Vec::new(),
+        };
+        received.extend(r.gc());
+        replies.mark_arriving(|id| r.in_progress(id));
+        let closed = r.closed;
+        drop(r);
+
+        session.write().unwrap().handle_received(received);
+        if closed {
+            break;
+        }
+    }
+}
+
+/// The async watcher of the session, keeps it alive, reconnects and collects like the watcher thread
+async fn watch(session: Weak<RwLock<DaemonSession>>) {
+    let mut last_gc = SystemTime::now();
+    let mut last_shm_gc = SystemTime::now();
+    loop {
+        tokio::time::sleep(EVENT_POLL).await;
+        let Some(session) = session.upgrade() else {
+            break;
+        };
+
+        let reconnecting = {
+            let s = session.read().unwrap();
+            !s.connected && s.last_reconnect.elapsed().unwrap_or_default() >= RECONNECT_INTERVAL
+        };
+        let reconnected = if reconnecting {
+            // connecting and the handshake block
+            let sc = session.clone();
+            tokio::task::spawn_blocking(move || sc.write().unwrap().keep_alive())
+                .await
+                .unwrap_or(false)
+        } else {
+            // the packets are only queued
+            session.write().unwrap().keep_alive()
+        };
+        if reconnected {
+            if let Err(err) = drive(&session) {
+                log::error!("Cannot read the daemon: {err}");
+                session.write().unwrap().disconnected();
+                continue;
+            }
+            let sc = session.clone();
+            tokio::task::spawn_blocking(move || revalidate_refs(&sc));
+        }
+
+        if last_gc.elapsed().unwrap_or_default() < Duration::new(1, 0) {
+            continue;
+        }
+        last_gc = SystemTime::now();
+
+        if collect(&session, &mut last_shm_gc) {
+            tokio::task::spawn_blocking(|| shm::gc(REPLY_EXPIRY));
+        }
+    }
+}
+
+impl Drop for DaemonSession {
+    fn drop(&mut self) {
+        if self.connected && self.lease.is_some() {
+            self.send(ServerPackets::Disconnect);
+        }
     }
 }
 
 pub trait TDaemonSession: Send + Sync {
     fn pull_packets(&self);
 
-    fn waiting_for(&self, id: u128) -> Option<ClientPackets>;
+    fn waiting_for(&self, id: u128) -> Result<ClientPackets, SessionError>;
+    /// `None` will wait until the response arrives
+    fn waiting_for_timeout(
+        &self,
+        id: u128,
+        timeout: Option<Duration>,
+    ) -> Result<ClientPackets, SessionError>;
+    /// Sends the packet and waits for the response, with the timeout configured for its kind
+    fn request(&self, packet: ServerPackets) -> Result<ClientPackets, RequestError>;
+    /// Sends the packet again with the same id every time it timed out, until the retries are used
+    /// `None` will wait until the response arrives
+    fn request_timeout(
+        &self,
+        packet: ServerPackets,
+        timeout: Option<Duration>,
+    ) -> Result<ClientPackets, RequestError>;
     fn send(&self, packet: ServerPackets);
     fn generate(&self) -> u128;
+    /// Receiver for every session event that arrives after this
+    fn subscribe(&self) -> Receiver<SessionEvent>;
+    /// Receiver for every daemon event that arrives after this
+    fn subscribe_daemon(&self) -> Receiver<DaemonEvent>;
+    /// Only the events that match the filter will be sent by the daemon
+    fn set_event_filter(&self, filter: EventFilter) -> Result<(), SessionError>;
+    /// Sequence number of the last session event that was received
+    fn last_event(&self) -> u64;
+    /// `false` while the daemon is dead
+    fn is_connected(&self) -> bool;
+    /// Changes every time that session events were lost
+    fn resyncs(&self) -> u64;
+    /// Gives up on the request `id`, the daemon will not run it if is still queued and will not respond
+    /// The thread that waits for it returns `RequestError::Cancelled`
+    fn cancel(&self, id: u128);
+    /// The response of the request `id` is dropped when it arrives, for requests that are sent without waiting
+    fn forget(&self, id: u128);
+    /// The events after `seq` that match the filter
+    fn events_since(&self, seq: u64) -> Result<EventReplay, SessionError>;
+    /// Runs every request in one round trip, the responses are in the same order
+    /// Requests that are handled by the daemon itself, like `ElementWait`, are responded with an error
+    fn batch(&self, requests: Vec<ServerPackets>) -> Result<Vec<ClientPackets>, SessionError>;
+    /// The locations and elements under `root` with the selected fields, in one response
+    /// `depth` is how many levels of sub locations are included, 0 is only the root
+    fn get_tree_snapshot(
+        &self,
+        root: &LocationId,
+        depth: u32,
+        fields: Vec<TreeField>,
+    ) -> Result<LocationNode, SessionError>;
+    /// The elements that match the query, filtered and sorted by the daemon
+    /// `cursor` is `None` for the first page, then `QueryPage::next`
+    fn query_elements(
+        &self,
+        query: ElementQuery,
+        cursor: Option<QueryCursor>,
+        limit: u32,
+    ) -> Result<QueryPage, SessionError>;
+    /// Opens the data of the element to be read or written in chunks
+    /// Opened for writing the data is set to the element when it is closed
+    fn open_data(&self, element_id: &ElementId, write: bool) -> Result<ElementData, SessionError>;
 
     fn eref_get_or_add(&self, element_id: ElementId) -> ERef;
     fn lref_get_or_add(&self, location_id: LocationId) -> LRef;
@@ -173,32 +956,46 @@ impl TDaemonSession for Arc<RwLock<DaemonSession>> {
         self.write().unwrap().pull_packets()
     }
 
-    fn waiting_for(&self, id: u128) -> Option<ClientPackets> {
-        let start_time = SystemTime::now();
-        let mut index = None;
-        'm: loop {
-            if start_time.elapsed().unwrap() > TIMEOUT {
-                println!("Time Out!");
-                break;
-            }
-            for (i, packet) in self.read().unwrap().packets.iter().enumerate() {
-                if packet.id() == id {
-                    index = Some(i);
-                    break 'm;
-                }
+    fn waiting_for(&self, id: u128) -> Result<ClientPackets, SessionError> {
+        let timeout = self.read().unwrap().timeout;
+        self.waiting_for_timeout(id, Some(timeout))
+    }
+
+    fn waiting_for_timeout(
+        &self,
+        id: u128,
+        timeout: Option<Duration>,
+    ) -> Result<ClientPackets, SessionError> {
+        Ok(wait_for(self, id, "Request", timeout, None)?)
+    }
+
+    fn request(&self, packet: ServerPackets) -> Result<ClientPackets, RequestError> {
+        let timeout = self.read().unwrap().timeout_for(packet.kind());
+        self.request_timeout(packet, Some(timeout))
+    }
+
+    fn request_timeout(
+        &self,
+        packet: ServerPackets,
+        timeout: Option<Duration>,
+    ) -> Result<ClientPackets, RequestError> {
+        let id = packet.id();
+        let kind = packet.kind();
+
+        let mut attempt = 0;
+        loop {
+            self.send(packet.clone());
+            let response = wait_for(self, id, kind, timeout, Some(&packet));
+            if let Some(response) = retried(self, &mut attempt, response) {
+                return response;
             }
-            self.pull_packets();
         }
-
-        index.map(|index| self.write().unwrap().packets.remove(index))
     }
 
     fn send(&self, packet: ServerPackets) {
-        let mut bytes = packet.to_bytes();
-        bytes.reverse();
-
-        for chunk in bytes.chunks(4096) {
-            let _ = self.write().unwrap().conn.send(chunk);
+        let mut s = self.write().unwrap();
+        if s.connected {
+            s.send(packet)
         }
     }
 
@@ -207,6 +1004,154 @@ impl TDaemonSession for Arc<RwLock<DaemonSession>> {
         self.read().unwrap().generator.sub(1)
     }
 
+    fn subscribe(&self) -> Receiver<SessionEvent> {
+        self.write().unwrap().subscribe()
+    }
+
+    fn subscribe_daemon(&self) -> Receiver<DaemonEvent> {
+        self.write().unwrap().subscribe_daemon()
+    }
+
+    fn set_event_filter(&self, filter: EventFilter) -> Result<(), SessionError> {
+        if !self.read().unwrap().has_feature("event-filter") {
+            return Err(SessionError::Custom(
+                "Daemon cannot filter events".to_string(),
+            ));
+        }
+
+        let id = self.generate();
+        self.write().unwrap().event_filter = Some(filter.clone());
+        if let ClientPackets::SetEventFilter(_, response) =
+            self.request(ServerPackets::SetEventFilter { id, filter })?
+        {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    fn last_event(&self) -> u64 {
+        self.read().unwrap().last_event
+    }
+
+    fn is_connected(&self) -> bool {
+        self.read().unwrap().connected
+    }
+
+    fn resyncs(&self) -> u64 {
+        self.read().unwrap().resyncs
+    }
+
+    fn cancel(&self, id: u128) {
+        let mut s = self.write().unwrap();
+        s.give_up(id);
+        s.replies.cancel(id);
+    }
+
+    fn forget(&self, id: u128) {
+        self.read().unwrap().replies.forget(id);
+    }
+
+    fn events_since(&self, seq: u64) -> Result<EventReplay, SessionError> {
+        let id = self.generate();
+        if let ClientPackets::EventsSince(_, response) =
+            self.request(ServerPackets::EventsSince { id, seq })?
+        {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    fn batch(&self, requests: Vec<ServerPackets>) -> Result<Vec<ClientPackets>, SessionError> {
+        if !self.read().unwrap().has_feature("batch") {
+            return Err(SessionError::Custom(
+                "Daemon cannot batch requests".to_string(),
+            ));
+        }
+
+        let id = self.generate();
+        if let ClientPackets::Batch(_, responses) =
+            self.request(ServerPackets::Batch { id, requests })?
+        {
+            Ok(responses)
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    fn get_tree_snapshot(
+        &self,
+        root: &LocationId,
+        depth: u32,
+        fields: Vec<TreeField>,
+    ) -> Result<LocationNode, SessionError> {
+        if !self.read().unwrap().has_feature("tree-snapshot") {
+            return Err(SessionError::Custom(
+                "Daemon cannot make tree snapshots".to_string(),
+            ));
+        }
+
+        let id = self.generate();
+        let packet = ServerPackets::GetTreeSnapshot {
+            id,
+            root: root.clone(),
+            depth,
+            fields,
+        };
+        if let ClientPackets::GetTreeSnapshot(_, response) = self.request(packet)? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    fn query_elements(
+        &self,
+        query: ElementQuery,
+        cursor: Option<QueryCursor>,
+        limit: u32,
+    ) -> Result<QueryPage, SessionError> {
+        if !self.read().unwrap().has_feature("query") {
+            return Err(SessionError::Custom(
+                "Daemon cannot query elements".to_string(),
+            ));
+        }
+
+        let id = self.generate();
+        let packet = ServerPackets::QueryElements {
+            id,
+            query,
+            cursor,
+            limit,
+        };
+        if let ClientPackets::QueryElements(_, response) = self.request(packet)? {
+            response
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
+    fn open_data(&self, element_id: &ElementId, write: bool) -> Result<ElementData, SessionError> {
+        if !self.read().unwrap().has_feature("data-stream") {
+            return Err(SessionError::Custom(
+                "Daemon cannot stream data".to_string(),
+            ));
+        }
+
+        let id = self.generate();
+        let packet = ServerPackets::DataOpen {
+            id,
+            element_id: element_id.clone(),
+            write,
+        };
+        if let ClientPackets::DataOpen(_, response) = self.request(packet)? {
+            Ok(ElementData::new(self.cl(), response?))
+        } else {
+            Err(SessionError::ServerTimeOut)
+        }
+    }
+
     fn eref_get_or_add(&self, element_id: ElementId) -> ERef {
         for eref in self.read().unwrap().element_refs.iter() {
             if eref.id() == element_id {
@@ -259,3 +1204,231 @@ impl TDaemonSession for Arc<RwLock<DaemonSession>> {
         Box::new(self.clone())
     }
 }
+
+/// Forgets the refs whose id the daemon doesn't know anymore
+/// Every ref is checked in one batch, without holding the session lock while waiting
+fn revalidate_refs(session: &Arc<RwLock<DaemonSession>>) {
+    let requests = {
+        let s = session.read().unwrap();
+        let elements = s
+            .element_refs
+            .iter()
+            .map(|eref| ServerPackets::ElementGetName {
+                id: 0,
+                element_id: eref.id(),
+            });
+        let locations = s
+            .locations_refs
+            .iter()
+            .map(|lref| ServerPackets::LocationGetName {
+                id: 0,
+                from: lref.id(),
+            });
+        let modules = s
+            .module_refs
+            .iter()
+            .map(|mref| ServerPackets::ModuleGetName {
+                id: 0,
+                module_id: mref.id(),
+            });
+        elements.chain(locations).chain(modules).collect::<Vec<_>>()
+    };
+    if requests.is_empty() {
+        return;
+    }
+
+    let responses = match session.batch(requests.clone()) {
+        Ok(responses) => responses,
+        Err(err) => {
+            log::error!("Cannot revalidate refs: {err:?}");
+            return;
+        }
+    };
+
+    let mut s = session.write().unwrap();
+    for (packet, response) in requests.into_iter().zip(responses) {
+        let valid = !matches!(
+            response,
+            ClientPackets::ElementGetName(_, Err(_))
+                | ClientPackets::LocationGetName(_, Err(_))
+                | ClientPackets::ModuleGetName(_, Err(_))
+        );
+        if valid {
+            continue;
+        }
+
+        log::warn!("Ref does not exist after reconnecting: {packet:?}");
+        match packet {
+            ServerPackets::ElementGetName { element_id, .. } => {
+                s.element_refs.retain(|eref| eref.id() != element_id)
+            }
+            ServerPackets::LocationGetName { from, .. } => {
+                s.locations_refs.retain(|lref| lref.id() != from)
+            }
+            ServerPackets::ModuleGetName { module_id, .. } => {
+                s.module_refs.retain(|mref| mref.id() != module_id)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// `None` when the request timed out and should be sent again
+/// After the last retry the daemon is told that nobody waits for it
+fn retried(
+    session: &Arc<RwLock<DaemonSession>>,
+    attempt: &mut u32,
+    response: Result<ClientPackets, RequestError>,
+) -> Option<Result<ClientPackets, RequestError>> {
+    let retries = session.read().unwrap().retries;
+    match response {
+        Err(RequestError::TimedOut { kind, id, .. }) if *attempt < retries => {
+            *attempt += 1;
+            log::warn!("Retrying: {kind} with id: {id}, attempt: {attempt}/{retries}");
+            None
+        }
+        Err(RequestError::TimedOut { kind, id, after }) => {
+            session.write().unwrap().give_up(id);
+            Some(Err(RequestError::TimedOut {
+                kind,
+                id,
+                after: after * (*attempt + 1),
+            }))
+        }
+        response => Some(response),
+    }
+}
+
+/// A waiter for the response with the id, `kind` is only used for errors
+/// `request` is sent again every `DaemonSession::retransmit` when it can be
+struct Wait<'a> {
+    id: u128,
+    kind: &'static str,
+    timeout: Option<Duration>,
+    request: Option<&'a ServerPackets>,
+    start_time: SystemTime,
+    last_sent: SystemTime,
+}
+
+impl<'a> Wait<'a> {
+    fn new(
+        id: u128,
+        kind: &'static str,
+        timeout: Option<Duration>,
+        request: Option<&'a ServerPackets>,
+    ) -> Self {
+        Self {
+            id,
+            kind,
+            timeout,
+            request,
+            start_time: SystemTime::now(),
+            last_sent: SystemTime::now(),
+        }
+    }
+
+    /// How long to sleep before checking the reply again, retransmits the request when is time
+    fn sleep(
+        &mut self,
+        session: &Arc<RwLock<DaemonSession>>,
+        replies: &Replies,
+    ) -> Result<Duration, RequestError> {
+        let (connected, retransmit) = {
+            let s = session.read().unwrap();
+            (s.connected, s.can_retransmit().then_some(s.retransmit))
+        };
+        if !connected {
+            return Err(RequestError::Disconnected);
+        }
+
+        // a big response is still arriving
+        if replies.take_arriving(self.id) {
+            self.start_time = SystemTime::now();
+            self.last_sent = SystemTime::now();
+        }
+
+        let mut sleep = self
+            .timeout
+            .map(|timeout| timeout.saturating_sub(self.start_time.elapsed().unwrap_or_default()))
+            .unwrap_or(Duration::MAX);
+        if let (Some(request), Some(retransmit)) = (self.request, retransmit) {
+            let since_sent = self.last_sent.elapsed().unwrap_or_default();
+            if since_sent > retransmit {
+                log::debug!("Retransmitting: {} with id: {}", self.kind, self.id);
+                session.send(request.clone());
+                self.last_sent = SystemTime::now();
+                sleep = sleep.min(retransmit);
+            } else {
+                sleep = sleep.min(retransmit - since_sent);
+            }
+        }
+        Ok(sleep)
+    }
+
+    /// `Ok(None)` when the waiter should sleep again
+    fn response(&self, reply: Option<Reply>) -> Result<Option<ClientPackets>, RequestError> {
+        let (kind, id) = (self.kind, self.id);
+        match reply {
+            Some(Reply::Response(ClientPackets::Error(_, err))) => {
+                return Err(RequestError::Session(err))
+            }
+            Some(Reply::Response(packet)) => return Ok(Some(packet)),
+            Some(Reply::Incomplete) => return Err(RequestError::Incomplete { kind, id }),
+            Some(Reply::Cancelled) => return Err(RequestError::Cancelled { kind, id }),
+            None => {}
+        }
+
+        if let Some(timeout) = self.timeout {
+            if self.start_time.elapsed().unwrap_or_default() > timeout {
+                log::warn!("Request: {kind} with id: {id} timed out after: {timeout:?}");
+                return Err(RequestError::TimedOut {
+                    kind,
+                    id,
+                    after: timeout,
+                });
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Waits for the response with the id, `kind` is only used for errors
+/// The packets are received by the watcher thread, the waiter sleeps until its reply arrives
+/// or until the request has to be sent again
+fn wait_for(
+    session: &Arc<RwLock<DaemonSession>>,
+    id: u128,
+    kind: &'static str,
+    timeout: Option<Duration>,
+    request: Option<&ServerPackets>,
+) -> Result<ClientPackets, RequestError> {
+    let replies = session.read().unwrap().replies.clone();
+    let mut wait = Wait::new(id, kind, timeout, request);
+    loop {
+        let sleep = wait.sleep(session, &replies)?;
+        if let Some(response) = wait.response(replies.wait_for(id, sleep))? {
+            return Ok(response);
+        }
+    }
+}
+
+/// Like `wait_for` without blocking, the task sleeps until the reader task notifies it
+async fn wait_for_async(
+    session: &Arc<RwLock<DaemonSession>>,
+    id: u128,
+    kind: &'static str,
+    timeout: Option<Duration>,
+    request: Option<&ServerPackets>,
+) -> Result<ClientPackets, RequestError> {
+    let replies = session.read().unwrap().replies.clone();
+    let mut wait = Wait::new(id, kind, timeout, request);
+    loop {
+        // registered before checking the session, a disconnect after the check still wakes it
+        let notified = replies.notify(id);
+        let sleep = wait.sleep(session, &replies)?;
+        let _ = tokio::time::timeout(sleep, notified).await;
+        if let Some(response) = wait.response(replies.take(id))? {
+            return Ok(response);
+        }
+    }
+}
@@ -0,0 +1,366 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    time::{Duration, SystemTime},
+};
+
+use bytes_kman::prelude::*;
+
+/// Biggest datagram that we send or expect to receive
+pub const MAX_DATAGRAM: usize = 4096;
+/// How much of a message fits in one frame, the rest of the datagram is the header
+pub const FRAME_PAYLOAD: usize = MAX_DATAGRAM - 128;
+/// Messages bigger than this are rejected
+pub const MAX_MESSAGE: usize = 256 * 1024 * 1024;
+/// Responses on streams start with their request
+pub const REQUEST_HEADER: usize = 16;
+/// How long a partial message can wait for the next chunk
+pub const REASSEMBLY_TIMEOUT: Duration = Duration::new(1, 0);
+/// How many partial messages one sender can have, frames of more are dropped
+pub const MAX_PARTIALS_PER_SENDER: usize = 16;
+/// How many partial messages can be collected from every sender together
+pub const MAX_PARTIALS: usize = 256;
+
+/// One datagram of a message
+/// `message` is unique per sender, `request` is the id of the request that the message is part of, 0 for events
+#[derive(Clone, Debug, Bytes)]
+pub struct Frame {
+    pub message: u64,
+    pub request: u128,
+    pub index: u32,
+    pub count: u32,
+    pub len: u64,
+    pub data: Vec<u8>,
+}
+
+impl Frame {
+    /// Split a message in datagrams ready to be sent
+    pub fn split(message: u64, request: u128, bytes: &[u8]) -> Vec<Vec<u8>> {
+        let count = bytes.len().div_ceil(FRAME_PAYLOAD).max(1);
+        let mut datagrams = Vec::with_capacity(count);
+
+        for index in 0..count {
+            let start = index * FRAME_PAYLOAD;
+            let end = (start + FRAME_PAYLOAD).min(bytes.len());
+            let frame = Frame {
+                message,
+                request,
+                index: index as u32,
+                count: count as u32,
+                len: bytes.len() as u64,
+                data: bytes[start..end].to_vec(),
+            };
+            let mut datagram = frame.to_bytes();
+            datagram.reverse();
+            datagrams.push(datagram);
+        }
+
+        datagrams
+    }
+
+    /// Rejects frames that don't agree with how `split` makes them
+    pub fn decode(datagram: &[u8]) -> Option<Frame> {
+        let mut buffer = datagram.to_vec();
+        let frame = Frame::from_bytes(&mut buffer)?;
+
+        if !buffer.is_empty() || frame.len > MAX_MESSAGE as u64 {
+            return None;
+        }
+
+        let len = frame.len as usize;
+        let count = len.div_ceil(FRAME_PAYLOAD).max(1);
+        if frame.count as usize != count || frame.index >= frame.count {
+            return None;
+        }
+
+        // every chunk is full, except the last one
+        let start = frame.index as usize * FRAME_PAYLOAD;
+        let expected = (len - start.min(len)).min(FRAME_PAYLOAD);
+        if frame.data.len() != expected {
+            return None;
+        }
+
+        Some(frame)
+    }
+}
+
+/// A message that was dropped because not all the chunks arrived in time
+#[derive(Clone, Debug)]
+pub struct Incomplete<K> {
+    pub from: K,
+    pub request: u128,
+    pub received: u32,
+    pub count: u32,
+}
+
+struct Partial {
+    request: u128,
+    len: u64,
+    count: u32,
+    /// by index, only the chunks that arrived are allocated
+    chunks: HashMap<u32, Vec<u8>>,
+    last: SystemTime,
+}
+
+/// Collects frames until a message is complete
+/// `K` identifies the sender
+pub struct Reassembler<K> {
+    partials: HashMap<(K, u64), Partial>,
+    timeout: Duration,
+}
+
+impl<K: Hash + Eq + Clone> Default for Reassembler<K> {
+    fn default() -> Self {
+        Self::new(REASSEMBLY_TIMEOUT)
+    }
+}
+
+impl<K: Hash + Eq + Clone> Reassembler<K> {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            partials: HashMap::new(),
+            timeout,
+        }
+    }
+
+    /// Returns the request id and the message when the last chunk arrives
+    pub fn push(&mut self, from: K, frame: Frame) -> Option<(u128, Vec<u8>)> {
+        if frame.count == 1 {
+            if frame.data.len() as u64 != frame.len {
+                log::warn!("Dropped frame with invalid length for: {}", frame.request);
+                return None;
+            }
+            return Some((frame.request, frame.data));
+        }
+
+        let key = (from, frame.message);
+        if !self.partials.contains_key(&key) {
+            let from_sender = self
+                .partials
+                .keys()
+                .filter(|(sender, _)| *sender == key.0)
+                .count();
+            if from_sender >= MAX_PARTIALS_PER_SENDER || self.partials.len() >= MAX_PARTIALS {
+                log::warn!(
+                    "Too many partial messages, dropped frame for: {}",
+                    frame.request
+                );
+                return None;
+            }
+        }
+
+        let partial = self.partials.entry(key.clone()).or_insert_with(|| Partial {
+            request: frame.request,
+            len: frame.len,
+            count: frame.count,
+            chunks: HashMap::new(),
+            last: SystemTime::now(),
+        });
+
+        if partial.count != frame.count
+            || partial.len != frame.len
+            || partial.request != frame.request
+        {
+            log::warn!(
+                "Dropped frame that does not match message: {}",
+                frame.message
+            );
+            return None;
+        }
+
+        partial.last = SystemTime::now();
+        partial.chunks.entry(frame.index).or_insert(frame.data);
+
+        if partial.chunks.len() != partial.count as usize {
+            return None;
+        }
+
+        let mut partial = self.partials.remove(&key)?;
+        let mut message = Vec::with_capacity(partial.len as usize);
+        for index in 0..partial.count {
+            message.extend(partial.chunks.remove(&index)?);
+        }
+
+        if message.len() as u64 != partial.len {
+            log::warn!(
+                "Dropped message with invalid length for: {}",
+                partial.request
+            );
+            return None;
+        }
+
+        Some((partial.request, message))
+    }
+
+    /// If a message for `request` is still arriving
+    pub fn in_progress(&self, request: u128) -> bool {
+        self.partials
+            .values()
+            .any(|partial| partial.request == request)
+    }
+
+    /// Removes messages that stopped receiving chunks
+    pub fn gc(&mut self) -> Vec<Incomplete<K>> {
+        let mut expired = Vec::new();
+        let timeout = self.timeout;
+
+        self.partials.retain(|(from, _), partial| {
+            if partial.last.elapsed().unwrap_or_default() < timeout {
+                return true;
+            }
+            expired.push(Incomplete {
+                from: from.clone(),
+                request: partial.request,
+                received: partial.chunks.len() as u32,
+                count: partial.count,
+            });
+            false
+        });
+
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(len: usize) -> Vec<u8> {
+        (0..len).map(|i| i as u8).collect()
+    }
+
+    fn frames(message: &[u8]) -> Vec<Frame> {
+        Frame::split(1, 7, message)
+            .iter()
+            .map(|datagram| Frame::decode(datagram).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn split_fits_datagrams() {
+        let bytes = message(FRAME_PAYLOAD * 2 + 10);
+        let datagrams = Frame::split(1, 7, &bytes);
+
+        assert_eq!(datagrams.len(), 3);
+        assert!(datagrams
+            .iter()
+            .all(|datagram| datagram.len() <= MAX_DATAGRAM));
+    }
+
+    #[test]
+    fn empty_message_is_one_frame() {
+        let frames = frames(&[]);
+
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].count, 1);
+        assert!(frames[0].data.is_empty());
+    }
+
+    #[test]
+    fn decode_keeps_header() {
+        let bytes = message(FRAME_PAYLOAD + 1);
+        let frames = frames(&bytes);
+
+        assert_eq!(frames.len(), 2);
+        for (index, frame) in frames.iter().enumerate() {
+            assert_eq!(frame.message, 1);
+            assert_eq!(frame.request, 7);
+            assert_eq!(frame.index, index as u32);
+            assert_eq!(frame.count, 2);
+            assert_eq!(frame.len, bytes.len() as u64);
+        }
+        assert_eq!(frames[1].data, vec![bytes[FRAME_PAYLOAD]]);
+    }
+
+    #[test]
+    fn reassembles_out_of_order() {
+        let bytes = message(FRAME_PAYLOAD * 3 + 5);
+        let mut frames = frames(&bytes);
+        frames.reverse();
+
+        let mut reassembler = Reassembler::default();
+        let last = frames.pop().unwrap();
+        for frame in frames {
+            assert!(reassembler.push(1, frame).is_none());
+        }
+        assert!(reassembler.in_progress(7));
+
+        assert_eq!(reassembler.push(1, last), Some((7, bytes)));
+        assert!(!reassembler.in_progress(7));
+    }
+
+    #[test]
+    fn duplicated_chunk_is_ignored() {
+        let bytes = message(FRAME_PAYLOAD + 5);
+        let frames = frames(&bytes);
+
+        let mut reassembler = Reassembler::default();
+        assert!(reassembler.push(1, frames[0].clone()).is_none());
+        assert!(reassembler.push(1, frames[0].clone()).is_none());
+        assert_eq!(reassembler.push(1, frames[1].clone()), Some((7, bytes)));
+    }
+
+    #[test]
+    fn rejects_forged_count() {
+        let frame = Frame {
+            message: 1,
+            request: 7,
+            index: 0,
+            count: u32::MAX,
+            len: 10,
+            data: vec![0; 10],
+        };
+        let mut datagram = frame.to_bytes();
+        datagram.reverse();
+
+        assert!(Frame::decode(&datagram).is_none());
+    }
+
+    #[test]
+    fn rejects_short_chunk() {
+        let frame = Frame {
+            message: 1,
+            request: 7,
+            index: 0,
+            count: 2,
+            len: FRAME_PAYLOAD as u64 + 1,
+            data: vec![0; 10],
+        };
+        let mut datagram = frame.to_bytes();
+        datagram.reverse();
+
+        assert!(Frame::decode(&datagram).is_none());
+    }
+
+    #[test]
+    fn caps_partials_per_sender() {
+        let mut reassembler = Reassembler::default();
+        let bytes = message(FRAME_PAYLOAD + 1);
+
+        for message in 0..MAX_PARTIALS_PER_SENDER as u64 + 1 {
+            let mut frame = frames(&bytes).remove(0);
+            frame.message = message;
+            assert!(reassembler.push(1, frame).is_none());
+        }
+        assert_eq!(reassembler.partials.len(), MAX_PARTIALS_PER_SENDER);
+
+        // other senders still have room
+        let frame = frames(&bytes).remove(0);
+        reassembler.push(2, frame);
+        assert_eq!(reassembler.partials.len(), MAX_PARTIALS_PER_SENDER + 1);
+    }
+
+    #[test]
+    fn gc_reports_incomplete() {
+        let bytes = message(FRAME_PAYLOAD + 1);
+        let mut reassembler = Reassembler::new(Duration::ZERO);
+        reassembler.push(1, frames(&bytes).remove(0));
+
+        let incomplete = reassembler.gc();
+        assert_eq!(incomplete.len(), 1);
+        assert_eq!(incomplete[0].request, 7);
+        assert_eq!(incomplete[0].received, 1);
+        assert_eq!(incomplete[0].count, 2);
+        assert!(!reassembler.in_progress(7));
+    }
+}
@@ -10,10 +10,26 @@ use muzzman_lib::{
     types::{Type, ID, UID},
 };
 
+use crate::{
+    events::EventFilter,
+    query::{ElementQuery, QueryCursor, QueryPage},
+    shm::ShmBlob,
+};
+
+pub mod frame;
+
 // send
 #[derive(Clone, Debug, Bytes)]
 #[allow(clippy::large_enum_variant)]
 pub enum ServerPackets {
+    /// Should be the first packet of a client
+    /// Needs to stay the first variant with the same layout, so every version can decode it
+    Hello {
+        id: u128,
+        version: u64,
+        features: Vec<String>,
+        name: String,
+    },
     LoadModule {
         id: u128,
         path: PathBuf,
@@ -509,14 +525,516 @@ pub enum ServerPackets {
         id: u128,
     },
 
+    /// Replaces the filter of the session events that the client receives
+    SetEventFilter {
+        id: u128,
+        filter: EventFilter,
+    },
+    /// The events after `seq` that match the filter of the client
+    EventsSince {
+        id: u128,
+        seq: u64,
+    },
+    /// Every session event until `seq` was received, has no response
+    AckEvent {
+        seq: u64,
+    },
+    /// The client is present for `lease` milliseconds after every packet
+    /// Should be sent after `Hello`, responds with the lease that was granted
+    Connect {
+        id: u128,
+        lease: u64,
+    },
+    /// The daemon releases the client, has no response
+    Disconnect,
+    /// Responded with `ClientPackets::Pong` right away, to know that the daemon is alive
+    Ping {
+        id: u128,
+    },
+    /// The client gave up on the request `id`, it will not be responded, has no response
+    Cancel {
+        id: u128,
+    },
+    /// Runs the requests one after another, responds with all the responses in the same order
+    Batch {
+        id: u128,
+        requests: Vec<ServerPackets>,
+    },
+    /// The locations and elements under `root` with the selected fields
+    /// `depth` is how many levels of sub locations are included, 0 is only the root
+    GetTreeSnapshot {
+        id: u128,
+        root: LocationId,
+        depth: u32,
+        fields: Vec<TreeField>,
+    },
+    /// The elements that match the query, at most `limit` after `cursor`
+    /// `cursor` is `None` for the first page, then `QueryPage::next`
+    QueryElements {
+        id: u128,
+        query: ElementQuery,
+        cursor: Option<QueryCursor>,
+        limit: u32,
+    },
+    /// Opens the data of the element to be read or written in chunks
+    /// The written data is set to the element when the stream is closed
+    DataOpen {
+        id: u128,
+        element_id: ElementId,
+        write: bool,
+    },
+    /// Responds with at most `len` bytes from `offset`, less at the end of the data
+    DataRead {
+        id: u128,
+        stream: u64,
+        offset: u64,
+        len: u32,
+    },
+    /// Responds with how many bytes were written
+    DataWrite {
+        id: u128,
+        stream: u64,
+        offset: u64,
+        bytes: Vec<u8>,
+    },
+    /// With `discard` what was written is dropped instead of set to the element
+    DataClose {
+        id: u128,
+        stream: u64,
+        discard: bool,
+    },
+    /// A packet that is in shared memory, is read instead of this one
+    /// `id` is the id of the packet in the blob
+    Shm {
+        id: u128,
+        blob: ShmBlob,
+    },
+
     Tick,
 }
 
+impl ServerPackets {
+    pub fn id(&self) -> u128 {
+        match self {
+            ServerPackets::Hello { id, .. } => *id,
+            ServerPackets::LoadModule { id, .. } => *id,
+            ServerPackets::RemoveModule { id, .. } => *id,
+            ServerPackets::LoadModuleInfo { id, .. } => *id,
+            ServerPackets::FindModule { id, .. } => *id,
+            ServerPackets::GetActionsLen { id, .. } => *id,
+            ServerPackets::GetActions { id, .. } => *id,
+            ServerPackets::RunAction { id, .. } => *id,
+            ServerPackets::GetModulesLen { id, .. } => *id,
+            ServerPackets::GetModules { id, .. } => *id,
+            ServerPackets::ModuleGetName { id, .. } => *id,
+            ServerPackets::ModuleSetName { id, .. } => *id,
+            ServerPackets::ModuleGetDefaultName { id, .. } => *id,
+            ServerPackets::ModuleGetUid { id, .. } => *id,
+            ServerPackets::ModuleGetVersion { id, .. } => *id,
+            ServerPackets::ModuleSupportedVersions { id, .. } => *id,
+            ServerPackets::ModuleGetDesc { id, .. } => *id,
+            ServerPackets::ModuleSetDesc { id, .. } => *id,
+            ServerPackets::ModuleGetDefaultDesc { id, .. } => *id,
+            ServerPackets::ModuleGetProxy { id, .. } => *id,
+            ServerPackets::ModuleSetProxy { id, .. } => *id,
+            ServerPackets::ModuleGetSettings { id, .. } => *id,
+            ServerPackets::ModuleSetSettings { id, .. } => *id,
+            ServerPackets::ModuleGetElementSettings { id, .. } => *id,
+            ServerPackets::ModuleSetElementSettings { id, .. } => *id,
+            ServerPackets::ModuleGetLocationSettings { id, .. } => *id,
+            ServerPackets::ModuleSetLocationSettings { id, .. } => *id,
+            ServerPackets::ModuleInitLocation { id, .. } => *id,
+            ServerPackets::ModuleInitElement { id, .. } => *id,
+            ServerPackets::ModuleAcceptUrl { id, .. } => *id,
+            ServerPackets::ModuleAcceptExtension { id, .. } => *id,
+            ServerPackets::ModuleAcceptedProtocols { id, .. } => *id,
+            ServerPackets::ModuleAcceptedExtensions { id, .. } => *id,
+            ServerPackets::GetDefaultLocation { id, .. } => *id,
+            ServerPackets::LocationGetName { id, .. } => *id,
+            ServerPackets::LocationSetName { id, .. } => *id,
+            ServerPackets::LocationGetDesc { id, .. } => *id,
+            ServerPackets::LocationSetDesc { id, .. } => *id,
+            ServerPackets::LocationGetInfo { id, .. } => *id,
+            ServerPackets::CreateElement { id, .. } => *id,
+            ServerPackets::LoadElementInfo { id, .. } => *id,
+            ServerPackets::MoveElement { id, .. } => *id,
+            ServerPackets::DestroyElement { id, .. } => *id,
+            ServerPackets::ElementGetName { id, .. } => *id,
+            ServerPackets::ElementSetName { id, .. } => *id,
+            ServerPackets::ElementGetDesc { id, .. } => *id,
+            ServerPackets::ElementSetDesc { id, .. } => *id,
+            ServerPackets::ElementGetMeta { id, .. } => *id,
+            ServerPackets::ElementSetMeta { id, .. } => *id,
+            ServerPackets::ElementGetUrl { id, .. } => *id,
+            ServerPackets::ElementSetUrl { id, .. } => *id,
+            ServerPackets::ElementGetElementData { id, .. } => *id,
+            ServerPackets::ElementSetElementData { id, .. } => *id,
+            ServerPackets::ElementGetModuleData { id, .. } => *id,
+            ServerPackets::ElementSetModuleData { id, .. } => *id,
+            ServerPackets::ElementGetModule { id, .. } => *id,
+            ServerPackets::ElementSetModule { id, .. } => *id,
+            ServerPackets::ElementGetStatuses { id, .. } => *id,
+            ServerPackets::ElementSetStatuses { id, .. } => *id,
+            ServerPackets::ElementGetStatus { id, .. } => *id,
+            ServerPackets::ElementSetStatus { id, .. } => *id,
+            ServerPackets::ElementGetData { id, .. } => *id,
+            ServerPackets::ElementSetData { id, .. } => *id,
+            ServerPackets::ElementGetProgress { id, .. } => *id,
+            ServerPackets::ElementSetProgress { id, .. } => *id,
+            ServerPackets::ElementGetShouldSave { id, .. } => *id,
+            ServerPackets::ElementSetShouldSave { id, .. } => *id,
+            ServerPackets::ElementGetEnabled { id, .. } => *id,
+            ServerPackets::ElementSetEnabled { id, .. } => *id,
+            ServerPackets::ElementIsError { id, .. } => *id,
+            ServerPackets::ElementResolvModule { id, .. } => *id,
+            ServerPackets::ElementWait { id, .. } => *id,
+            ServerPackets::ElementGetInfo { id, .. } => *id,
+            ServerPackets::ElementNotify { id, .. } => *id,
+            ServerPackets::ElementEmit { id, .. } => *id,
+            ServerPackets::ElementSubscribe { id, .. } => *id,
+            ServerPackets::ElementUnSubscribe { id, .. } => *id,
+            ServerPackets::CreateLocation { id, .. } => *id,
+            ServerPackets::LoadLocationInfo { id, .. } => *id,
+            ServerPackets::GetLocationsLen { id, .. } => *id,
+            ServerPackets::GetLocations { id, .. } => *id,
+            ServerPackets::DestroyLocation { id, .. } => *id,
+            ServerPackets::MoveLocation { id, .. } => *id,
+            ServerPackets::LocationGetPath { id, .. } => *id,
+            ServerPackets::LocationSetPath { id, .. } => *id,
+            ServerPackets::LocationGetShouldSave { id, .. } => *id,
+            ServerPackets::LocationSetShouldSave { id, .. } => *id,
+            ServerPackets::LocationGetElementsLen { id, .. } => *id,
+            ServerPackets::LocationGetElements { id, .. } => *id,
+            ServerPackets::LocationGetModule { id, .. } => *id,
+            ServerPackets::LocationSetModule { id, .. } => *id,
+            ServerPackets::LocationGetSettings { id, .. } => *id,
+            ServerPackets::LocationSetSettings { id, .. } => *id,
+            ServerPackets::LocationGetModuleSettings { id, .. } => *id,
+            ServerPackets::LocationSetModuleSettings { id, .. } => *id,
+            ServerPackets::LocationGetStatuses { id, .. } => *id,
+            ServerPackets::LocationSetStatuses { id, .. } => *id,
+            ServerPackets::LocationGetStatus { id, .. } => *id,
+            ServerPackets::LocationSetStatus { id, .. } => *id,
+            ServerPackets::LocationGetProgress { id, .. } => *id,
+            ServerPackets::LocationSetProgress { id, .. } => *id,
+            ServerPackets::LocationIsEnabled { id, .. } => *id,
+            ServerPackets::LocationSetEnabled { id, .. } => *id,
+            ServerPackets::LocationIsError { id, .. } => *id,
+            ServerPackets::LocationNotify { id, .. } => *id,
+            ServerPackets::LocationEmit { id, .. } => *id,
+            ServerPackets::LocationSubscribe { id, .. } => *id,
+            ServerPackets::LocationUnSubscribe { id, .. } => *id,
+            ServerPackets::GetVersion { id, .. } => *id,
+            ServerPackets::GetVersionText { id, .. } => *id,
+            ServerPackets::SetEventFilter { id, .. } => *id,
+            ServerPackets::EventsSince { id, .. } => *id,
+            ServerPackets::AckEvent { .. } => 0,
+            ServerPackets::Connect { id, .. } => *id,
+            ServerPackets::Disconnect => 0,
+            ServerPackets::Ping { id } => *id,
+            ServerPackets::Cancel { .. } => 0,
+            ServerPackets::Batch { id, .. } => *id,
+            ServerPackets::GetTreeSnapshot { id, .. } => *id,
+            ServerPackets::QueryElements { id, .. } => *id,
+            ServerPackets::DataOpen { id, .. } => *id,
+            ServerPackets::DataRead { id, .. } => *id,
+            ServerPackets::DataWrite { id, .. } => *id,
+            ServerPackets::DataClose { id, .. } => *id,
+            ServerPackets::Shm { id, .. } => *id,
+            ServerPackets::Tick => 0,
+        }
+    }
+
+    /// Name of the request, used to configure timeouts and in errors
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ServerPackets::Hello { .. } => "Hello",
+            ServerPackets::LoadModule { .. } => "LoadModule",
+            ServerPackets::RemoveModule { .. } => "RemoveModule",
+            ServerPackets::LoadModuleInfo { .. } => "LoadModuleInfo",
+            ServerPackets::FindModule { .. } => "FindModule",
+            ServerPackets::GetActionsLen { .. } => "GetActionsLen",
+            ServerPackets::GetActions { .. } => "GetActions",
+            ServerPackets::RunAction { .. } => "RunAction",
+            ServerPackets::GetModulesLen { .. } => "GetModulesLen",
+            ServerPackets::GetModules { .. } => "GetModules",
+            ServerPackets::ModuleGetName { .. } => "ModuleGetName",
+            ServerPackets::ModuleSetName { .. } => "ModuleSetName",
+            ServerPackets::ModuleGetDefaultName { .. } => "ModuleGetDefaultName",
+            ServerPackets::ModuleGetUid { .. } => "ModuleGetUid",
+            ServerPackets::ModuleGetVersion { .. } => "ModuleGetVersion",
+            ServerPackets::ModuleSupportedVersions { .. } => "ModuleSupportedVersions",
+            ServerPackets::ModuleGetDesc { .. } => "ModuleGetDesc",
+            ServerPackets::ModuleSetDesc { .. } => "ModuleSetDesc",
+            ServerPackets::ModuleGetDefaultDesc { .. } => "ModuleGetDefaultDesc",
+            ServerPackets::ModuleGetProxy { .. } => "ModuleGetProxy",
+            ServerPackets::ModuleSetProxy { .. } => "ModuleSetProxy",
+            ServerPackets::ModuleGetSettings { .. } => "ModuleGetSettings",
+            ServerPackets::ModuleSetSettings { .. } => "ModuleSetSettings",
+            ServerPackets::ModuleGetElementSettings { .. } => "ModuleGetElementSettings",
+            ServerPackets::ModuleSetElementSettings { .. } => "ModuleSetElementSettings",
+            ServerPackets::ModuleGetLocationSettings { .. } => "ModuleGetLocationSettings",
+            ServerPackets::ModuleSetLocationSettings { .. } => "ModuleSetLocationSettings",
+            ServerPackets::ModuleInitLocation { .. } => "ModuleInitLocation",
+            ServerPackets::ModuleInitElement { .. } => "ModuleInitElement",
+            ServerPackets::ModuleAcceptUrl { .. } => "ModuleAcceptUrl",
+            ServerPackets::ModuleAcceptExtension { .. } => "ModuleAcceptExtension",
+            ServerPackets::ModuleAcceptedProtocols { .. } => "ModuleAcceptedProtocols",
+            ServerPackets::ModuleAcceptedExtensions { .. } => "ModuleAcceptedExtensions",
+            ServerPackets::GetDefaultLocation { .. } => "GetDefaultLocation",
+            ServerPackets::LocationGetName { .. } => "LocationGetName",
+            ServerPackets::LocationSetName { .. } => "LocationSetName",
+            ServerPackets::LocationGetDesc { .. } => "LocationGetDesc",
+            ServerPackets::LocationSetDesc { .. } => "LocationSetDesc",
+            ServerPackets::LocationGetInfo { .. } => "LocationGetInfo",
+            ServerPackets::CreateElement { .. } => "CreateElement",
+            ServerPackets::LoadElementInfo { .. } => "LoadElementInfo",
+            ServerPackets::MoveElement { .. } => "MoveElement",
+            ServerPackets::DestroyElement { .. } => "DestroyElement",
+            ServerPackets::ElementGetName { .. } => "ElementGetName",
+            ServerPackets::ElementSetName { .. } => "ElementSetName",
+            ServerPackets::ElementGetDesc { .. } => "ElementGetDesc",
+            ServerPackets::ElementSetDesc { .. } => "ElementSetDesc",
+            ServerPackets::ElementGetMeta { .. } => "ElementGetMeta",
+            ServerPackets::ElementSetMeta { .. } => "ElementSetMeta",
+            ServerPackets::ElementGetUrl { .. } => "ElementGetUrl",
+            ServerPackets::ElementSetUrl { .. } => "ElementSetUrl",
+            ServerPackets::ElementGetElementData { .. } => "ElementGetElementData",
+            ServerPackets::ElementSetElementData { .. } => "ElementSetElementData",
+            ServerPackets::ElementGetModuleData { .. } => "ElementGetModuleData",
+            ServerPackets::ElementSetModuleData { .. } => "ElementSetModuleData",
+            ServerPackets::ElementGetModule { .. } => "ElementGetModule",
+            ServerPackets::ElementSetModule { .. } => "ElementSetModule",
+            ServerPackets::ElementGetStatuses { .. } => "ElementGetStatuses",
+            ServerPackets::ElementSetStatuses { .. } => "ElementSetStatuses",
+            ServerPackets::ElementGetStatus { .. } => "ElementGetStatus",
+            ServerPackets::ElementSetStatus { .. } => "ElementSetStatus",
+            ServerPackets::ElementGetData { .. } => "ElementGetData",
+            ServerPackets::ElementSetData { .. } => "ElementSetData",
+            ServerPackets::ElementGetProgress { .. } => "ElementGetProgress",
+            ServerPackets::ElementSetProgress { .. } => "ElementSetProgress",
+            ServerPackets::ElementGetShouldSave { .. } => "ElementGetShouldSave",
+            ServerPackets::ElementSetShouldSave { .. } => "ElementSetShouldSave",
+            ServerPackets::ElementGetEnabled { .. } => "ElementGetEnabled",
+            ServerPackets::ElementSetEnabled { .. } => "ElementSetEnabled",
+            ServerPackets::ElementIsError { .. } => "ElementIsError",
+            ServerPackets::ElementResolvModule { .. } => "ElementResolvModule",
+            ServerPackets::ElementWait { .. } => "ElementWait",
+            ServerPackets::ElementGetInfo { .. } => "ElementGetInfo",
+            ServerPackets::ElementNotify { .. } => "ElementNotify",
+            ServerPackets::ElementEmit { .. } => "ElementEmit",
+            ServerPackets::ElementSubscribe { .. } => "ElementSubscribe",
+            ServerPackets::ElementUnSubscribe { .. } => "ElementUnSubscribe",
+            ServerPackets::CreateLocation { .. } => "CreateLocation",
+            ServerPackets::LoadLocationInfo { .. } => "LoadLocationInfo",
+            ServerPackets::GetLocationsLen { .. } => "GetLocationsLen",
+            ServerPackets::GetLocations { .. } => "GetLocations",
+            ServerPackets::DestroyLocation { .. } => "DestroyLocation",
+            ServerPackets::MoveLocation { .. } => "MoveLocation",
+            ServerPackets::LocationGetPath { .. } => "LocationGetPath",
+            ServerPackets::LocationSetPath { .. } => "LocationSetPath",
+            ServerPackets::LocationGetShouldSave { .. } => "LocationGetShouldSave",
+            ServerPackets::LocationSetShouldSave { .. } => "LocationSetShouldSave",
+            ServerPackets::LocationGetElementsLen { .. } => "LocationGetElementsLen",
+            ServerPackets::LocationGetElements { .. } => "LocationGetElements",
+            ServerPackets::LocationGetModule { .. } => "LocationGetModule",
+            ServerPackets::LocationSetModule { .. } => "LocationSetModule",
+            ServerPackets::LocationGetSettings { .. } => "LocationGetSettings",
+            ServerPackets::LocationSetSettings { .. } => "LocationSetSettings",
+            ServerPackets::LocationGetModuleSettings { .. } => "LocationGetModuleSettings",
+            ServerPackets::LocationSetModuleSettings { .. } => "LocationSetModuleSettings",
+            ServerPackets::LocationGetStatuses { .. } => "LocationGetStatuses",
+            ServerPackets::LocationSetStatuses { .. } => "LocationSetStatuses",
+            ServerPackets::LocationGetStatus { .. } => "LocationGetStatus",
+            ServerPackets::LocationSetStatus { .. } => "LocationSetStatus",
+            ServerPackets::LocationGetProgress { .. } => "LocationGetProgress",
+            ServerPackets::LocationSetProgress { .. } => "LocationSetProgress",
+            ServerPackets::LocationIsEnabled { .. } => "LocationIsEnabled",
+            ServerPackets::LocationSetEnabled { .. } => "LocationSetEnabled",
+            ServerPackets::LocationIsError { .. } => "LocationIsError",
+            ServerPackets::LocationNotify { .. } => "LocationNotify",
+            ServerPackets::LocationEmit { .. } => "LocationEmit",
+            ServerPackets::LocationSubscribe { .. } => "LocationSubscribe",
+            ServerPackets::LocationUnSubscribe { .. } => "LocationUnSubscribe",
+            ServerPackets::GetVersion { .. } => "GetVersion",
+            ServerPackets::GetVersionText { .. } => "GetVersionText",
+            ServerPackets::SetEventFilter { .. } => "SetEventFilter",
+            ServerPackets::EventsSince { .. } => "EventsSince",
+            ServerPackets::AckEvent { .. } => "AckEvent",
+            ServerPackets::Connect { .. } => "Connect",
+            ServerPackets::Disconnect => "Disconnect",
+            ServerPackets::Ping { .. } => "Ping",
+            ServerPackets::Cancel { .. } => "Cancel",
+            ServerPackets::Batch { .. } => "Batch",
+            ServerPackets::GetTreeSnapshot { .. } => "GetTreeSnapshot",
+            ServerPackets::QueryElements { .. } => "QueryElements",
+            ServerPackets::DataOpen { .. } => "DataOpen",
+            ServerPackets::DataRead { .. } => "DataRead",
+            ServerPackets::DataWrite { .. } => "DataWrite",
+            ServerPackets::DataClose { .. } => "DataClose",
+            ServerPackets::Shm { .. } => "Shm",
+            ServerPackets::Tick => "Tick",
+        }
+    }
+
+    /// Only reads, running it again gives the same response and changes nothing
+    pub fn is_idempotent(&self) -> bool {
+        match self {
+            ServerPackets::Batch { requests, .. } => {
+                requests.iter().all(|request| request.is_idempotent())
+            }
+            packet => matches!(
+                packet,
+                ServerPackets::FindModule { .. }
+                    | ServerPackets::GetActionsLen { .. }
+                    | ServerPackets::GetActions { .. }
+                    | ServerPackets::GetModulesLen { .. }
+                    | ServerPackets::GetModules { .. }
+                    | ServerPackets::ModuleGetName { .. }
+                    | ServerPackets::ModuleGetDefaultName { .. }
+                    | ServerPackets::ModuleGetUid { .. }
+                    | ServerPackets::ModuleGetVersion { .. }
+                    | ServerPackets::ModuleSupportedVersions { .. }
+                    | ServerPackets::ModuleGetDesc { .. }
+                    | ServerPackets::ModuleGetDefaultDesc { .. }
+                    | ServerPackets::ModuleGetProxy { .. }
+                    | ServerPackets::ModuleGetSettings { .. }
+                    | ServerPackets::ModuleGetElementSettings { .. }
+                    | ServerPackets::ModuleGetLocationSettings { .. }
+                    | ServerPackets::ModuleAcceptUrl { .. }
+                    | ServerPackets::ModuleAcceptExtension { .. }
+                    | ServerPackets::ModuleAcceptedProtocols { .. }
+                    | ServerPackets::ModuleAcceptedExtensions { .. }
+                    | ServerPackets::GetDefaultLocation { .. }
+                    | ServerPackets::LocationGetName { .. }
+                    | ServerPackets::LocationGetDesc { .. }
+                    | ServerPackets::LocationGetInfo { .. }
+                    | ServerPackets::ElementGetName { .. }
+                    | ServerPackets::ElementGetDesc { .. }
+                    | ServerPackets::ElementGetMeta { .. }
+                    | ServerPackets::ElementGetUrl { .. }
+                    | ServerPackets::ElementGetElementData { .. }
+                    | ServerPackets::ElementGetModuleData { .. }
+                    | ServerPackets::ElementGetModule { .. }
+                    | ServerPackets::ElementGetStatuses { .. }
+                    | ServerPackets::ElementGetStatus { .. }
+                    | ServerPackets::ElementGetData { .. }
+                    | ServerPackets::ElementGetProgress { .. }
+                    | ServerPackets::ElementGetShouldSave { .. }
+                    | ServerPackets::ElementGetEnabled { .. }
+                    | ServerPackets::ElementIsError { .. }
+                    | ServerPackets::ElementGetInfo { .. }
+                    | ServerPackets::GetLocationsLen { .. }
+                    | ServerPackets::GetLocations { .. }
+                    | ServerPackets::LocationGetPath { .. }
+                    | ServerPackets::LocationGetShouldSave { .. }
+                    | ServerPackets::LocationGetElementsLen { .. }
+                    | ServerPackets::LocationGetElements { .. }
+                    | ServerPackets::LocationGetModule { .. }
+                    | ServerPackets::LocationGetSettings { .. }
+                    | ServerPackets::LocationGetModuleSettings { .. }
+                    | ServerPackets::LocationGetStatuses { .. }
+                    | ServerPackets::LocationGetStatus { .. }
+                    | ServerPackets::LocationGetProgress { .. }
+                    | ServerPackets::LocationIsEnabled { .. }
+                    | ServerPackets::LocationIsError { .. }
+                    | ServerPackets::GetVersion { .. }
+                    | ServerPackets::GetVersionText { .. }
+                    | ServerPackets::EventsSince { .. }
+                    | ServerPackets::GetTreeSnapshot { .. }
+                    | ServerPackets::QueryElements { .. }
+            ),
+        }
+    }
+}
+
 pub type Actions = Vec<(String, ModuleId, Vec<(String, Value)>)>;
 
+#[derive(Clone, Debug, Bytes)]
+pub struct Welcome {
+    pub version: u64,
+    pub features: Vec<String>,
+    /// sequence number of the last session event
+    pub event_seq: u64,
+}
+
+/// Something that happened to the daemon
+#[derive(Clone, Debug, Bytes)]
+pub enum DaemonEvent {
+    ClientJoined { name: String, addr: String },
+    ClientLeft { name: String, addr: String },
+}
+
+/// Response for `ServerPackets::EventsSince`
+#[derive(Clone, Debug, Bytes)]
+pub enum EventReplay {
+    /// The missed events with their sequence number
+    Events(Vec<(u64, SessionEvent)>),
+    /// The events are not kept anymore, everything should be fetched again
+    /// Has the sequence number of the last event
+    ResyncRequired(u64),
+}
+
+/// Response for `ServerPackets::DataOpen`
+#[derive(Clone, Debug, Bytes)]
+pub struct DataStreamInfo {
+    pub stream: u64,
+    /// Size of the data when it was opened
+    pub len: u64,
+    /// Most bytes of one `ServerPackets::DataRead` or `ServerPackets::DataWrite`
+    pub chunk: u32,
+    /// Most bytes that can be requested or written before their responses arrive
+    pub window: u32,
+}
+
+/// What `ServerPackets::GetTreeSnapshot` reads for every location and element
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Bytes)]
+pub enum TreeField {
+    Name,
+    Desc,
+    Enabled,
+    Progress,
+    Status,
+    IsError,
+}
+
+/// The selected fields, `None` when is not selected or cannot be read
+#[derive(Clone, Debug, Default, Bytes)]
+pub struct TreeFields {
+    pub name: Option<String>,
+    pub desc: Option<String>,
+    pub enabled: Option<bool>,
+    pub progress: Option<f32>,
+    pub status: Option<usize>,
+    pub is_error: Option<bool>,
+}
+
+#[derive(Clone, Debug, Bytes)]
+pub struct ElementNode {
+    pub id: ElementId,
+    pub fields: TreeFields,
+}
+
+/// A location with its elements and sub locations
+#[derive(Clone, Debug, Bytes)]
+pub struct LocationNode {
+    pub id: LocationId,
+    pub fields: TreeFields,
+    pub elements: Vec<ElementNode>,
+    pub locations: Vec<LocationNode>,
+    /// the sub locations were not included because the depth was reached
+    pub truncated: bool,
+}
+
 // recv
 #[derive(Clone, Debug, Bytes)]
 pub enum ClientPackets {
+    /// Response for `ServerPackets::Hello`
+    /// Needs to stay the first variant with the same layout, so every version can decode it
+    Welcome(u128, Result<Welcome, SessionError>),
+    /// The request was refused before being handled
+    Error(u128, SessionError),
+
     LoadModule(u128, Result<ModuleId, SessionError>),
     RemoveModule(u128, Result<(), SessionError>),
     LoadModuleInfo(u128, Result<ModuleId, SessionError>),
@@ -633,13 +1151,32 @@ pub enum ClientPackets {
     GetVersion(u128, Result<u64, SessionError>),
     GetVersionText(u128, Result<String, SessionError>),
 
-    NewSessionEvent(SessionEvent),
+    SetEventFilter(u128, Result<(), SessionError>),
+    EventsSince(u128, Result<EventReplay, SessionError>),
+    Connect(u128, Result<u64, SessionError>),
+    Pong(u128),
+    Batch(u128, Vec<ClientPackets>),
+    GetTreeSnapshot(u128, Result<LocationNode, SessionError>),
+    QueryElements(u128, Result<QueryPage, SessionError>),
+    DataOpen(u128, Result<DataStreamInfo, SessionError>),
+    DataRead(u128, Result<Vec<u8>, SessionError>),
+    DataWrite(u128, Result<u32, SessionError>),
+    DataClose(u128, Result<(), SessionError>),
+    /// A packet that is in shared memory, is read instead of this one
+    /// The id is the id of the packet in the blob
+    Shm(u128, ShmBlob),
+
+    /// Sequence number of the event, sequence number of the previous event sent to the client, the event
+    NewSessionEvent(u64, u64, SessionEvent),
+    DaemonEvent(DaemonEvent),
 }
 
 impl ClientPackets {
     pub fn id(&self) -> u128 {
         match self {
-            ClientPackets::NewSessionEvent(_) => 0,
+            ClientPackets::NewSessionEvent(..) => 0,
+            ClientPackets::Welcome(id, _) => *id,
+            ClientPackets::Error(id, _) => *id,
             ClientPackets::GetDefaultLocation(id, _) => *id,
             ClientPackets::LocationGetName(id, _) => *id,
             ClientPackets::LocationSetName(id, _) => *id,
@@ -731,6 +1268,19 @@ impl ClientPackets {
             ClientPackets::LoadLocationInfo(id, _) => *id,
             ClientPackets::GetVersion(id, _) => *id,
             ClientPackets::GetVersionText(id, _) => *id,
+            ClientPackets::SetEventFilter(id, _) => *id,
+            ClientPackets::EventsSince(id, _) => *id,
+            ClientPackets::Connect(id, _) => *id,
+            ClientPackets::Pong(id) => *id,
+            ClientPackets::Batch(id, _) => *id,
+            ClientPackets::GetTreeSnapshot(id, _) => *id,
+            ClientPackets::QueryElements(id, _) => *id,
+            ClientPackets::DataOpen(id, _) => *id,
+            ClientPackets::DataRead(id, _) => *id,
+            ClientPackets::DataWrite(id, _) => *id,
+            ClientPackets::DataClose(id, _) => *id,
+            ClientPackets::Shm(id, _) => *id,
+            ClientPackets::DaemonEvent(_) => 0,
             ClientPackets::ModuleGetLocationSettings(id, _) => *id,
             ClientPackets::ModuleSetLocationSettings(id, _) => *id,
             ClientPackets::ElementIsError(id, _) => *id,
@@ -752,3 +1302,54 @@ impl ClientPackets {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element_id() -> ElementId {
+        ElementId {
+            uid: 0,
+            location_id: LocationId(Vec::new()),
+        }
+    }
+
+    #[test]
+    fn reads_are_idempotent() {
+        let get = ServerPackets::ElementGetName {
+            id: 1,
+            element_id: element_id(),
+        };
+        let set = ServerPackets::ElementSetName {
+            id: 2,
+            element_id: element_id(),
+            to: "name".to_string(),
+        };
+
+        assert!(get.is_idempotent());
+        assert!(!set.is_idempotent());
+    }
+
+    #[test]
+    fn batch_is_idempotent_if_every_request_is() {
+        let get = ServerPackets::ElementGetName {
+            id: 0,
+            element_id: element_id(),
+        };
+        let destroy = ServerPackets::DestroyElement {
+            id: 0,
+            element_id: element_id(),
+        };
+
+        let reads = ServerPackets::Batch {
+            id: 1,
+            requests: vec![get.clone(), get.clone()],
+        };
+        let mixed = ServerPackets::Batch {
+            id: 2,
+            requests: vec![get, destroy],
+        };
+        assert!(reads.is_idempotent());
+        assert!(!mixed.is_idempotent());
+    }
+}
@@ -0,0 +1,392 @@
+use std::cmp::Ordering;
+
+use bytes_kman::prelude::*;
+use muzzman_lib::prelude::{ElementId, LocationId, ModuleId};
+
+/// How the elements of a query are ordered
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Bytes)]
+pub enum ElementSort {
+    /// The order of the locations and of the elements in them
+    Tree,
+    Name,
+    Progress,
+    Status,
+}
+
+/// Which elements `ServerPackets::QueryElements` returns
+/// `None` fields don't filter anything
+#[derive(Clone, Debug, Bytes)]
+pub struct ElementQuery {
+    /// Only the elements of this location and of everything inside it, `None` is the whole session
+    pub root: Option<LocationId>,
+    pub status: Option<usize>,
+    pub enabled: Option<bool>,
+    pub is_error: Option<bool>,
+    pub module: Option<ModuleId>,
+    /// Part of the url
+    pub url: Option<String>,
+    /// Pattern of the name, `*` matches any characters and `?` one character
+    pub name: Option<String>,
+    pub sort: ElementSort,
+    pub descending: bool,
+}
+
+impl Default for ElementQuery {
+    fn default() -> Self {
+        Self {
+            root: None,
+            status: None,
+            enabled: None,
+            is_error: None,
+            module: None,
+            url: None,
+            name: None,
+            sort: ElementSort::Tree,
+            descending: false,
+        }
+    }
+}
+
+impl ElementQuery {
+    /// Every element
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    pub fn root(mut self, location_id: LocationId) -> Self {
+        self.root = Some(location_id);
+        self
+    }
+
+    pub fn status(mut self, status: usize) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = Some(enabled);
+        self
+    }
+
+    pub fn is_error(mut self, is_error: bool) -> Self {
+        self.is_error = Some(is_error);
+        self
+    }
+
+    pub fn module(mut self, module_id: ModuleId) -> Self {
+        self.module = Some(module_id);
+        self
+    }
+
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    pub fn name(mut self, pattern: impl Into<String>) -> Self {
+        self.name = Some(pattern.into());
+        self
+    }
+
+    pub fn sort(mut self, sort: ElementSort, descending: bool) -> Self {
+        self.sort = sort;
+        self.descending = descending;
+        self
+    }
+}
+
+/// What an element is sorted by, the value of the field of `ElementSort`
+#[derive(Clone, Debug, PartialEq, Bytes)]
+pub enum SortKey {
+    Tree,
+    Name(String),
+    Progress(f32),
+    Status(usize),
+}
+
+impl SortKey {
+    fn compare(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (SortKey::Name(a), SortKey::Name(b)) => a.cmp(b),
+            (SortKey::Progress(a), SortKey::Progress(b)) => a.total_cmp(b),
+            (SortKey::Status(a), SortKey::Status(b)) => a.cmp(b),
+            _ => Ordering::Equal,
+        }
+    }
+}
+
+/// The last element of a page, the next page starts after it
+/// Elements added or removed between the pages don't make the next page skip or repeat elements
+#[derive(Clone, Debug, Bytes)]
+pub struct QueryCursor {
+    pub key: SortKey,
+    pub element_id: ElementId,
+}
+
+/// Response for `ServerPackets::QueryElements`
+#[derive(Clone, Debug, Bytes)]
+pub struct QueryPage {
+    pub elements: Vec<ElementId>,
+    /// Cursor of the next page, `None` if this is the last page
+    pub next: Option<QueryCursor>,
+    /// How many elements match the query
+    pub total: u64,
+}
+
+/// Order of the locations and of the elements in them, a location is before its sub locations
+fn tree_order(a: &ElementId, b: &ElementId) -> Ordering {
+    a.location_id
+        .0
+        .cmp(&b.location_id.0)
+        .then_with(|| a.uid.cmp(&b.uid))
+}
+
+/// Order of the elements of a query, equal keys are in the order of the tree
+fn query_order(
+    query: &ElementQuery,
+    (a, a_key): (&ElementId, &SortKey),
+    (b, b_key): (&ElementId, &SortKey),
+) -> Ordering {
+    let order = a_key.compare(b_key).then_with(|| tree_order(a, b));
+    if query.descending {
+        order.reverse()
+    } else {
+        order
+    }
+}
+
+/// Sorts the elements that matched the query and takes at most `limit` after `cursor`
+pub fn page(
+    query: &ElementQuery,
+    mut matched: Vec<(ElementId, SortKey)>,
+    cursor: Option<&QueryCursor>,
+    limit: usize,
+) -> QueryPage {
+    matched.sort_by(|(a, a_key), (b, b_key)| query_order(query, (a, a_key), (b, b_key)));
+
+    let total = matched.len() as u64;
+    let start = match cursor {
+        Some(cursor) => matched.partition_point(|(element_id, key)| {
+            query_order(query, (element_id, key), (&cursor.element_id, &cursor.key))
+                != Ordering::Greater
+        }),
+        None => 0,
+    };
+    let end = (start + limit.max(1)).min(matched.len());
+
+    let next = (end < matched.len()).then(|| {
+        let (element_id, key) = matched[end - 1].clone();
+        QueryCursor { key, element_id }
+    });
+    QueryPage {
+        elements: matched
+            .drain(start..end)
+            .map(|(element_id, _)| element_id)
+            .collect(),
+        next,
+        total,
+    }
+}
+
+/// `*` matches any characters and `?` one character
+pub fn matches_pattern(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let text = text.chars().collect::<Vec<char>>();
+
+    let (mut p, mut t) = (0, 0);
+    // where the last `*` was and the text position that it matched until
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(path: &[usize], uid: usize) -> ElementId {
+        ElementId {
+            uid: uid as _,
+            location_id: LocationId(path.iter().map(|index| *index as _).collect()),
+        }
+    }
+
+    fn named(elements: &[(ElementId, &str)]) -> Vec<(ElementId, SortKey)> {
+        elements
+            .iter()
+            .map(|(element_id, name)| (element_id.clone(), SortKey::Name(name.to_string())))
+            .collect()
+    }
+
+    /// Every page until the last one
+    fn pages(
+        query: &ElementQuery,
+        matched: &[(ElementId, SortKey)],
+        limit: usize,
+    ) -> Vec<Vec<ElementId>> {
+        let mut pages = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = page(query, matched.to_vec(), cursor.as_ref(), limit);
+            pages.push(page.elements);
+            match page.next {
+                Some(next) => cursor = Some(next),
+                None => return pages,
+            }
+        }
+    }
+
+    #[test]
+    fn tree_order_is_locations_before_their_sub_locations() {
+        let matched = vec![
+            (element(&[1], 0), SortKey::Tree),
+            (element(&[0, 0], 0), SortKey::Tree),
+            (element(&[0], 1), SortKey::Tree),
+            (element(&[0], 0), SortKey::Tree),
+        ];
+        let page = page(&ElementQuery::all(), matched, None, 10);
+
+        assert_eq!(
+            page.elements,
+            vec![
+                element(&[0], 0),
+                element(&[0], 1),
+                element(&[0, 0], 0),
+                element(&[1], 0)
+            ]
+        );
+        assert!(page.next.is_none());
+        assert_eq!(page.total, 4);
+    }
+
+    #[test]
+    fn sorts_by_key_then_by_tree() {
+        let matched = named(&[
+            (element(&[0], 0), "b"),
+            (element(&[0], 1), "a"),
+            (element(&[0], 2), "b"),
+        ]);
+        let query = ElementQuery::all().sort(ElementSort::Name, false);
+
+        assert_eq!(
+            page(&query, matched, None, 10).elements,
+            vec![element(&[0], 1), element(&[0], 0), element(&[0], 2)]
+        );
+    }
+
+    #[test]
+    fn descending_reverses_the_order() {
+        let matched = named(&[
+            (element(&[0], 0), "b"),
+            (element(&[0], 1), "a"),
+            (element(&[0], 2), "b"),
+        ]);
+        let query = ElementQuery::all().sort(ElementSort::Name, true);
+
+        assert_eq!(
+            page(&query, matched, None, 10).elements,
+            vec![element(&[0], 2), element(&[0], 0), element(&[0], 1)]
+        );
+    }
+
+    #[test]
+    fn pages_have_every_element_once() {
+        let matched = (0..7)
+            .map(|uid| (element(&[0], uid), SortKey::Status(uid % 2)))
+            .collect::<Vec<_>>();
+        let query = ElementQuery::all().sort(ElementSort::Status, false);
+
+        let pages = pages(&query, &matched, 3);
+        assert_eq!(
+            pages.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![3, 3, 1]
+        );
+        assert_eq!(pages.concat(), page(&query, matched, None, 10).elements);
+    }
+
+    #[test]
+    fn removed_element_does_not_skip_the_next_page() {
+        let mut matched = named(&[
+            (element(&[0], 0), "a"),
+            (element(&[0], 1), "b"),
+            (element(&[0], 2), "c"),
+            (element(&[0], 3), "d"),
+        ]);
+        let query = ElementQuery::all().sort(ElementSort::Name, false);
+
+        let first = page(&query, matched.clone(), None, 2);
+        assert_eq!(first.elements, vec![element(&[0], 0), element(&[0], 1)]);
+
+        matched.remove(0);
+        let second = page(&query, matched, first.next.as_ref(), 2);
+        assert_eq!(second.elements, vec![element(&[0], 2), element(&[0], 3)]);
+        assert!(second.next.is_none());
+    }
+
+    #[test]
+    fn added_element_does_not_repeat_the_previous_page() {
+        let mut matched = named(&[
+            (element(&[0], 0), "b"),
+            (element(&[0], 1), "c"),
+            (element(&[0], 2), "d"),
+        ]);
+        let query = ElementQuery::all().sort(ElementSort::Name, false);
+
+        let first = page(&query, matched.clone(), None, 2);
+        assert_eq!(first.elements, vec![element(&[0], 0), element(&[0], 1)]);
+
+        matched.push((element(&[0], 3), SortKey::Name("a".to_string())));
+        let second = page(&query, matched, first.next.as_ref(), 2);
+        assert_eq!(second.elements, vec![element(&[0], 2)]);
+        assert_eq!(second.total, 4);
+    }
+
+    #[test]
+    fn pattern_without_wildcards_is_exact() {
+        assert!(matches_pattern("file.zip", "file.zip"));
+        assert!(!matches_pattern("file.zip", "file.zip.part"));
+        assert!(!matches_pattern("file.zip", "file"));
+    }
+
+    #[test]
+    fn question_mark_is_one_character() {
+        assert!(matches_pattern("file.?ip", "file.zip"));
+        assert!(!matches_pattern("file.?ip", "file.ip"));
+    }
+
+    #[test]
+    fn star_is_any_characters() {
+        assert!(matches_pattern("*", ""));
+        assert!(matches_pattern("*.zip", "file.zip"));
+        assert!(matches_pattern("f*e*", "file.zip"));
+        assert!(matches_pattern("*.*.*", "a.b.c"));
+        assert!(!matches_pattern("*.zip", "file.tar"));
+    }
+
+    #[test]
+    fn star_backtracks() {
+        assert!(matches_pattern("*ab", "aab"));
+        assert!(matches_pattern("a*b?d", "axxbcbxd"));
+        assert!(!matches_pattern("a*b?d", "axxbd"));
+    }
+}
@@ -0,0 +1,809 @@
+use std::{collections::HashMap, sync::mpsc::Receiver};
+
+use bytes_kman::TBytes;
+use muzzman_lib::prelude::*;
+
+use crate::{
+    packets::{LocationNode, ServerPackets, TreeField, TreeFields},
+    tree::{element_field_requests, fields_from_responses, location_field_requests},
+    TDaemonSession,
+};
+
+/// Every field is replicated
+const FIELDS: [TreeField; 6] = [
+    TreeField::Name,
+    TreeField::Desc,
+    TreeField::Enabled,
+    TreeField::Progress,
+    TreeField::Status,
+    TreeField::IsError,
+];
+
+#[derive(Clone, Debug)]
+pub struct ReplicaLocation {
+    pub id: LocationId,
+    pub fields: TreeFields,
+    pub locations: Vec<LocationId>,
+    pub elements: Vec<ElementId>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReplicaElement {
+    pub id: ElementId,
+    pub fields: TreeFields,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReplicaModule {
+    pub id: ModuleId,
+    pub name: Option<String>,
+    pub desc: Option<String>,
+}
+
+/// Client side copy of the session, reads are served without a round trip
+/// Is initialised from a tree snapshot and kept current by applying the session events in `sync`
+/// When session events were lost everything is fetched again
+/// Fields that change without a session event, like `is_error`, can be fetched with `refresh_element`
+pub struct Replica {
+    session: Box<dyn TDaemonSession>,
+    events: Receiver<SessionEvent>,
+    /// `TDaemonSession::resyncs` when everything was fetched
+    resyncs: u64,
+    root: LocationId,
+    // ids are not hashable, so their bytes are the keys
+    locations: HashMap<Vec<u8>, ReplicaLocation>,
+    elements: HashMap<Vec<u8>, ReplicaElement>,
+    modules: HashMap<Vec<u8>, ReplicaModule>,
+}
+
+impl Replica {
+    pub fn new(session: Box<dyn TDaemonSession>) -> Result<Self, SessionError> {
+        // subscribed before the snapshot so nothing is missed, events that are already in it are harmless
+        let events = session.subscribe();
+        let root = session.get_default_location()?.id();
+
+        let mut replica = Self {
+            resyncs: session.resyncs(),
+            session,
+            events,
+            root,
+            locations: HashMap::new(),
+            elements: HashMap::new(),
+            modules: HashMap::new(),
+        };
+        replica.resync()?;
+        Ok(replica)
+    }
+
+    /// Fetches everything again
+    pub fn resync(&mut self) -> Result<(), SessionError> {
+        self.resyncs = self.session.resyncs();
+        // the snapshot has everything that they changed
+        while self.events.try_recv().is_ok() {}
+
+        self.locations.clear();
+        self.elements.clear();
+        self.modules.clear();
+
+        let root = self
+            .session
+            .get_tree_snapshot(&self.root, u32::MAX, FIELDS.to_vec())?;
+        self.insert_tree(root);
+
+        let len = self.session.get_modules_len()?;
+        for module in self.session.get_modules(0..len)? {
+            self.fetch_module(module.id());
+        }
+        Ok(())
+    }
+
+    /// Applies the session events that arrived, resyncs if some were lost
+    pub fn sync(&mut self) -> Result<(), SessionError> {
+        if self.session.resyncs() != self.resyncs {
+            log::warn!("Session events were lost, replica is fetched again");
+            return self.resync();
+        }
+
+        while let Ok(event) = self.events.try_recv() {
+            self.apply(event);
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, event: SessionEvent) {
+        match event {
+            SessionEvent::NewElement(id) => self.fetch_element(id),
+            SessionEvent::NewLocation(id) => self.fetch_location(id),
+            SessionEvent::NewModule(id) => self.fetch_module(id),
+            SessionEvent::DestroyedElement(id) => {
+                self.remove_element(&id);
+            }
+            SessionEvent::DestroyedLocation(id) => self.remove_location(&id),
+            SessionEvent::DestroyedModule(id) => {
+                self.modules.remove(&id.to_bytes());
+            }
+            SessionEvent::ElementIdChanged(last, new) => {
+                if let Some(mut element) = self.remove_element(&last) {
+                    element.id = new;
+                    self.insert_element(element);
+                }
+            }
+            // the ids of everything inside changed too
+            SessionEvent::LocationIdChanged(last, new) => {
+                self.remove_location(&last);
+                self.fetch_location(new);
+            }
+            SessionEvent::ModuleIdChanged(last, new) => {
+                if let Some(mut module) = self.modules.remove(&last.to_bytes()) {
+                    module.id = new;
+                    self.modules.insert(new.to_bytes(), module);
+                }
+            }
+            // the changes have the new value
+            SessionEvent::ElementNameChanged(id, name) => {
+                if let Some(fields) = self.element_fields(&id) {
+                    fields.name = Some(name);
+                }
+            }
+            SessionEvent::ElementDescChanged(id, desc) => {
+                if let Some(fields) = self.element_fields(&id) {
+                    fields.desc = Some(desc);
+                }
+            }
+            SessionEvent::ElementProgressChanged(id, progress) => {
+                if let Some(fields) = self.element_fields(&id) {
+                    fields.progress = Some(progress);
+                }
+            }
+            SessionEvent::ElementStatusChanged(id, status) => {
+                if let Some(fields) = self.element_fields(&id) {
+                    fields.status = Some(status);
+                }
+            }
+            SessionEvent::ElementEnabledChanged(id, enabled) => {
+                if let Some(fields) = self.element_fields(&id) {
+                    fields.enabled = Some(enabled);
+                }
+            }
+            SessionEvent::LocationNameChanged(id, name) => {
+                if let Some(fields) = self.location_fields(&id) {
+                    fields.name = Some(name);
+                }
+            }
+            SessionEvent::LocationDescChanged(id, desc) => {
+                if let Some(fields) = self.location_fields(&id) {
+                    fields.desc = Some(desc);
+                }
+            }
+            SessionEvent::LocationProgressChanged(id, progress) => {
+                if let Some(fields) = self.location_fields(&id) {
+                    fields.progress = Some(progress);
+                }
+            }
+            SessionEvent::LocationStatusChanged(id, status) => {
+                if let Some(fields) = self.location_fields(&id) {
+                    fields.status = Some(status);
+                }
+            }
+            SessionEvent::LocationEnabledChanged(id, enabled) => {
+                if let Some(fields) = self.location_fields(&id) {
+                    fields.enabled = Some(enabled);
+                }
+            }
+        }
+    }
+
+    fn element_fields(&mut self, element_id: &ElementId) -> Option<&mut TreeFields> {
+        Some(&mut self.elements.get_mut(&element_id.to_bytes())?.fields)
+    }
+
+    fn location_fields(&mut self, location_id: &LocationId) -> Option<&mut TreeFields> {
+        Some(&mut self.locations.get_mut(&location_id.to_bytes())?.fields)
+    }
+
+    /// Every field in one round trip
+    fn fetch_fields(&self, requests: Vec<ServerPackets>) -> Option<TreeFields> {
+        match self.session.batch(requests) {
+            Ok(responses) => Some(fields_from_responses(responses)),
+            Err(err) => {
+                log::debug!("Cannot fetch fields: {err:?}");
+                None
+            }
+        }
+    }
+
+    /// Fetches the fields of the element again
+    pub fn refresh_element(&mut self, element_id: &ElementId) {
+        let Some(fields) = self.fetch_fields(element_field_requests(element_id, &FIELDS)) else {
+            return;
+        };
+        if let Some(element) = self.elements.get_mut(&element_id.to_bytes()) {
+            element.fields = fields;
+        }
+    }
+
+    /// Fetches the fields of the location again
+    pub fn refresh_location(&mut self, location_id: &LocationId) {
+        let Some(fields) = self.fetch_fields(location_field_requests(location_id, &FIELDS)) else {
+            return;
+        };
+        if let Some(location) = self.locations.get_mut(&location_id.to_bytes()) {
+            location.fields = fields;
+        }
+    }
+
+    fn fetch_element(&mut self, element_id: ElementId) {
+        let Some(fields) = self.fetch_fields(element_field_requests(&element_id, &FIELDS)) else {
+            return;
+        };
+        // was destroyed before it could be fetched
+        if fields.name.is_none() {
+            return;
+        }
+        self.insert_element(ReplicaElement {
+            id: element_id,
+            fields,
+        });
+    }
+
+    fn fetch_location(&mut self, location_id: LocationId) {
+        match self
+            .session
+            .get_tree_snapshot(&location_id, u32::MAX, FIELDS.to_vec())
+        {
+            Ok(node) => self.insert_tree(node),
+            Err(err) => log::debug!("Cannot fetch location: {location_id:?}: {err:?}"),
+        }
+    }
+
+    fn fetch_module(&mut self, module_id: ModuleId) {
+        let module = ReplicaModule {
+            id: module_id,
+            name: self.session.module_get_name(&module_id).ok(),
+            desc: self.session.module_get_desc(&module_id).ok(),
+        };
+        self.modules.insert(module_id.to_bytes(), module);
+    }
+
+    fn insert_element(&mut self, element: ReplicaElement) {
+        if let Some(location) = self.locations.get_mut(&element.id.location_id.to_bytes()) {
+            if !location.elements.contains(&element.id) {
+                location.elements.push(element.id.clone());
+            }
+        }
+        self.elements.insert(element.id.to_bytes(), element);
+    }
+
+    fn remove_element(&mut self, element_id: &ElementId) -> Option<ReplicaElement> {
+        if let Some(location) = self.locations.get_mut(&element_id.location_id.to_bytes()) {
+            location.elements.retain(|id| id != element_id);
+        }
+        self.elements.remove(&element_id.to_bytes())
+    }
+
+    fn insert_tree(&mut self, node: LocationNode) {
+        if let Some(parent) = parent(&node.id).and_then(|id| self.locations.get_mut(&id.to_bytes()))
+        {
+            if !parent.locations.contains(&node.id) {
+                parent.locations.push(node.id.clone());
+            }
+        }
+
+        self.locations.insert(
+            node.id.to_bytes(),
+            ReplicaLocation {
+                id: node.id,
+                fields: node.fields,
+                locations: node
+                    .locations
+                    .iter()
+                    .map(|location| location.id.clone())
+                    .collect(),
+                elements: Vec::new(),
+            },
+        );
+
+        for element in node.elements {
+            self.insert_element(ReplicaElement {
+                id: element.id,
+                fields: element.fields,
+            });
+        }
+        for location in node.locations {
+            self.insert_tree(location);
+        }
+    }
+
+    fn remove_location(&mut self, location_id: &LocationId) {
+        if let Some(parent) =
+            parent(location_id).and_then(|id| self.locations.get_mut(&id.to_bytes()))
+        {
+            parent.locations.retain(|id| id != location_id);
+        }
+
+        let Some(location) = self.locations.remove(&location_id.to_bytes()) else {
+            return;
+        };
+        for element_id in location.elements {
+            self.elements.remove(&element_id.to_bytes());
+        }
+        for location_id in location.locations {
+            self.remove_location(&location_id);
+        }
+    }
+
+    pub fn root(&self) -> Option<&ReplicaLocation> {
+        self.location(&self.root)
+    }
+
+    pub fn location(&self, location_id: &LocationId) -> Option<&ReplicaLocation> {
+        self.locations.get(&location_id.to_bytes())
+    }
+
+    pub fn element(&self, element_id: &ElementId) -> Option<&ReplicaElement> {
+        self.elements.get(&element_id.to_bytes())
+    }
+
+    pub fn module(&self, module_id: &ModuleId) -> Option<&ReplicaModule> {
+        self.modules.get(&module_id.to_bytes())
+    }
+
+    pub fn modules(&self) -> impl Iterator<Item = &ReplicaModule> {
+        self.modules.values()
+    }
+
+    pub fn element_get_name(&self, element_id: &ElementId) -> Option<&str> {
+        self.element(element_id)?.fields.name.as_deref()
+    }
+
+    pub fn element_get_desc(&self, element_id: &ElementId) -> Option<&str> {
+        self.element(element_id)?.fields.desc.as_deref()
+    }
+
+    pub fn element_get_enabled(&self, element_id: &ElementId) -> Option<bool> {
+        self.element(element_id)?.fields.enabled
+    }
+
+    pub fn element_get_progress(&self, element_id: &ElementId) -> Option<f32> {
+        self.element(element_id)?.fields.progress
+    }
+
+    pub fn element_get_status(&self, element_id: &ElementId) -> Option<usize> {
+        self.element(element_id)?.fields.status
+    }
+
+    pub fn element_is_error(&self, element_id: &ElementId) -> Option<bool> {
+        self.element(element_id)?.fields.is_error
+    }
+
+    pub fn location_get_name(&self, location_id: &LocationId) -> Option<&str> {
+        self.location(location_id)?.fields.name.as_deref()
+    }
+
+    pub fn location_get_desc(&self, location_id: &LocationId) -> Option<&str> {
+        self.location(location_id)?.fields.desc.as_deref()
+    }
+
+    pub fn location_is_enabled(&self, location_id: &LocationId) -> Option<bool> {
+        self.location(location_id)?.fields.enabled
+    }
+
+    pub fn location_get_progress(&self, location_id: &LocationId) -> Option<f32> {
+        self.location(location_id)?.fields.progress
+    }
+
+    pub fn location_get_status(&self, location_id: &LocationId) -> Option<usize> {
+        self.location(location_id)?.fields.status
+    }
+
+    pub fn location_is_error(&self, location_id: &LocationId) -> Option<bool> {
+        self.location(location_id)?.fields.is_error
+    }
+
+    pub fn module_get_name(&self, module_id: &ModuleId) -> Option<&str> {
+        self.module(module_id)?.name.as_deref()
+    }
+}
+
+/// The location that contains the location, `None` for the root
+fn parent(location_id: &LocationId) -> Option<LocationId> {
+    let (_, parent) = location_id.0.split_last()?;
+    Some(LocationId(parent.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{mpsc::channel, Arc, Mutex},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{
+        data::ElementData,
+        error::RequestError,
+        events::EventFilter,
+        packets::{ClientPackets, DaemonEvent, ElementNode, EventReplay},
+        query::{ElementQuery, QueryCursor, QueryPage},
+    };
+
+    /// Serves the snapshots and the element names from `tree`, like the daemon would
+    struct FakeSession {
+        tree: Arc<Mutex<LocationNode>>,
+    }
+
+    impl TDaemonSession for FakeSession {
+        fn pull_packets(&self) {}
+
+        fn waiting_for(&self, _: u128) -> Result<ClientPackets, SessionError> {
+            unimplemented!()
+        }
+
+        fn waiting_for_timeout(
+            &self,
+            _: u128,
+            _: Option<Duration>,
+        ) -> Result<ClientPackets, SessionError> {
+            unimplemented!()
+        }
+
+        fn request(&self, _: ServerPackets) -> Result<ClientPackets, RequestError> {
+            unimplemented!()
+        }
+
+        fn request_timeout(
+            &self,
+            _: ServerPackets,
+            _: Option<Duration>,
+        ) -> Result<ClientPackets, RequestError> {
+            unimplemented!()
+        }
+
+        fn send(&self, _: ServerPackets) {}
+
+        fn generate(&self) -> u128 {
+            1
+        }
+
+        fn subscribe(&self) -> Receiver<SessionEvent> {
+            channel().1
+        }
+
+        fn subscribe_daemon(&self) -> Receiver<DaemonEvent> {
+            channel().1
+        }
+
+        fn set_event_filter(&self, _: EventFilter) -> Result<(), SessionError> {
+            Ok(())
+        }
+
+        fn last_event(&self) -> u64 {
+            0
+        }
+
+        fn is_connected(&self) -> bool {
+            true
+        }
+
+        fn resyncs(&self) -> u64 {
+            0
+        }
+
+        fn cancel(&self, _: u128) {}
+
+        fn forget(&self, _: u128) {}
+
+        fn events_since(&self, _: u64) -> Result<EventReplay, SessionError> {
+            unimplemented!()
+        }
+
+        fn batch(&self, requests: Vec<ServerPackets>) -> Result<Vec<ClientPackets>, SessionError> {
+            let tree = self.tree.lock().unwrap();
+            Ok(requests
+                .into_iter()
+                .filter_map(|request| match request {
+                    ServerPackets::ElementGetName { element_id, .. } => {
+                        let name = find_element(&tree, &element_id)
+                            .and_then(|element| element.fields.name.clone())
+                            .ok_or(SessionError::Custom("Not found".to_string()));
+                        Some(ClientPackets::ElementGetName(0, name))
+                    }
+                    _ => None,
+                })
+                .collect())
+        }
+
+        fn get_tree_snapshot(
+            &self,
+            root: &LocationId,
+            _: u32,
+            _: Vec<TreeField>,
+        ) -> Result<LocationNode, SessionError> {
+            find_location(&self.tree.lock().unwrap(), root)
+                .cloned()
+                .ok_or(SessionError::Custom("Not found".to_string()))
+        }
+
+        fn query_elements(
+            &self,
+            _: ElementQuery,
+            _: Option<QueryCursor>,
+            _: u32,
+        ) -> Result<QueryPage, SessionError> {
+            unimplemented!()
+        }
+
+        fn open_data(&self, _: &ElementId, _: bool) -> Result<ElementData, SessionError> {
+            unimplemented!()
+        }
+
+        fn eref_get_or_add(&self, _: ElementId) -> ERef {
+            unimplemented!()
+        }
+
+        fn lref_get_or_add(&self, _: LocationId) -> LRef {
+            unimplemented!()
+        }
+
+        fn mref_get_or_add(&self, _: ModuleId) -> MRef {
+            unimplemented!()
+        }
+
+        fn cl(&self) -> Box<dyn TDaemonSession> {
+            unimplemented!()
+        }
+    }
+
+    fn find_location<'a>(node: &'a LocationNode, id: &LocationId) -> Option<&'a LocationNode> {
+        if &node.id == id {
+            return Some(node);
+        }
+        node.locations
+            .iter()
+            .find_map(|location| find_location(location, id))
+    }
+
+    fn find_element<'a>(node: &'a LocationNode, id: &ElementId) -> Option<&'a ElementNode> {
+        find_location(node, &id.location_id)?
+            .elements
+            .iter()
+            .find(|element| &element.id == id)
+    }
+
+    fn location_id(path: &[usize]) -> LocationId {
+        LocationId(path.iter().map(|index| *index as _).collect())
+    }
+
+    fn element_id(path: &[usize], uid: usize) -> ElementId {
+        ElementId {
+            uid: uid as _,
+            location_id: location_id(path),
+        }
+    }
+
+    fn named(name: &str) -> TreeFields {
+        TreeFields {
+            name: Some(name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn element(path: &[usize], uid: usize, name: &str) -> ElementNode {
+        ElementNode {
+            id: element_id(path, uid),
+            fields: named(name),
+        }
+    }
+
+    fn location(
+        path: &[usize],
+        name: &str,
+        elements: Vec<ElementNode>,
+        locations: Vec<LocationNode>,
+    ) -> LocationNode {
+        LocationNode {
+            id: location_id(path),
+            fields: named(name),
+            elements,
+            locations,
+            truncated: false,
+        }
+    }
+
+    /// root with the element "first" and the location "sub" that has the element "second"
+    fn tree() -> LocationNode {
+        location(
+            &[],
+            "root",
+            vec![element(&[], 0, "first")],
+            vec![location(
+                &[0],
+                "sub",
+                vec![element(&[0], 0, "second")],
+                Vec::new(),
+            )],
+        )
+    }
+
+    /// Replica of the snapshot of `tree` and the tree of the daemon, that can change after the snapshot
+    fn replica(tree: LocationNode) -> (Replica, Arc<Mutex<LocationNode>>) {
+        let daemon = Arc::new(Mutex::new(tree.clone()));
+        let session = FakeSession {
+            tree: daemon.clone(),
+        };
+        let mut replica = Replica {
+            session: Box::new(session),
+            events: channel().1,
+            resyncs: 0,
+            root: tree.id.clone(),
+            locations: HashMap::new(),
+            elements: HashMap::new(),
+            modules: HashMap::new(),
+        };
+        replica.insert_tree(tree);
+        (replica, daemon)
+    }
+
+    #[test]
+    fn snapshot_is_inserted() {
+        let (replica, _) = replica(tree());
+
+        let root = replica.root().unwrap();
+        assert_eq!(root.elements, vec![element_id(&[], 0)]);
+        assert_eq!(root.locations, vec![location_id(&[0])]);
+        assert_eq!(replica.location_get_name(&location_id(&[0])), Some("sub"));
+        assert_eq!(
+            replica.location(&location_id(&[0])).unwrap().elements,
+            vec![element_id(&[0], 0)]
+        );
+        assert_eq!(
+            replica.element_get_name(&element_id(&[0], 0)),
+            Some("second")
+        );
+    }
+
+    #[test]
+    fn created_are_fetched() {
+        let (mut replica, daemon) = replica(tree());
+        {
+            let mut daemon = daemon.lock().unwrap();
+            daemon.elements.push(element(&[], 1, "third"));
+            daemon.locations.push(location(
+                &[1],
+                "other",
+                vec![element(&[1], 0, "fourth")],
+                Vec::new(),
+            ));
+        }
+
+        replica.apply(SessionEvent::NewElement(element_id(&[], 1)));
+        replica.apply(SessionEvent::NewLocation(location_id(&[1])));
+
+        let root = replica.root().unwrap();
+        assert_eq!(root.elements, vec![element_id(&[], 0), element_id(&[], 1)]);
+        assert_eq!(root.locations, vec![location_id(&[0]), location_id(&[1])]);
+        assert_eq!(replica.element_get_name(&element_id(&[], 1)), Some("third"));
+        assert_eq!(
+            replica.element_get_name(&element_id(&[1], 0)),
+            Some("fourth")
+        );
+    }
+
+    #[test]
+    fn created_and_destroyed_before_the_fetch_is_skipped() {
+        let (mut replica, _) = replica(tree());
+
+        replica.apply(SessionEvent::NewElement(element_id(&[], 1)));
+
+        assert!(replica.element(&element_id(&[], 1)).is_none());
+        assert_eq!(replica.root().unwrap().elements, vec![element_id(&[], 0)]);
+    }
+
+    #[test]
+    fn changes_set_their_value() {
+        let (mut replica, _) = replica(tree());
+
+        replica.apply(SessionEvent::ElementNameChanged(
+            element_id(&[0], 0),
+            "renamed".to_string(),
+        ));
+        replica.apply(SessionEvent::ElementStatusChanged(element_id(&[0], 0), 3));
+        replica.apply(SessionEvent::LocationEnabledChanged(
+            location_id(&[0]),
+            true,
+        ));
+
+        assert_eq!(
+            replica.element_get_name(&element_id(&[0], 0)),
+            Some("renamed")
+        );
+        assert_eq!(replica.element_get_status(&element_id(&[0], 0)), Some(3));
+        assert_eq!(replica.location_is_enabled(&location_id(&[0])), Some(true));
+        // only the changed field
+        assert_eq!(replica.element_get_name(&element_id(&[], 0)), Some("first"));
+    }
+
+    #[test]
+    fn destroyed_location_takes_its_contents() {
+        let (mut replica, _) = replica(tree());
+
+        replica.apply(SessionEvent::DestroyedElement(element_id(&[], 0)));
+        replica.apply(SessionEvent::DestroyedLocation(location_id(&[0])));
+
+        let root = replica.root().unwrap();
+        assert!(root.elements.is_empty());
+        assert!(root.locations.is_empty());
+        assert!(replica.location(&location_id(&[0])).is_none());
+        assert!(replica.element(&element_id(&[0], 0)).is_none());
+    }
+
+    #[test]
+    fn moved_element_keeps_its_fields() {
+        let (mut replica, _) = replica(tree());
+        replica.apply(SessionEvent::ElementStatusChanged(element_id(&[], 0), 2));
+
+        replica.apply(SessionEvent::ElementIdChanged(
+            element_id(&[], 0),
+            element_id(&[0], 1),
+        ));
+
+        assert!(replica.element(&element_id(&[], 0)).is_none());
+        assert!(replica.root().unwrap().elements.is_empty());
+        assert_eq!(
+            replica.location(&location_id(&[0])).unwrap().elements,
+            vec![element_id(&[0], 0), element_id(&[0], 1)]
+        );
+        assert_eq!(
+            replica.element_get_name(&element_id(&[0], 1)),
+            Some("first")
+        );
+        assert_eq!(replica.element_get_status(&element_id(&[0], 1)), Some(2));
+    }
+
+    #[test]
+    fn moved_location_remaps_its_contents() {
+        let (mut replica, daemon) = replica(tree());
+        // "sub" is moved into a new location, so it and its element have new ids
+        *daemon.lock().unwrap() = location(
+            &[],
+            "root",
+            vec![element(&[], 0, "first")],
+            vec![location(
+                &[0],
+                "parent",
+                Vec::new(),
+                vec![location(
+                    &[0, 0],
+                    "sub",
+                    vec![element(&[0, 0], 0, "second")],
+                    Vec::new(),
+                )],
+            )],
+        );
+
+        replica.apply(SessionEvent::LocationIdChanged(
+            location_id(&[0]),
+            location_id(&[0, 0]),
+        ));
+        replica.apply(SessionEvent::NewLocation(location_id(&[0])));
+
+        assert_eq!(replica.root().unwrap().locations, vec![location_id(&[0])]);
+        assert_eq!(
+            replica.location_get_name(&location_id(&[0])),
+            Some("parent")
+        );
+        assert_eq!(
+            replica.location(&location_id(&[0])).unwrap().locations,
+            vec![location_id(&[0, 0])]
+        );
+        assert_eq!(
+            replica.location_get_name(&location_id(&[0, 0])),
+            Some("sub")
+        );
+        assert_eq!(
+            replica.element_get_name(&element_id(&[0, 0], 0)),
+            Some("second")
+        );
+        assert!(replica.element(&element_id(&[0], 0)).is_none());
+    }
+}
@@ -0,0 +1,339 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::{Duration, SystemTime},
+};
+
+use tokio::sync::oneshot;
+
+use crate::packets::ClientPackets;
+
+/// How many answered requests are remembered to drop their duplicated responses
+const ANSWERED: usize = 64;
+
+/// What arrived for a request
+pub enum Reply {
+    Response(ClientPackets),
+    /// the response was lost in transit
+    Incomplete,
+    /// the request was cancelled, the response will not arrive
+    Cancelled,
+}
+
+#[derive(Default)]
+struct Slots {
+    replies: HashMap<u128, (SystemTime, Reply)>,
+    /// requests that already took their reply, a retransmitted request can be responded twice
+    answered: VecDeque<u128>,
+    /// one condvar for every request that has a thread waiting, only its reply wakes it
+    waiting: HashMap<u128, Arc<Condvar>>,
+    /// requests that have a task waiting, resolved like the condvars
+    pending: HashMap<u128, oneshot::Sender<()>>,
+    /// waited requests whose response started to arrive
+    arriving: HashSet<u128>,
+}
+
+/// Replies by request id, shared by every thread that waits for one
+/// Waiting doesn't hold the session lock
+#[derive(Default)]
+pub struct Replies {
+    slots: Mutex<Slots>,
+}
+
+impl Replies {
+    pub fn insert(&self, id: u128, reply: Reply) {
+        let mut slots = self.slots.lock().unwrap();
+        if slots.answered.contains(&id) {
+            log::debug!("Dropped duplicated response for: {id}");
+            return;
+        }
+        slots.replies.insert(id, (SystemTime::now(), reply));
+        Self::wake(&mut slots, id);
+    }
+
+    fn wake(slots: &mut MutexGuard<Slots>, id: u128) {
+        if let Some(waiting) = slots.waiting.get(&id) {
+            waiting.notify_all();
+        }
+        if let Some(pending) = slots.pending.remove(&id) {
+            let _ = pending.send(());
+        }
+    }
+
+    pub fn take(&self, id: u128) -> Option<Reply> {
+        Self::take_from(&mut self.slots.lock().unwrap(), id)
+    }
+
+    /// Takes the reply, if is not here waits at most `timeout` for it to arrive
+    /// Returns `None` also when the waiter was woken by `wake_all`
+    pub fn wait_for(&self, id: u128, timeout: Duration) -> Option<Reply> {
+        let mut slots = self.slots.lock().unwrap();
+        if !slots.replies.contains_key(&id) {
+            let waiting = slots.waiting.entry(id).or_default().clone();
+            slots = waiting.wait_timeout(slots, timeout).unwrap().0;
+            // the same request can have other waiters
+            if Arc::strong_count(&waiting) == 2 {
+                slots.waiting.remove(&id);
+            }
+        }
+        Self::take_from(&mut slots, id)
+    }
+
+    /// Resolved when the reply arrives or by `wake_all`, then the reply is taken with `take`
+    /// Is the async `wait_for`, a task waits without holding a thread
+    pub fn notify(&self, id: u128) -> oneshot::Receiver<()> {
+        let (sender, receiver) = oneshot::channel();
+        let mut slots = self.slots.lock().unwrap();
+        // their tasks stopped waiting
+        slots.pending.retain(|_, pending| !pending.is_closed());
+        if slots.replies.contains_key(&id) {
+            let _ = sender.send(());
+        } else {
+            slots.pending.insert(id, sender);
+        }
+        receiver
+    }
+
+    /// Called with what the transport is receiving, marks the waited requests whose response is arriving
+    pub fn mark_arriving(&self, in_progress: impl Fn(u128) -> bool) {
+        let mut slots = self.slots.lock().unwrap();
+        let arriving = slots
+            .waiting
+            .keys()
+            .chain(slots.pending.keys())
+            .copied()
+            .filter(|id| in_progress(*id))
+            .collect::<Vec<_>>();
+        slots.arriving.extend(arriving);
+    }
+
+    /// `true` if part of the response arrived since the last call, the waiter should wait longer
+    pub fn take_arriving(&self, id: u128) -> bool {
+        self.slots.lock().unwrap().arriving.remove(&id)
+    }
+
+    /// Wakes every waiter, they check again if the session is connected
+    pub fn wake_all(&self) {
+        let mut slots = self.slots.lock().unwrap();
+        for waiting in slots.waiting.values() {
+            waiting.notify_all();
+        }
+        for (_, pending) in slots.pending.drain() {
+            let _ = pending.send(());
+        }
+    }
+
+    fn take_from(slots: &mut MutexGuard<Slots>, id: u128) -> Option<Reply> {
+        let (_, reply) = slots.replies.remove(&id)?;
+        slots.arriving.remove(&id);
+        Self::answered(slots, id);
+        Some(reply)
+    }
+
+    fn answered(slots: &mut MutexGuard<Slots>, id: u128) {
+        if slots.answered.len() == ANSWERED {
+            slots.answered.pop_front();
+        }
+        slots.answered.push_back(id);
+    }
+
+    /// Nobody waits for the request anymore, its response will be dropped
+    pub fn forget(&self, id: u128) {
+        let mut slots = self.slots.lock().unwrap();
+        slots.replies.remove(&id);
+        slots.arriving.remove(&id);
+        Self::answered(&mut slots, id);
+    }
+
+    /// Wakes the thread that waits for the request with `Reply::Cancelled`
+    pub fn cancel(&self, id: u128) {
+        let mut slots = self.slots.lock().unwrap();
+        slots
+            .replies
+            .insert(id, (SystemTime::now(), Reply::Cancelled));
+        Self::wake(&mut slots, id);
+    }
+
+    /// Forgets the replies that nobody took for longer than `max_age`, their request timed out
+    pub fn gc(&self, max_age: Duration) {
+        self.slots
+            .lock()
+            .unwrap()
+            .replies
+            .retain(|_, (arrived, _)| arrived.elapsed().unwrap_or_default() < max_age);
+    }
+
+    pub fn clear(&self) {
+        let mut slots = self.slots.lock().unwrap();
+        slots.replies.clear();
+        slots.answered.clear();
+        slots.arriving.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.lock().unwrap().replies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use super::*;
+
+    fn pong(id: u128) -> Reply {
+        Reply::Response(ClientPackets::Pong(id))
+    }
+
+    #[test]
+    fn takes_inserted_reply() {
+        let replies = Replies::default();
+        replies.insert(1, pong(1));
+
+        assert!(matches!(
+            replies.take(1),
+            Some(Reply::Response(ClientPackets::Pong(1)))
+        ));
+        assert!(replies.take(1).is_none());
+    }
+
+    #[test]
+    fn drops_duplicated_response() {
+        let replies = Replies::default();
+        replies.insert(1, pong(1));
+        replies.take(1);
+        replies.insert(1, pong(1));
+
+        assert!(replies.is_empty());
+    }
+
+    #[test]
+    fn forgotten_response_is_dropped() {
+        let replies = Replies::default();
+        replies.forget(1);
+        replies.insert(1, pong(1));
+
+        assert!(replies.is_empty());
+    }
+
+    #[test]
+    fn wait_times_out() {
+        let replies = Replies::default();
+
+        assert!(replies.wait_for(1, Duration::from_millis(10)).is_none());
+        assert!(replies.slots.lock().unwrap().waiting.is_empty());
+    }
+
+    #[test]
+    fn waiter_is_woken_by_its_reply() {
+        let replies = Arc::new(Replies::default());
+        let waiter = {
+            let replies = replies.clone();
+            thread::spawn(move || replies.wait_for(1, Duration::new(10, 0)))
+        };
+
+        while !replies.slots.lock().unwrap().waiting.contains_key(&1) {
+            thread::yield_now();
+        }
+        replies.insert(1, pong(1));
+
+        assert!(matches!(waiter.join().unwrap(), Some(Reply::Response(_))));
+    }
+
+    #[test]
+    fn cancel_wakes_the_waiter() {
+        let replies = Arc::new(Replies::default());
+        let waiter = {
+            let replies = replies.clone();
+            thread::spawn(move || replies.wait_for(1, Duration::new(10, 0)))
+        };
+
+        while !replies.slots.lock().unwrap().waiting.contains_key(&1) {
+            thread::yield_now();
+        }
+        replies.cancel(1);
+
+        assert!(matches!(waiter.join().unwrap(), Some(Reply::Cancelled)));
+    }
+
+    #[test]
+    fn other_replies_do_not_wake_the_waiter() {
+        let replies = Arc::new(Replies::default());
+        let waiter = {
+            let replies = replies.clone();
+            thread::spawn(move || replies.wait_for(1, Duration::from_millis(100)))
+        };
+
+        while !replies.slots.lock().unwrap().waiting.contains_key(&1) {
+            thread::yield_now();
+        }
+        replies.insert(2, pong(2));
+
+        assert!(waiter.join().unwrap().is_none());
+        assert!(replies.take(2).is_some());
+    }
+
+    #[test]
+    fn only_waited_requests_are_arriving() {
+        let replies = Arc::new(Replies::default());
+        let waiter = {
+            let replies = replies.clone();
+            thread::spawn(move || replies.wait_for(1, Duration::new(10, 0)))
+        };
+
+        while !replies.slots.lock().unwrap().waiting.contains_key(&1) {
+            thread::yield_now();
+        }
+        replies.mark_arriving(|_| true);
+
+        assert!(replies.take_arriving(1));
+        assert!(!replies.take_arriving(1));
+        assert!(!replies.take_arriving(2));
+        replies.insert(1, pong(1));
+        waiter.join().unwrap();
+    }
+
+    #[test]
+    fn notified_when_the_reply_arrives() {
+        let replies = Replies::default();
+        let mut notified = replies.notify(1);
+
+        replies.insert(2, pong(2));
+        assert!(notified.try_recv().is_err());
+        replies.insert(1, pong(1));
+        assert!(notified.try_recv().is_ok());
+        assert!(replies.take(1).is_some());
+    }
+
+    #[test]
+    fn notified_at_once_if_the_reply_is_here() {
+        let replies = Replies::default();
+        replies.insert(1, pong(1));
+
+        assert!(replies.notify(1).try_recv().is_ok());
+    }
+
+    #[test]
+    fn wake_all_notifies_every_task() {
+        let replies = Replies::default();
+        let mut first = replies.notify(1);
+        let mut second = replies.notify(2);
+        replies.wake_all();
+
+        assert!(first.try_recv().is_ok());
+        assert!(second.try_recv().is_ok());
+    }
+
+    #[test]
+    fn gc_drops_old_replies() {
+        let replies = Replies::default();
+        replies.insert(1, pong(1));
+        replies.gc(Duration::ZERO);
+
+        assert!(replies.is_empty());
+    }
+}
@@ -49,3 +49,58 @@ impl IntoRawSock for UdpSocket {
         self.into_raw_fd()
     }
 }
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl FromRawSock for std::os::unix::net::UnixStream {
+    fn from_raw(raw_sock: RawSock) -> Self {
+        use std::os::unix::io::FromRawFd;
+
+        unsafe { Self::from_raw_fd(raw_sock) }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl IntoRawSock for std::os::unix::net::UnixStream {
+    fn into_raw(self) -> RawSock {
+        use std::os::unix::io::IntoRawFd;
+
+        self.into_raw_fd()
+    }
+}
+
+impl FromRawSock for std::net::TcpStream {
+    fn from_raw(raw_sock: RawSock) -> Self {
+        #[cfg(target_os = "windows")]
+        use std::os::windows::io::FromRawSocket;
+
+        #[cfg(target_os = "windows")]
+        unsafe {
+            Self::from_raw_socket(raw_sock)
+        }
+
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        use std::os::unix::io::FromRawFd;
+
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        unsafe {
+            Self::from_raw_fd(raw_sock)
+        }
+    }
+}
+
+impl IntoRawSock for std::net::TcpStream {
+    fn into_raw(self) -> RawSock {
+        #[cfg(target_os = "windows")]
+        use std::os::windows::io::IntoRawSocket;
+
+        #[cfg(target_os = "windows")]
+        unsafe {
+            self.into_raw_socket()
+        }
+
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        use std::os::unix::io::IntoRawFd;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        self.into_raw_fd()
+    }
+}
@@ -2,19 +2,16 @@ use std::path::PathBuf;
 
 use crate::{
     packets::{ClientPackets, ServerPackets},
-    TDaemonSession,
+    TDaemonSession, DAEMON_VERSION,
 };
 use muzzman_lib::prelude::*;
 
-pub const DAEMON_CLIENT_VERSION: u64 = 1;
-
 impl TSession for Box<dyn TDaemonSession> {
     fn load_module(&self, path: PathBuf) -> Result<MRef, SessionError> {
         let id = self.generate();
         let packet = ServerPackets::LoadModule { id, path };
 
-        self.send(packet);
-        if let Some(ClientPackets::LoadModule(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LoadModule(_, response) = self.request(packet)? {
             match response {
                 Ok(ok) => Ok(self.mref_get_or_add(ok)),
                 Err(err) => Err(err),
@@ -28,8 +25,7 @@ impl TSession for Box<dyn TDaemonSession> {
         let id = self.generate();
         let packet = ServerPackets::RemoveModule { id, module_id };
 
-        self.send(packet);
-        if let Some(ClientPackets::RemoveModule(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::RemoveModule(_, response) = self.request(packet)? {
             match response {
                 Ok(_) => Err(SessionError::Custom("Cannot be transfered".into())),
                 Err(err) => Err(err),
@@ -46,8 +42,7 @@ impl TSession for Box<dyn TDaemonSession> {
             module_info: info,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::LoadModuleInfo(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LoadModuleInfo(_, response) = self.request(packet)? {
             match response {
                 Ok(id) => self.get_module_ref(&id),
                 Err(err) => Err(err),
@@ -64,8 +59,7 @@ impl TSession for Box<dyn TDaemonSession> {
             module_info: info,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::FindModule(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::FindModule(_, response) = self.request(packet)? {
             match response {
                 Ok(id) => self.get_module_ref(&id),
                 Err(err) => Err(err),
@@ -93,8 +87,7 @@ impl TSession for Box<dyn TDaemonSession> {
         let id = self.generate();
         let packet = ServerPackets::GetActions { id, range };
 
-        self.send(packet);
-        if let Some(ClientPackets::GetActions(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::GetActions(_, response) = self.request(packet)? {
             match response {
                 Ok(ok) => {
                     let mut tmp = Vec::new();
@@ -114,8 +107,7 @@ impl TSession for Box<dyn TDaemonSession> {
         let id = self.generate();
         let packet = ServerPackets::GetActionsLen { id };
 
-        self.send(packet);
-        if let Some(ClientPackets::GetActionsLen(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::GetActionsLen(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -136,8 +128,7 @@ impl TSession for Box<dyn TDaemonSession> {
             data,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::RunAction(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::RunAction(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -148,8 +139,7 @@ impl TSession for Box<dyn TDaemonSession> {
         let id = self.generate();
         let packet = ServerPackets::GetModulesLen { id };
 
-        self.send(packet);
-        if let Some(ClientPackets::GetModulesLen(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::GetModulesLen(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -160,8 +150,7 @@ impl TSession for Box<dyn TDaemonSession> {
         let id = self.generate();
         let packet = ServerPackets::GetModules { id, range };
 
-        self.send(packet);
-        if let Some(ClientPackets::GetModules(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::GetModules(_, response) = self.request(packet)? {
             match response {
                 Ok(ok) => {
                     let mut tmp = Vec::new();
@@ -184,8 +173,7 @@ impl TSession for Box<dyn TDaemonSession> {
             module_id: *module_id,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ModuleGetName(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ModuleGetName(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -200,8 +188,7 @@ impl TSession for Box<dyn TDaemonSession> {
             to: name,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ModuleSetName(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ModuleSetName(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -215,8 +202,7 @@ impl TSession for Box<dyn TDaemonSession> {
             module_id: *module_id,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ModuleGetDefaultName(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ModuleGetDefaultName(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -230,8 +216,7 @@ impl TSession for Box<dyn TDaemonSession> {
             module_id: *module_id,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ModuleGetUid(_, res)) = self.waiting_for(id) {
+        if let ClientPackets::ModuleGetUid(_, res) = self.request(packet)? {
             res
         } else {
             Err(SessionError::ServerTimeOut)
@@ -245,8 +230,7 @@ impl TSession for Box<dyn TDaemonSession> {
             module_id: *module_id,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ModuleGetVersion(_, res)) = self.waiting_for(id) {
+        if let ClientPackets::ModuleGetVersion(_, res) = self.request(packet)? {
             res
         } else {
             Err(SessionError::ServerTimeOut)
@@ -263,8 +247,7 @@ impl TSession for Box<dyn TDaemonSession> {
             module_id: *module_id,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ModuleSupportedVersions(_, res)) = self.waiting_for(id) {
+        if let ClientPackets::ModuleSupportedVersions(_, res) = self.request(packet)? {
             res
         } else {
             Err(SessionError::ServerTimeOut)
@@ -278,8 +261,7 @@ impl TSession for Box<dyn TDaemonSession> {
             module_id: *module_id,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ModuleGetDesc(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ModuleGetDesc(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -294,8 +276,7 @@ impl TSession for Box<dyn TDaemonSession> {
             to: desc,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ModuleSetDesc(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ModuleSetDesc(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -309,8 +290,7 @@ impl TSession for Box<dyn TDaemonSession> {
             module_id: *module_id,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ModuleGetDefaultDesc(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ModuleGetDefaultDesc(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -324,8 +304,7 @@ impl TSession for Box<dyn TDaemonSession> {
             module_id: *module_id,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ModuleGetProxy(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ModuleGetProxy(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -340,8 +319,7 @@ impl TSession for Box<dyn TDaemonSession> {
             to: proxy,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ModuleSetProxy(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ModuleSetProxy(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -355,8 +333,7 @@ impl TSession for Box<dyn TDaemonSession> {
             module_id: *module_id,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ModuleGetSettings(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ModuleGetSettings(_, response) = self.request(packet)? {
             *response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -371,8 +348,7 @@ impl TSession for Box<dyn TDaemonSession> {
             to: data,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ModuleSetSettings(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ModuleSetSettings(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -386,8 +362,7 @@ impl TSession for Box<dyn TDaemonSession> {
             module_id: *module_id,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ModuleGetElementSettings(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ModuleGetElementSettings(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -406,8 +381,7 @@ impl TSession for Box<dyn TDaemonSession> {
             to: data,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ModuleSetElementSettings(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ModuleSetElementSettings(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -420,8 +394,7 @@ impl TSession for Box<dyn TDaemonSession> {
             id,
             module_id: *module_id,
         };
-        self.send(packet);
-        if let Some(ClientPackets::ModuleGetLocationSettings(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ModuleGetLocationSettings(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -439,8 +412,7 @@ impl TSession for Box<dyn TDaemonSession> {
             module_id: *module_id,
             to: data,
         };
-        self.send(packet);
-        if let Some(ClientPackets::ModuleSetLocationSettings(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ModuleSetLocationSettings(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -459,8 +431,7 @@ impl TSession for Box<dyn TDaemonSession> {
             location_id: location_id.clone(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ModuleInitLocation(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ModuleInitLocation(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -479,8 +450,7 @@ impl TSession for Box<dyn TDaemonSession> {
             element_id: element_id.clone(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ModuleInitElement(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ModuleInitElement(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -495,8 +465,7 @@ impl TSession for Box<dyn TDaemonSession> {
             url,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ModuleAcceptUrl(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ModuleAcceptUrl(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -515,8 +484,7 @@ impl TSession for Box<dyn TDaemonSession> {
             filename: filename.to_owned(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ModuleAcceptExtension(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ModuleAcceptExtension(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -530,8 +498,7 @@ impl TSession for Box<dyn TDaemonSession> {
             module_id: *module_id,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ModuleAcceptedProtocols(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ModuleAcceptedProtocols(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -548,8 +515,7 @@ impl TSession for Box<dyn TDaemonSession> {
             module_id: *module_id,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ModuleAcceptedExtensions(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ModuleAcceptedExtensions(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -584,8 +550,7 @@ impl TSession for Box<dyn TDaemonSession> {
             name: name.to_string(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::CreateElement(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::CreateElement(_, response) = self.request(packet)? {
             match response {
                 Ok(ok) => Ok(self.eref_get_or_add(ok)),
                 Err(err) => Err(err),
@@ -599,8 +564,7 @@ impl TSession for Box<dyn TDaemonSession> {
         let id = self.generate();
         let packet = ServerPackets::LoadElementInfo { id, element_info };
 
-        self.send(packet);
-        if let Some(ClientPackets::LoadElementInfo(_, id)) = self.waiting_for(id) {
+        if let ClientPackets::LoadElementInfo(_, id) = self.request(packet)? {
             self.get_element_ref(&id?)
         } else {
             Err(SessionError::ServerTimeOut)
@@ -619,8 +583,7 @@ impl TSession for Box<dyn TDaemonSession> {
             location_id: location_id.clone(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::MoveElement(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::MoveElement(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -631,8 +594,7 @@ impl TSession for Box<dyn TDaemonSession> {
         let id = self.generate();
         let packet = ServerPackets::DestroyElement { id, element_id };
 
-        self.send(packet);
-        if let Some(ClientPackets::DestroyElement(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::DestroyElement(_, response) = self.request(packet)? {
             match response {
                 Ok(_) => Err(SessionError::Custom("Cannot Transfer ERow".into())),
                 Err(err) => Err(err),
@@ -649,8 +611,7 @@ impl TSession for Box<dyn TDaemonSession> {
             element_id: element_id.clone(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementGetName(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementGetName(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -665,8 +626,7 @@ impl TSession for Box<dyn TDaemonSession> {
             to: name.to_string(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementSetName(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementSetName(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -680,8 +640,7 @@ impl TSession for Box<dyn TDaemonSession> {
             element_id: element_id.clone(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementGetDesc(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementGetDesc(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -696,8 +655,7 @@ impl TSession for Box<dyn TDaemonSession> {
             to: desc.to_string(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementSetDesc(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementSetDesc(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -711,8 +669,7 @@ impl TSession for Box<dyn TDaemonSession> {
             element_id: element_id.clone(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementGetMeta(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementGetMeta(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -727,8 +684,7 @@ impl TSession for Box<dyn TDaemonSession> {
             to: meta.to_string(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementSetMeta(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementSetMeta(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -741,9 +697,7 @@ impl TSession for Box<dyn TDaemonSession> {
             id,
             element_id: element_id.clone(),
         };
-        self.send(packet);
-
-        if let Some(ClientPackets::ElementGetUrl(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementGetUrl(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -761,9 +715,7 @@ impl TSession for Box<dyn TDaemonSession> {
             element_id: element_id.clone(),
             to: url,
         };
-        self.send(packet);
-
-        if let Some(ClientPackets::ElementSetUrl(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementSetUrl(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -777,8 +729,7 @@ impl TSession for Box<dyn TDaemonSession> {
             element_id: element_id.clone(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementGetElementData(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementGetElementData(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -797,8 +748,7 @@ impl TSession for Box<dyn TDaemonSession> {
             to: data,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementSetElementData(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementSetElementData(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -812,8 +762,7 @@ impl TSession for Box<dyn TDaemonSession> {
             element_id: element_id.clone(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementGetModuleData(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementGetModuleData(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -832,8 +781,7 @@ impl TSession for Box<dyn TDaemonSession> {
             to: data,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementSetModuleData(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementSetModuleData(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -847,8 +795,7 @@ impl TSession for Box<dyn TDaemonSession> {
             element_id: element_id.clone(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementGetModule(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementGetModule(_, response) = self.request(packet)? {
             match response {
                 Ok(ok) => match ok {
                     Some(some) => Ok(Some(self.mref_get_or_add(some))),
@@ -873,8 +820,7 @@ impl TSession for Box<dyn TDaemonSession> {
             module,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementSetModule(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementSetModule(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -888,8 +834,7 @@ impl TSession for Box<dyn TDaemonSession> {
             element_id: element_id.clone(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementGetStatuses(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementGetStatuses(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -908,8 +853,7 @@ impl TSession for Box<dyn TDaemonSession> {
             to: statuses,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementSetStatuses(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementSetStatuses(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -923,8 +867,7 @@ impl TSession for Box<dyn TDaemonSession> {
             element_id: element_id.clone(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementGetStatus(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementGetStatus(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -943,8 +886,7 @@ impl TSession for Box<dyn TDaemonSession> {
             to: status,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementSetStatus(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementSetStatus(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -958,8 +900,7 @@ impl TSession for Box<dyn TDaemonSession> {
             element_id: element_id.clone(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementGetData(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementGetData(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -974,8 +915,7 @@ impl TSession for Box<dyn TDaemonSession> {
             to: data,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementSetData(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementSetData(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -989,8 +929,7 @@ impl TSession for Box<dyn TDaemonSession> {
             element_id: element_id.clone(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementGetProgress(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementGetProgress(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1009,8 +948,7 @@ impl TSession for Box<dyn TDaemonSession> {
             to: progress,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementSetProgress(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementSetProgress(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1024,8 +962,7 @@ impl TSession for Box<dyn TDaemonSession> {
             element_id: element_id.clone(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementGetShouldSave(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementGetShouldSave(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1044,8 +981,7 @@ impl TSession for Box<dyn TDaemonSession> {
             to: should_save,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementSetShouldSave(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementSetShouldSave(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1059,8 +995,7 @@ impl TSession for Box<dyn TDaemonSession> {
             element_id: element_id.clone(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementGetEnabled(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementGetEnabled(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1086,8 +1021,7 @@ impl TSession for Box<dyn TDaemonSession> {
             to: enabled,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementSetEnabled(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementSetEnabled(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1101,8 +1035,7 @@ impl TSession for Box<dyn TDaemonSession> {
             element_id: element_id.clone(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementIsError(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementIsError(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1116,8 +1049,7 @@ impl TSession for Box<dyn TDaemonSession> {
             element_id: element_id.clone(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementResolvModule(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementResolvModule(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1125,16 +1057,14 @@ impl TSession for Box<dyn TDaemonSession> {
     }
 
     fn element_wait(&self, element_id: &ElementId) -> Result<(), SessionError> {
-        // TODO: Fix daemon problem in ServerPackets::ElementWait
-
         let id = self.generate();
         let packet = ServerPackets::ElementWait {
             id,
             element_id: element_id.clone(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementWait(_, response)) = self.waiting_for(id) {
+        // the element can take any amount of time
+        if let ClientPackets::ElementWait(_, response) = self.request_timeout(packet, None)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1151,8 +1081,7 @@ impl TSession for Box<dyn TDaemonSession> {
             element_id: element_id.clone(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementGetInfo(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementGetInfo(_, response) = self.request(packet)? {
             *response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1167,8 +1096,7 @@ impl TSession for Box<dyn TDaemonSession> {
             event,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementNotify(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementNotify(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1183,8 +1111,7 @@ impl TSession for Box<dyn TDaemonSession> {
             event,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementEmit(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementEmit(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1199,8 +1126,7 @@ impl TSession for Box<dyn TDaemonSession> {
             to: _ref,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementSubscribe(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementSubscribe(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1215,8 +1141,7 @@ impl TSession for Box<dyn TDaemonSession> {
             to: _ref,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::ElementUnSubscribe(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::ElementUnSubscribe(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1231,8 +1156,7 @@ impl TSession for Box<dyn TDaemonSession> {
             location_id: location_id.clone(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::CreateLocation(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::CreateLocation(_, response) = self.request(packet)? {
             match response {
                 Ok(ok) => Ok(self.lref_get_or_add(ok)),
                 Err(err) => Err(err),
@@ -1246,8 +1170,7 @@ impl TSession for Box<dyn TDaemonSession> {
         let id = self.generate();
         let packet = ServerPackets::LoadLocationInfo { id, location_info };
 
-        self.send(packet);
-        if let Some(ClientPackets::LoadLocationInfo(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LoadLocationInfo(_, response) = self.request(packet)? {
             match response {
                 Ok(id) => self.get_location_ref(&id),
                 Err(err) => Err(err),
@@ -1264,8 +1187,7 @@ impl TSession for Box<dyn TDaemonSession> {
             location_id: location_id.clone(),
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::GetLocationsLen(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::GetLocationsLen(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1284,8 +1206,7 @@ impl TSession for Box<dyn TDaemonSession> {
             range,
         };
 
-        self.send(packet);
-        if let Some(ClientPackets::GetLocations(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::GetLocations(_, response) = self.request(packet)? {
             match response {
                 Ok(ok) => {
                     let mut tmp = Vec::with_capacity(ok.len());
@@ -1307,8 +1228,7 @@ impl TSession for Box<dyn TDaemonSession> {
         let id = self.generate();
         let packet = ServerPackets::DestroyLocation { id, location_id };
 
-        self.send(packet);
-        if let Some(ClientPackets::DestroyLocation(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::DestroyLocation(_, response) = self.request(packet)? {
             match response {
                 Ok(_) => Err(SessionError::Custom("LRow Cannot be transfered!".into())),
                 Err(err) => Err(err),
@@ -1321,15 +1241,10 @@ impl TSession for Box<dyn TDaemonSession> {
     fn get_default_location(&self) -> Result<LRef, SessionError> {
         let id = self.generate();
         let packet = ServerPackets::GetDefaultLocation { id };
-        self.send(packet);
-        if let Some(packet) = self.waiting_for(id) {
-            if let ClientPackets::GetDefaultLocation(_, response) = packet {
-                match response {
-                    Ok(ok) => Ok(self.lref_get_or_add(ok)),
-                    Err(err) => Err(err),
-                }
-            } else {
-                panic!()
+        if let ClientPackets::GetDefaultLocation(_, response) = self.request(packet)? {
+            match response {
+                Ok(ok) => Ok(self.lref_get_or_add(ok)),
+                Err(err) => Err(err),
             }
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1343,8 +1258,7 @@ impl TSession for Box<dyn TDaemonSession> {
             location_id: location_id.clone(),
             to: to.clone(),
         };
-        self.send(packet);
-        if let Some(ClientPackets::MoveLocation(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::MoveLocation(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1357,8 +1271,7 @@ impl TSession for Box<dyn TDaemonSession> {
             id,
             from: location_id.clone(),
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationGetName(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationGetName(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1372,8 +1285,7 @@ impl TSession for Box<dyn TDaemonSession> {
             from: location_id.clone(),
             to: name.to_string(),
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationSetName(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationSetName(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1386,8 +1298,7 @@ impl TSession for Box<dyn TDaemonSession> {
             id,
             from: location_id.clone(),
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationGetDesc(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationGetDesc(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1401,8 +1312,7 @@ impl TSession for Box<dyn TDaemonSession> {
             from: location_id.clone(),
             to: desc.to_string(),
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationSetDesc(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationSetDesc(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1415,8 +1325,7 @@ impl TSession for Box<dyn TDaemonSession> {
             id,
             location_id: location_id.clone(),
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationGetPath(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationGetPath(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1434,8 +1343,7 @@ impl TSession for Box<dyn TDaemonSession> {
             location_id: location_id.clone(),
             to: path,
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationSetPath(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationSetPath(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1463,8 +1371,7 @@ impl TSession for Box<dyn TDaemonSession> {
             id,
             location_id: location_id.clone(),
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationGetShouldSave(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationGetShouldSave(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1482,8 +1389,7 @@ impl TSession for Box<dyn TDaemonSession> {
             location_id: location_id.clone(),
             to: should_save,
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationSetShouldSave(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationSetShouldSave(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1496,8 +1402,7 @@ impl TSession for Box<dyn TDaemonSession> {
             id,
             location_id: location_id.clone(),
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationGetElementsLen(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationGetElementsLen(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1515,8 +1420,7 @@ impl TSession for Box<dyn TDaemonSession> {
             location_id: location_id.clone(),
             range,
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationGetElements(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationGetElements(_, response) = self.request(packet)? {
             match response {
                 Ok(ok) => {
                     let mut tmp = Vec::with_capacity(ok.len());
@@ -1540,8 +1444,7 @@ impl TSession for Box<dyn TDaemonSession> {
             id,
             location_id: location_id.clone(),
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationGetModule(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationGetModule(_, response) = self.request(packet)? {
             match response {
                 Ok(option_module_id) => {
                     if let Some(module_id) = option_module_id {
@@ -1568,8 +1471,7 @@ impl TSession for Box<dyn TDaemonSession> {
             location_id: location_id.clone(),
             module_id,
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationSetModule(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationSetModule(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1582,8 +1484,7 @@ impl TSession for Box<dyn TDaemonSession> {
             id,
             location_id: location_id.clone(),
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationGetSettings(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationGetSettings(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1601,8 +1502,7 @@ impl TSession for Box<dyn TDaemonSession> {
             location_id: location_id.clone(),
             to: data,
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationSetSettings(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationSetSettings(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1618,8 +1518,7 @@ impl TSession for Box<dyn TDaemonSession> {
             id,
             location_id: location_id.clone(),
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationGetModuleSettings(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationGetModuleSettings(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1637,8 +1536,7 @@ impl TSession for Box<dyn TDaemonSession> {
             location_id: location_id.clone(),
             to: data,
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationSetModuleSettings(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationSetModuleSettings(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1651,8 +1549,7 @@ impl TSession for Box<dyn TDaemonSession> {
             id,
             location_id: location_id.clone(),
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationGetStatuses(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationGetStatuses(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1670,8 +1567,7 @@ impl TSession for Box<dyn TDaemonSession> {
             location_id: location_id.clone(),
             statuses,
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationSetStatuses(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationSetStatuses(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1684,8 +1580,7 @@ impl TSession for Box<dyn TDaemonSession> {
             id,
             location_id: location_id.clone(),
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationGetStatus(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationGetStatus(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1703,8 +1598,7 @@ impl TSession for Box<dyn TDaemonSession> {
             location_id: location_id.clone(),
             to: status,
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationSetStatus(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationSetStatus(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1717,8 +1611,7 @@ impl TSession for Box<dyn TDaemonSession> {
             id,
             location_id: location_id.clone(),
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationGetProgress(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationGetProgress(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1736,8 +1629,7 @@ impl TSession for Box<dyn TDaemonSession> {
             location_id: location_id.clone(),
             to: progress,
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationSetProgress(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationSetProgress(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1750,8 +1642,7 @@ impl TSession for Box<dyn TDaemonSession> {
             id,
             location_id: location_id.clone(),
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationIsEnabled(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationIsEnabled(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1774,8 +1665,7 @@ impl TSession for Box<dyn TDaemonSession> {
             location_id: location_id.clone(),
             to: enabled,
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationSetEnabled(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationSetEnabled(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1788,8 +1678,7 @@ impl TSession for Box<dyn TDaemonSession> {
             id,
             location_id: location_id.clone(),
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationIsError(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationIsError(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1805,8 +1694,7 @@ impl TSession for Box<dyn TDaemonSession> {
             id,
             from: location_id.clone(),
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationGetInfo(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationGetInfo(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1820,8 +1708,7 @@ impl TSession for Box<dyn TDaemonSession> {
             location_id: location_id.clone(),
             event,
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationNotify(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationNotify(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1835,8 +1722,7 @@ impl TSession for Box<dyn TDaemonSession> {
             location_id: location_id.clone(),
             event,
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationEmit(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationEmit(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1850,8 +1736,7 @@ impl TSession for Box<dyn TDaemonSession> {
             location_id: location_id.clone(),
             to: _ref,
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationSubscribe(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationSubscribe(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1865,8 +1750,7 @@ impl TSession for Box<dyn TDaemonSession> {
             location_id: location_id.clone(),
             to: _ref,
         };
-        self.send(packet);
-        if let Some(ClientPackets::LocationUnSubscribe(_, response)) = self.waiting_for(id) {
+        if let ClientPackets::LocationUnSubscribe(_, response) = self.request(packet)? {
             response
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1888,8 +1772,7 @@ impl TSession for Box<dyn TDaemonSession> {
     fn get_version(&self) -> Result<u64, SessionError> {
         let id = self.generate();
         let packet = ServerPackets::GetVersion { id };
-        self.send(packet);
-        if let Some(ClientPackets::GetVersion(_, res)) = self.waiting_for(id) {
+        if let ClientPackets::GetVersion(_, res) = self.request(packet)? {
             res
         } else {
             Err(SessionError::ServerTimeOut)
@@ -1899,9 +1782,8 @@ impl TSession for Box<dyn TDaemonSession> {
     fn get_version_text(&self) -> Result<String, SessionError> {
         let id = self.generate();
         let packet = ServerPackets::GetVersionText { id };
-        self.send(packet);
-        if let Some(ClientPackets::GetVersionText(_, res)) = self.waiting_for(id) {
-            res.map(|version| format!("{version}, DaemonClient: {DAEMON_CLIENT_VERSION}"))
+        if let ClientPackets::GetVersionText(_, res) = self.request(packet)? {
+            res.map(|version| format!("{version}, DaemonClient: {DAEMON_VERSION}"))
         } else {
             Err(SessionError::ServerTimeOut)
         }
@@ -0,0 +1,327 @@
+use std::{
+    fs,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+
+#[cfg(unix)]
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+
+use bytes_kman::prelude::*;
+
+use crate::packets::{frame::MAX_MESSAGE, ClientPackets, ServerPackets};
+
+/// Memory backed directory where the blobs are created
+const SHM_DIR: &str = "/dev/shm";
+/// Only files with this prefix are read or removed
+/// The prefix is followed by the pid of the process that created the blob
+const PREFIX: &str = "muzzman-daemon-";
+/// Messages bigger than this go through shared memory, when both sides can use it
+/// Smaller messages are faster through the socket than with a file
+pub const SHM_THRESHOLD: usize = 1024 * 1024;
+/// How often the lost blobs are looked for
+pub const GC_INTERVAL: Duration = Duration::new(60, 0);
+
+static GENERATOR: AtomicU64 = AtomicU64::new(0);
+/// This process created a blob, so it can have lost blobs to clean
+static CREATED: AtomicBool = AtomicBool::new(false);
+
+/// Handle of a message that is in shared memory
+/// The blob is owned by the receiver, that removes it when is taken
+/// Its name has the pid of the sender, so a peer can only make the other side read its own blobs
+#[derive(Clone, Debug, Bytes)]
+pub struct ShmBlob {
+    pub name: String,
+    pub len: u64,
+}
+
+/// `false` if this host has no shared memory that can be used
+pub fn available() -> bool {
+    Path::new(SHM_DIR).is_dir()
+}
+
+fn path(name: &str) -> PathBuf {
+    Path::new(SHM_DIR).join(name)
+}
+
+/// Prefix of the blobs that the process created
+fn prefix_of(pid: u32) -> String {
+    format!("{PREFIX}{pid}-")
+}
+
+/// Pid of the process that created the blob
+fn creator(name: &str) -> Option<u32> {
+    name.strip_prefix(PREFIX)?.split('-').next()?.parse().ok()
+}
+
+/// Pid of the process on the other side of the unix socket, only if it runs as the same user
+/// Blobs are exchanged only with such a peer
+#[cfg(target_os = "linux")]
+pub fn same_user_peer(socket: &impl std::os::unix::io::AsRawFd) -> Option<u32> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: `cred` and `len` are valid for the size that is given
+    let res = unsafe {
+        libc::getsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    // SAFETY: geteuid cannot fail
+    if res != 0 || cred.uid != unsafe { libc::geteuid() } {
+        return None;
+    }
+    Some(cred.pid as u32)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn same_user_peer<T>(_socket: &T) -> Option<u32> {
+    None
+}
+
+/// Writes the message in a new blob, only the same user can read it
+pub fn create(bytes: &[u8]) -> Result<ShmBlob, std::io::Error> {
+    let name = format!(
+        "{}{}",
+        prefix_of(std::process::id()),
+        GENERATOR.fetch_add(1, Ordering::Relaxed)
+    );
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600).custom_flags(libc::O_NOFOLLOW);
+
+    let mut file = options.open(path(&name))?;
+    CREATED.store(true, Ordering::Relaxed);
+    if let Err(err) = file.write_all(bytes) {
+        let _ = fs::remove_file(path(&name));
+        return Err(err);
+    }
+
+    Ok(ShmBlob {
+        name,
+        len: bytes.len() as u64,
+    })
+}
+
+/// Reads the message and removes the blob
+/// `from` is the pid of the peer, only the blobs that it created can be taken
+pub fn take(blob: &ShmBlob, from: u32) -> Result<Vec<u8>, std::io::Error> {
+    // the name comes from the other side, it cannot point outside of the blobs of the peer
+    if !blob.name.starts_with(&prefix_of(from))
+        || blob.name.contains('/')
+        || blob.name.contains('\\')
+    {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Invalid blob: {}", blob.name),
+        ));
+    }
+    if blob.len > MAX_MESSAGE as u64 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Blob too big: {}", blob.len),
+        ));
+    }
+
+    let path = path(&blob.name);
+    let bytes = read_blob(&path, blob.len);
+    let _ = fs::remove_file(&path);
+    let bytes = bytes?;
+
+    if bytes.len() as u64 != blob.len {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            format!(
+                "Blob: {} has {} bytes expected: {}",
+                blob.name,
+                bytes.len(),
+                blob.len
+            ),
+        ));
+    }
+    Ok(bytes)
+}
+
+/// Reads at most `len` bytes, only from a regular file of this user that is not a link
+fn read_blob(path: &Path, len: u64) -> Result<Vec<u8>, std::io::Error> {
+    let mut options = fs::OpenOptions::new();
+    options.read(true);
+    #[cfg(unix)]
+    options.custom_flags(libc::O_NOFOLLOW);
+    let file = options.open(path)?;
+
+    let metadata = file.metadata()?;
+    #[cfg(unix)]
+    // SAFETY: geteuid cannot fail
+    let foreign = metadata.uid() != unsafe { libc::geteuid() };
+    #[cfg(not(unix))]
+    let foreign = false;
+    if !metadata.is_file() || foreign {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!("Blob is not a file of this user: {path:?}"),
+        ));
+    }
+
+    // one more byte, to know if the file is bigger than the blob says
+    let mut bytes = Vec::new();
+    file.take(len + 1).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Moves a big message to shared memory and returns the message of its handle
+/// Small messages, or if the blob cannot be created, are returned as they are
+fn offload(bytes: Vec<u8>, envelope: impl FnOnce(ShmBlob) -> Vec<u8>) -> Vec<u8> {
+    if bytes.len() <= SHM_THRESHOLD {
+        return bytes;
+    }
+
+    match create(&bytes) {
+        Ok(blob) => envelope(blob),
+        Err(err) => {
+            log::warn!("Cannot create blob, sent through the socket: {err}");
+            bytes
+        }
+    }
+}
+
+/// The message of a `ServerPackets`, the message of a `ServerPackets::Shm` if is big
+pub fn offload_request(id: u128, bytes: Vec<u8>) -> Vec<u8> {
+    offload(bytes, |blob| {
+        let mut bytes = ServerPackets::Shm { id, blob }.to_bytes();
+        bytes.reverse();
+        bytes
+    })
+}
+
+/// The message of a `ClientPackets`, the message of a `ClientPackets::Shm` if is big
+pub fn offload_response(id: u128, bytes: Vec<u8>) -> Vec<u8> {
+    offload(bytes, |blob| {
+        let mut bytes = ClientPackets::Shm(id, blob).to_bytes();
+        bytes.reverse();
+        bytes
+    })
+}
+
+/// The packet in the blob of a `ServerPackets::Shm` sent by `from`
+pub fn take_request(blob: &ShmBlob, from: u32) -> Result<ServerPackets, std::io::Error> {
+    let mut bytes = take(blob, from)?;
+    match ServerPackets::from_bytes(&mut bytes) {
+        Some(packet) if bytes.is_empty() => Ok(packet),
+        _ => Err(malformed(blob)),
+    }
+}
+
+/// The packet in the blob of a `ClientPackets::Shm` sent by `from`
+pub fn take_response(blob: &ShmBlob, from: u32) -> Result<ClientPackets, std::io::Error> {
+    let mut bytes = take(blob, from)?;
+    match ClientPackets::from_bytes(&mut bytes) {
+        Some(packet) if bytes.is_empty() => Ok(packet),
+        _ => Err(malformed(blob)),
+    }
+}
+
+fn malformed(blob: &ShmBlob) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("Malformed packet in blob: {}", blob.name),
+    )
+}
+
+/// If this process created a blob since it started
+pub fn created() -> bool {
+    CREATED.load(Ordering::Relaxed)
+}
+
+/// `false` only if the process is known to be dead
+fn is_alive(pid: u32) -> bool {
+    #[cfg(target_os = "linux")]
+    return Path::new("/proc").join(pid.to_string()).exists();
+    #[cfg(not(target_os = "linux"))]
+    return true;
+}
+
+/// Removes the blobs that were not taken for longer than `max_age`, their message was lost
+/// Only the blobs of this process and of processes that are dead, the others are cleaned by their creator
+pub fn gc(max_age: Duration) {
+    let Ok(entries) = fs::read_dir(SHM_DIR) else {
+        return;
+    };
+
+    let own = std::process::id();
+    for entry in entries.flatten() {
+        let Some(pid) = creator(&entry.file_name().to_string_lossy()) else {
+            continue;
+        };
+        if pid != own && is_alive(pid) {
+            continue;
+        }
+
+        let expired = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .map(|modified| modified.elapsed().unwrap_or_default() > max_age)
+            .unwrap_or(false);
+        if expired {
+            log::debug!("Removed lost blob: {:?}", entry.file_name());
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn creator_is_in_the_name() {
+        assert_eq!(creator("muzzman-daemon-42-7"), Some(42));
+        assert_eq!(creator("muzzman-daemon-x-7"), None);
+        assert_eq!(creator("other-42-7"), None);
+    }
+
+    #[test]
+    fn takes_own_blob_once() {
+        if !available() {
+            return;
+        }
+        let blob = create(b"message").unwrap();
+
+        assert_eq!(take(&blob, std::process::id()).unwrap(), b"message");
+        assert!(take(&blob, std::process::id()).is_err());
+    }
+
+    #[test]
+    fn blob_of_another_process_is_refused() {
+        if !available() {
+            return;
+        }
+        let blob = create(b"message").unwrap();
+
+        assert!(take(&blob, std::process::id() + 1).is_err());
+        // was not touched
+        assert!(path(&blob.name).exists());
+        take(&blob, std::process::id()).unwrap();
+    }
+
+    #[test]
+    fn name_cannot_leave_the_blobs() {
+        let blob = ShmBlob {
+            name: format!("{}../../etc/passwd", prefix_of(std::process::id())),
+            len: 1,
+        };
+
+        assert!(take(&blob, std::process::id()).is_err());
+    }
+}
@@ -0,0 +1,609 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{SocketAddr, TcpStream, UdpSocket},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+};
+
+#[cfg(unix)]
+use std::{os::unix::net::UnixStream, path::PathBuf};
+
+use crate::{
+    packets::frame::{Frame, Reassembler, MAX_DATAGRAM, MAX_MESSAGE, REQUEST_HEADER},
+    DAEMON_PORT,
+};
+
+/// Where the daemon can be found
+#[derive(Clone, Debug)]
+pub enum DaemonAddress {
+    Udp(SocketAddr),
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl Default for DaemonAddress {
+    fn default() -> Self {
+        Self::Udp(SocketAddr::from(([127, 0, 0, 1], DAEMON_PORT)))
+    }
+}
+
+impl DaemonAddress {
+    /// The daemon is on this host
+    pub fn is_local(&self) -> bool {
+        match self {
+            DaemonAddress::Udp(addr) | DaemonAddress::Tcp(addr) => addr.ip().is_loopback(),
+            #[cfg(unix)]
+            DaemonAddress::Unix(_) => true,
+        }
+    }
+}
+
+#[cfg(unix)]
+impl DaemonAddress {
+    pub fn unix() -> Self {
+        Self::Unix(crate::common::get_socket_path())
+    }
+}
+
+pub enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+pub enum Received {
+    Message(u128, Vec<u8>),
+    /// the response for the request was lost
+    Incomplete(u128),
+}
+
+/// Blocking client side of the daemon transports
+/// Datagram transports are framed, stream transports are length prefixed
+/// and the responses on streams start with their request
+/// This is the sending half, the messages are received by its `TransportReader`
+pub struct Transport {
+    pub conn: Connection,
+    message_generator: u64,
+    /// set by `drive`, the bytes are written by an async task
+    outgoing: Option<UnboundedSender<Vec<u8>>>,
+}
+
+/// Receiving half of a `Transport`, has its own handle of the socket
+/// so one thread can wait for messages while the others send
+pub struct TransportReader {
+    conn: Connection,
+    reassembler: Reassembler<()>,
+    stream_buffer: Vec<u8>,
+    /// the daemon closed the stream
+    pub closed: bool,
+    /// the socket is read by an async task, that hands what it reads to `push`
+    driven: bool,
+}
+
+/// Receiving half of a socket that is driven by async tasks
+pub(crate) enum AsyncReader {
+    Udp(Arc<tokio::net::UdpSocket>),
+    Stream(Box<dyn AsyncRead + Unpin + Send>),
+}
+
+/// Sending half of a socket that is driven by async tasks
+pub(crate) enum AsyncWriter {
+    Udp(Arc<tokio::net::UdpSocket>),
+    Stream(Box<dyn AsyncWrite + Unpin + Send>),
+}
+
+impl Connection {
+    fn try_clone(&self) -> Result<Self, std::io::Error> {
+        Ok(match self {
+            Connection::Udp(conn) => Connection::Udp(conn.try_clone()?),
+            Connection::Tcp(conn) => Connection::Tcp(conn.try_clone()?),
+            #[cfg(unix)]
+            Connection::Unix(conn) => Connection::Unix(conn.try_clone()?),
+        })
+    }
+
+    fn set_read_timeout(&self, timeout: Duration) -> Result<(), std::io::Error> {
+        // a zero timeout is refused by the sockets
+        let timeout = Some(timeout.max(Duration::from_micros(1)));
+        match self {
+            Connection::Udp(conn) => conn.set_read_timeout(timeout),
+            Connection::Tcp(conn) => conn.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Connection::Unix(conn) => conn.set_read_timeout(timeout),
+        }
+    }
+}
+
+impl Transport {
+    /// `timeout` is for establishing the connection and for every write
+    pub fn connect(
+        address: &DaemonAddress,
+        timeout: Duration,
+    ) -> Result<(Self, TransportReader), std::io::Error> {
+        let conn = match address {
+            DaemonAddress::Udp(addr) => {
+                let conn = UdpSocket::bind("127.0.0.1:0")?;
+                conn.connect(addr)?;
+                Connection::Udp(conn)
+            }
+            DaemonAddress::Tcp(addr) => {
+                let conn = TcpStream::connect_timeout(addr, timeout)?;
+                conn.set_nodelay(true)?;
+                conn.set_write_timeout(Some(timeout))?;
+                Connection::Tcp(conn)
+            }
+            #[cfg(unix)]
+            DaemonAddress::Unix(path) => {
+                let conn = UnixStream::connect(path)?;
+                conn.set_write_timeout(Some(timeout))?;
+                Connection::Unix(conn)
+            }
+        };
+
+        let reader = TransportReader {
+            conn: conn.try_clone()?,
+            reassembler: Reassembler::default(),
+            stream_buffer: Vec::new(),
+            closed: false,
+            driven: false,
+        };
+        let transport = Self {
+            conn,
+            message_generator: 1,
+            outgoing: None,
+        };
+        Ok((transport, reader))
+    }
+
+    /// Pid of the daemon if it runs as the same user, connected by a unix socket
+    pub fn shm_peer(&self) -> Option<u32> {
+        match &self.conn {
+            #[cfg(unix)]
+            Connection::Unix(conn) => crate::shm::same_user_peer(conn),
+            _ => None,
+        }
+    }
+
+    /// Blocks until the daemon takes the message or the write timeout
+    /// After `drive` only queues the message for the async task
+    pub fn send(&mut self, request: u128, bytes: &[u8]) -> Result<(), std::io::Error> {
+        if let Some(outgoing) = &self.outgoing {
+            let queued = match self.conn {
+                Connection::Udp(_) => {
+                    let message = self.message_generator;
+                    self.message_generator += 1;
+                    Frame::split(message, request, bytes)
+                        .into_iter()
+                        .try_for_each(|datagram| outgoing.send(datagram))
+                }
+                _ => outgoing.send(prefixed(bytes)),
+            };
+            return queued.map_err(|_| std::io::Error::from(ErrorKind::BrokenPipe));
+        }
+
+        match &mut self.conn {
+            Connection::Udp(conn) => {
+                let message = self.message_generator;
+                self.message_generator += 1;
+
+                for datagram in Frame::split(message, request, bytes) {
+                    conn.send(&datagram)?;
+                }
+            }
+            Connection::Tcp(conn) => write_message(conn, bytes)?,
+            #[cfg(unix)]
+            Connection::Unix(conn) => write_message(conn, bytes)?,
+        }
+        Ok(())
+    }
+
+    /// From now the socket is read and written by async tasks, `send` queues the messages for them
+    /// The messages are taken from the returned receiver, what is read is given to `TransportReader::push`
+    /// Must be called inside a tokio runtime
+    pub(crate) fn drive(
+        &mut self,
+        reader: &mut TransportReader,
+    ) -> Result<(AsyncReader, AsyncWriter, UnboundedReceiver<Vec<u8>>), std::io::Error> {
+        // the socket is shared with the blocking handles, that are not used for io anymore
+        let (read, write) = match &self.conn {
+            Connection::Udp(conn) => {
+                let conn = conn.try_clone()?;
+                conn.set_nonblocking(true)?;
+                let conn = Arc::new(tokio::net::UdpSocket::from_std(conn)?);
+                (AsyncReader::Udp(conn.clone()), AsyncWriter::Udp(conn))
+            }
+            Connection::Tcp(conn) => {
+                let conn = conn.try_clone()?;
+                conn.set_nonblocking(true)?;
+                let (read, write) = tokio::net::TcpStream::from_std(conn)?.into_split();
+                (
+                    AsyncReader::Stream(Box::new(read)),
+                    AsyncWriter::Stream(Box::new(write)),
+                )
+            }
+            #[cfg(unix)]
+            Connection::Unix(conn) => {
+                let conn = conn.try_clone()?;
+                conn.set_nonblocking(true)?;
+                let (read, write) = tokio::net::UnixStream::from_std(conn)?.into_split();
+                (
+                    AsyncReader::Stream(Box::new(read)),
+                    AsyncWriter::Stream(Box::new(write)),
+                )
+            }
+        };
+
+        let (outgoing, queued) = unbounded_channel();
+        self.outgoing = Some(outgoing);
+        reader.driven = true;
+        Ok((read, write, queued))
+    }
+}
+
+impl AsyncReader {
+    /// `Ok(None)` when the stream was closed
+    pub(crate) async fn read(
+        &mut self,
+        buffer: &mut [u8],
+    ) -> Result<Option<usize>, std::io::Error> {
+        match self {
+            AsyncReader::Udp(conn) => conn.recv(buffer).await.map(Some),
+            AsyncReader::Stream(conn) => match conn.read(buffer).await? {
+                0 => Ok(None),
+                len => Ok(Some(len)),
+            },
+        }
+    }
+}
+
+impl AsyncWriter {
+    /// Writes every queued message until the `Transport` is dropped or the stream fails
+    pub(crate) async fn write_queued(mut self, mut queued: UnboundedReceiver<Vec<u8>>) {
+        while let Some(bytes) = queued.recv().await {
+            let written = match &mut self {
+                AsyncWriter::Udp(conn) => conn.send(&bytes).await.map(|_| ()),
+                AsyncWriter::Stream(conn) => conn.write_all(&bytes).await,
+            };
+            if let Err(err) = written {
+                log::error!("Cannot send packet: {err}");
+                // a datagram is only lost, a stream is broken
+                if let AsyncWriter::Stream(_) = self {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+impl TransportReader {
+    /// Blocks until a message is complete or `timeout` passes, returns every message that is complete
+    pub fn recv(&mut self, timeout: Duration) -> Vec<Received> {
+        // the async task reads the socket
+        if self.driven {
+            return Vec::new();
+        }
+        // nothing will arrive anymore, the caller still expects to be blocked
+        if self.closed {
+            std::thread::sleep(timeout);
+            return Vec::new();
+        }
+
+        let start_time = SystemTime::now();
+        let mut received = Vec::new();
+
+        loop {
+            let remaining = timeout.saturating_sub(start_time.elapsed().unwrap_or_default());
+            if self.conn.set_read_timeout(remaining).is_err() {
+                break;
+            }
+
+            let read = match &mut self.conn {
+                Connection::Udp(conn) => recv_datagram(conn, &mut self.reassembler, &mut received),
+                Connection::Tcp(conn) => recv_stream(
+                    conn,
+                    &mut self.stream_buffer,
+                    &mut self.closed,
+                    &mut received,
+                ),
+                #[cfg(unix)]
+                Connection::Unix(conn) => recv_stream(
+                    conn,
+                    &mut self.stream_buffer,
+                    &mut self.closed,
+                    &mut received,
+                ),
+            };
+
+            if !read || self.closed || !received.is_empty() || remaining.is_zero() {
+                break;
+            }
+        }
+
+        received.extend(self.gc());
+        received
+    }
+
+    /// What the async task read, a datagram or the next bytes of the stream
+    /// Returns every message that is complete
+    pub(crate) fn push(&mut self, bytes: &[u8]) -> Vec<Received> {
+        let mut received = Vec::new();
+        match self.conn {
+            Connection::Udp(_) => accept_datagram(&mut self.reassembler, bytes, &mut received),
+            _ => accept_stream(
+                &mut self.stream_buffer,
+                &mut self.closed,
+                bytes,
+                &mut received,
+            ),
+        }
+        received
+    }
+
+    /// The responses whose chunks stopped arriving
+    pub(crate) fn gc(&mut self) -> Vec<Received> {
+        self.reassembler
+            .gc()
+            .into_iter()
+            .map(|incomplete| {
+                log::error!(
+                    "Response for: {} arrived incomplete, {} of {} chunks",
+                    incomplete.request,
+                    incomplete.received,
+                    incomplete.count
+                );
+                Received::Incomplete(incomplete.request)
+            })
+            .collect()
+    }
+
+    /// If a message for `request` is still arriving
+    pub fn in_progress(&self, request: u128) -> bool {
+        match self.conn {
+            Connection::Udp(_) => self.reassembler.in_progress(request),
+            _ => buffered_request(&self.stream_buffer) == Some(request),
+        }
+    }
+}
+
+/// One datagram, returns `false` if nothing can be read before the read timeout
+fn recv_datagram(
+    conn: &UdpSocket,
+    reassembler: &mut Reassembler<()>,
+    received: &mut Vec<Received>,
+) -> bool {
+    let mut buffer = [0; MAX_DATAGRAM];
+    let len = match conn.recv(&mut buffer) {
+        Ok(len) => len,
+        Err(err) => return err.kind() == ErrorKind::Interrupted,
+    };
+
+    accept_datagram(reassembler, &buffer[0..len], received);
+    true
+}
+
+fn accept_datagram(
+    reassembler: &mut Reassembler<()>,
+    datagram: &[u8],
+    received: &mut Vec<Received>,
+) {
+    let Some(frame) = Frame::decode(datagram) else {
+        log::warn!("Dropped invalid frame from daemon");
+        return;
+    };
+    if let Some((request, message)) = reassembler.push((), frame) {
+        received.push(Received::Message(request, message))
+    }
+}
+
+pub fn write_message(conn: &mut impl Write, bytes: &[u8]) -> Result<(), std::io::Error> {
+    conn.write_all(&prefixed(bytes))
+}
+
+/// The message with its length before it
+fn prefixed(bytes: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(bytes.len() + 4);
+    message.extend((bytes.len() as u32).to_le_bytes());
+    message.extend(bytes);
+    message
+}
+
+/// One read, returns `false` if nothing can be read before the read timeout
+fn recv_stream(
+    conn: &mut impl Read,
+    stream_buffer: &mut Vec<u8>,
+    closed: &mut bool,
+    received: &mut Vec<Received>,
+) -> bool {
+    let mut buffer = [0; MAX_DATAGRAM];
+    match conn.read(&mut buffer) {
+        Ok(0) => {
+            log::error!("Daemon closed the connection");
+            *closed = true;
+            return false;
+        }
+        Ok(len) => accept_stream(stream_buffer, closed, &buffer[0..len], received),
+        Err(err) if err.kind() == ErrorKind::Interrupted => return true,
+        Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+            return false
+        }
+        Err(err) => {
+            log::error!("Cannot read from the daemon: {err}");
+            *closed = true;
+            return false;
+        }
+    }
+    true
+}
+
+fn accept_stream(
+    stream_buffer: &mut Vec<u8>,
+    closed: &mut bool,
+    bytes: &[u8],
+    received: &mut Vec<Received>,
+) {
+    stream_buffer.extend(bytes);
+    loop {
+        match take_message(stream_buffer) {
+            Ok(Some(mut message)) if message.len() >= REQUEST_HEADER => {
+                let request = message.drain(..REQUEST_HEADER).collect::<Vec<u8>>();
+                let request = u128::from_le_bytes(request.try_into().unwrap());
+                received.push(Received::Message(request, message))
+            }
+            Ok(Some(_)) => {
+                log::error!("Response without request, closing the connection");
+                stream_buffer.clear();
+                *closed = true;
+                break;
+            }
+            Ok(None) => break,
+            // the framing is lost, nothing after can be trusted
+            Err(err) => {
+                log::error!("{err}, closing the connection");
+                stream_buffer.clear();
+                *closed = true;
+                break;
+            }
+        }
+    }
+}
+
+/// Request of the response that is buffered but not complete yet
+fn buffered_request(stream_buffer: &[u8]) -> Option<u128> {
+    let header = stream_buffer.get(4..4 + REQUEST_HEADER)?;
+    Some(u128::from_le_bytes(header.try_into().unwrap()))
+}
+
+/// Takes the first length prefixed message from the buffer if is complete
+/// Errors if the length is too big, the stream should be closed
+pub fn take_message(stream_buffer: &mut Vec<u8>) -> Result<Option<Vec<u8>>, std::io::Error> {
+    if stream_buffer.len() < 4 {
+        return Ok(None);
+    }
+
+    let len = u32::from_le_bytes([
+        stream_buffer[0],
+        stream_buffer[1],
+        stream_buffer[2],
+        stream_buffer[3],
+    ]) as usize;
+
+    if len > MAX_MESSAGE {
+        return Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("Message too big: {len}"),
+        ));
+    }
+
+    if stream_buffer.len() < len + 4 {
+        return Ok(None);
+    }
+
+    let message = stream_buffer[4..len + 4].to_vec();
+    stream_buffer.drain(0..len + 4);
+    Ok(Some(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_complete_messages() {
+        let mut buffer = prefixed(b"first");
+        buffer.extend(prefixed(b"second"));
+
+        assert_eq!(take_message(&mut buffer).unwrap(), Some(b"first".to_vec()));
+        assert_eq!(take_message(&mut buffer).unwrap(), Some(b"second".to_vec()));
+        assert_eq!(take_message(&mut buffer).unwrap(), None);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn waits_for_partial_message() {
+        let message = prefixed(b"message");
+        let mut buffer = message[..6].to_vec();
+
+        assert_eq!(take_message(&mut buffer).unwrap(), None);
+        buffer.extend(&message[6..]);
+        assert_eq!(
+            take_message(&mut buffer).unwrap(),
+            Some(b"message".to_vec())
+        );
+    }
+
+    #[test]
+    fn partial_response_is_of_its_request() {
+        let mut response = 7u128.to_le_bytes().to_vec();
+        response.extend(b"response");
+        let message = prefixed(&response);
+
+        assert_eq!(buffered_request(&message[..10]), None);
+        assert_eq!(buffered_request(&message[..24]), Some(7));
+    }
+
+    #[test]
+    fn too_big_message_is_an_error() {
+        let mut buffer = (MAX_MESSAGE as u32 + 1).to_le_bytes().to_vec();
+
+        assert!(take_message(&mut buffer).is_err());
+    }
+
+    #[test]
+    fn write_message_is_length_prefixed() {
+        let mut written = Vec::new();
+        write_message(&mut written, b"message").unwrap();
+
+        assert_eq!(written, prefixed(b"message"));
+    }
+
+    #[test]
+    fn driven_transport_is_read_and_written_by_tasks() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = DaemonAddress::Tcp(listener.local_addr().unwrap());
+        let (mut transport, mut reader) =
+            Transport::connect(&address, Duration::new(1, 0)).unwrap();
+        let (mut daemon, _) = listener.accept().unwrap();
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let received = runtime.block_on(async {
+            let (mut conn, writer, queued) = transport.drive(&mut reader).unwrap();
+            tokio::spawn(writer.write_queued(queued));
+            transport.send(7, b"request").unwrap();
+
+            let mut response = 7u128.to_le_bytes().to_vec();
+            response.extend(b"response");
+            write_message(&mut daemon, &response).unwrap();
+
+            let mut buffer = [0; MAX_DATAGRAM];
+            let mut received = Vec::new();
+            while received.is_empty() {
+                let len = conn.read(&mut buffer).await.unwrap().unwrap();
+                received = reader.push(&buffer[..len]);
+            }
+            received
+        });
+
+        let mut written = Vec::new();
+        let request = loop {
+            if let Some(request) = take_message(&mut written).unwrap() {
+                break request;
+            }
+            let mut buffer = [0; 64];
+            let len = daemon.read(&mut buffer).unwrap();
+            written.extend(&buffer[..len]);
+        };
+        assert_eq!(request, b"request");
+        assert!(matches!(
+            received.as_slice(),
+            [Received::Message(7, response)] if response == b"response"
+        ));
+        assert!(reader.recv(Duration::ZERO).is_empty());
+    }
+}
@@ -0,0 +1,185 @@
+use muzzman_lib::prelude::*;
+
+use crate::packets::{ClientPackets, ServerPackets, TreeField, TreeFields};
+
+/// Reads the fields of the location from the session, one call for every field
+pub fn location_fields(
+    session: &dyn TSession,
+    location_id: &LocationId,
+    fields: &[TreeField],
+) -> TreeFields {
+    let mut tree_fields = TreeFields::default();
+    for field in fields {
+        match field {
+            TreeField::Name => tree_fields.name = session.location_get_name(location_id).ok(),
+            TreeField::Desc => tree_fields.desc = session.location_get_desc(location_id).ok(),
+            TreeField::Enabled => {
+                tree_fields.enabled = session.location_is_enabled(location_id).ok()
+            }
+            TreeField::Progress => {
+                tree_fields.progress = session.location_get_progress(location_id).ok()
+            }
+            TreeField::Status => tree_fields.status = session.location_get_status(location_id).ok(),
+            TreeField::IsError => {
+                tree_fields.is_error = session.location_is_error(location_id).ok()
+            }
+        }
+    }
+    tree_fields
+}
+
+/// Reads the fields of the element from the session, one call for every field
+pub fn element_fields(
+    session: &dyn TSession,
+    element_id: &ElementId,
+    fields: &[TreeField],
+) -> TreeFields {
+    let mut tree_fields = TreeFields::default();
+    for field in fields {
+        match field {
+            TreeField::Name => tree_fields.name = session.element_get_name(element_id).ok(),
+            TreeField::Desc => tree_fields.desc = session.element_get_desc(element_id).ok(),
+            TreeField::Enabled => {
+                tree_fields.enabled = session.element_get_enabled(element_id).ok()
+            }
+            TreeField::Progress => {
+                tree_fields.progress = session.element_get_progress(element_id).ok()
+            }
+            TreeField::Status => tree_fields.status = session.element_get_status(element_id).ok(),
+            TreeField::IsError => tree_fields.is_error = session.element_is_error(element_id).ok(),
+        }
+    }
+    tree_fields
+}
+
+/// Requests that read the fields of the location, to be sent in one `ServerPackets::Batch`
+/// The responses are read by `fields_from_responses`
+pub fn location_field_requests(
+    location_id: &LocationId,
+    fields: &[TreeField],
+) -> Vec<ServerPackets> {
+    let (id, location_id) = (0, location_id.clone());
+    fields
+        .iter()
+        .map(|field| match field {
+            TreeField::Name => ServerPackets::LocationGetName {
+                id,
+                from: location_id.clone(),
+            },
+            TreeField::Desc => ServerPackets::LocationGetDesc {
+                id,
+                from: location_id.clone(),
+            },
+            TreeField::Enabled => ServerPackets::LocationIsEnabled {
+                id,
+                location_id: location_id.clone(),
+            },
+            TreeField::Progress => ServerPackets::LocationGetProgress {
+                id,
+                location_id: location_id.clone(),
+            },
+            TreeField::Status => ServerPackets::LocationGetStatus {
+                id,
+                location_id: location_id.clone(),
+            },
+            TreeField::IsError => ServerPackets::LocationIsError {
+                id,
+                location_id: location_id.clone(),
+            },
+        })
+        .collect()
+}
+
+/// Requests that read the fields of the element, to be sent in one `ServerPackets::Batch`
+/// The responses are read by `fields_from_responses`
+pub fn element_field_requests(element_id: &ElementId, fields: &[TreeField]) -> Vec<ServerPackets> {
+    let (id, element_id) = (0, element_id.clone());
+    fields
+        .iter()
+        .map(|field| match field {
+            TreeField::Name => ServerPackets::ElementGetName {
+                id,
+                element_id: element_id.clone(),
+            },
+            TreeField::Desc => ServerPackets::ElementGetDesc {
+                id,
+                element_id: element_id.clone(),
+            },
+            TreeField::Enabled => ServerPackets::ElementGetEnabled {
+                id,
+                element_id: element_id.clone(),
+            },
+            TreeField::Progress => ServerPackets::ElementGetProgress {
+                id,
+                element_id: element_id.clone(),
+            },
+            TreeField::Status => ServerPackets::ElementGetStatus {
+                id,
+                element_id: element_id.clone(),
+            },
+            TreeField::IsError => ServerPackets::ElementIsError {
+                id,
+                element_id: element_id.clone(),
+            },
+        })
+        .collect()
+}
+
+/// The fields in the responses of a batch of field requests, a field that cannot be read stays `None`
+pub fn fields_from_responses(responses: Vec<ClientPackets>) -> TreeFields {
+    let mut fields = TreeFields::default();
+    for response in responses {
+        match response {
+            ClientPackets::ElementGetName(_, Ok(name))
+            | ClientPackets::LocationGetName(_, Ok(name)) => fields.name = Some(name),
+            ClientPackets::ElementGetDesc(_, Ok(desc))
+            | ClientPackets::LocationGetDesc(_, Ok(desc)) => fields.desc = Some(desc),
+            ClientPackets::ElementGetEnabled(_, Ok(enabled))
+            | ClientPackets::LocationIsEnabled(_, Ok(enabled)) => fields.enabled = Some(enabled),
+            ClientPackets::ElementGetProgress(_, Ok(progress))
+            | ClientPackets::LocationGetProgress(_, Ok(progress)) => {
+                fields.progress = Some(progress)
+            }
+            ClientPackets::ElementGetStatus(_, Ok(status))
+            | ClientPackets::LocationGetStatus(_, Ok(status)) => fields.status = Some(status),
+            ClientPackets::ElementIsError(_, Ok(is_error))
+            | ClientPackets::LocationIsError(_, Ok(is_error)) => fields.is_error = Some(is_error),
+            _ => {}
+        }
+    }
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_request_for_every_field() {
+        let element_id = ElementId {
+            uid: 1 as _,
+            location_id: LocationId(Vec::new()),
+        };
+        let fields = [TreeField::Name, TreeField::Status];
+
+        assert_eq!(element_field_requests(&element_id, &fields).len(), 2);
+        assert_eq!(
+            location_field_requests(&element_id.location_id, &fields).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn failed_responses_leave_the_field_empty() {
+        let fields = fields_from_responses(vec![
+            ClientPackets::ElementGetName(0, Ok("name".to_string())),
+            ClientPackets::ElementGetStatus(0, Ok(2)),
+            ClientPackets::ElementIsError(0, Err(SessionError::ServerTimeOut)),
+        ]);
+
+        assert_eq!(fields.name.as_deref(), Some("name"));
+        assert_eq!(fields.status, Some(2));
+        assert_eq!(fields.is_error, None);
+        assert_eq!(fields.progress, None);
+    }
+}